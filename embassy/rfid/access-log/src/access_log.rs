@@ -0,0 +1,78 @@
+//! Append-only CSV log of every card scan, kept on the SD card.
+//!
+//! ```text
+//! timestamp          ,uid                 ,result ,reader
+//! 2026-10-18 08:30:12,A1B2C3D4            ,GRANTED,1
+//! ```
+//!
+//! Torn rows and rotation to `ACCESS01.CSV` .. `ACCESS99.CSV` are handled
+//! by `rfid_common::csv_log`.
+
+use core::fmt::Write as _;
+
+use embassy_rp::rtc::DateTime;
+use heapless::String;
+use rfid_common::csv_log::{self, TIMESTAMP_WIDTH, UID_WIDTH};
+
+/// `ACCESS.CSV`, archived as `ACCESS01.CSV` .. `ACCESS99.CSV`
+pub const LOG_NAME: &str = "ACCESS";
+
+const RESULT_WIDTH: usize = 7;
+const READER_WIDTH: usize = 6;
+
+/// Four fields, three commas and the newline
+pub const ROW_LEN: usize = TIMESTAMP_WIDTH + UID_WIDTH + RESULT_WIDTH + READER_WIDTH + 4;
+
+pub type Row = String<ROW_LEN>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub enum Decision {
+    Granted,
+    Denied,
+}
+
+impl Decision {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Decision::Granted => "GRANTED",
+            Decision::Denied => "DENIED",
+        }
+    }
+}
+
+fn pad_row(timestamp: &str, uid: &str, result: &str, reader: &str) -> Row {
+    csv_log::pad_row(&[
+        (timestamp, TIMESTAMP_WIDTH),
+        (uid, UID_WIDTH),
+        (result, RESULT_WIDTH),
+        (reader, READER_WIDTH),
+    ])
+}
+
+/// First row of a new log.
+pub fn header() -> Row {
+    pad_row("timestamp", "uid", "result", "reader")
+}
+
+/// Formats one scan as a log row.
+pub fn format_row(
+    time: &DateTime,
+    uid: &[u8],
+    decision: Decision,
+    reader: u8,
+) -> Result<Row, &'static str> {
+    let timestamp = csv_log::timestamp(
+        time.year,
+        time.month,
+        time.day,
+        time.hour,
+        time.minute,
+        time.second,
+    )?;
+    let uid_hex = csv_log::uid_hex(uid)?;
+
+    let mut reader_id: String<READER_WIDTH> = String::new();
+    write!(reader_id, "{}", reader).expect("reader ID too long");
+
+    Ok(pad_row(&timestamp, &uid_hex, decision.as_str(), &reader_id))
+}
@@ -0,0 +1,180 @@
+#![no_std]
+#![no_main]
+
+pub mod access_log;
+
+use embassy_executor::Spawner;
+use embassy_time::Timer;
+
+// defmt Logging
+use defmt::{error, info, warn};
+use defmt_rtt as _;
+
+use panic_probe as _;
+
+// For SPI
+use embassy_rp::spi;
+use embassy_rp::spi::Spi;
+use embassy_time::Delay;
+use embedded_hal_bus::spi::ExclusiveDevice;
+
+// For CS Pin
+use embassy_rp::gpio::{Level, Output};
+
+// For the timestamps
+use embassy_rp::bind_interrupts;
+use embassy_rp::peripherals::RTC;
+use embassy_rp::rtc::{self, DateTime, DayOfWeek, Rtc};
+
+// Driver for the MFRC522
+use mfrc522::{Mfrc522, comm::blocking::spi::SpiInterface};
+
+// For SdCard
+use embedded_sdmmc::{SdCard, TimeSource, Timestamp, VolumeIdx, VolumeManager};
+
+use rfid_common::csv_log::CsvLog;
+
+use crate::access_log::Decision;
+
+bind_interrupts!(struct Irqs {
+    RTC_IRQ => rtc::InterruptHandler;
+});
+
+/// Identifies this reader in the log, give each door its own number.
+const READER_ID: u8 = 1;
+
+/// Cards that may pass. Replace these with the UIDs of your own cards.
+const ALLOWED_UIDS: [&[u8]; 2] = [&[0x13, 0x37, 0x73, 0x31], &[0xA1, 0xB2, 0xC3, 0xD4]];
+
+/// The RTC starts from this time at power-up, set it to the current time
+/// before flashing. There is no battery, so it starts over after a power cut.
+fn start_time() -> DateTime {
+    DateTime {
+        year: 2026,
+        month: 1,
+        day: 1,
+        day_of_week: DayOfWeek::Thursday,
+        hour: 0,
+        minute: 0,
+        second: 0,
+    }
+}
+
+/// Timestamps files on the SD card with the RP2040's RTC.
+pub struct RtcTimeSource<'a>(&'a Rtc<'static, RTC>);
+
+impl TimeSource for RtcTimeSource<'_> {
+    fn get_timestamp(&self) -> Timestamp {
+        self.0
+            .now()
+            .ok()
+            .and_then(|now| {
+                Timestamp::from_calendar(
+                    now.year, now.month, now.day, now.hour, now.minute, now.second,
+                )
+                .ok()
+            })
+            .unwrap_or(Timestamp {
+                year_since_1970: 0,
+                zero_indexed_month: 0,
+                zero_indexed_day: 0,
+                hours: 0,
+                minutes: 0,
+                seconds: 0,
+            })
+    }
+}
+
+#[embassy_executor::main]
+async fn main(_spawner: Spawner) {
+    let p = embassy_rp::init(Default::default());
+
+    info!("Initializing the program");
+
+    let mut rtc = Rtc::new(p.RTC, Irqs);
+    if !rtc.is_running() {
+        rtc.set_datetime(start_time())
+            .expect("start time is not a valid date");
+    }
+
+    // RFID reader on SPI0
+    let miso = p.PIN_0;
+    let cs_pin = Output::new(p.PIN_1, Level::High);
+    let clk = p.PIN_2;
+    let mosi = p.PIN_3;
+
+    let mut config = spi::Config::default();
+    config.frequency = 1_000_000;
+
+    let spi_bus = Spi::new_blocking(p.SPI0, clk, mosi, miso, config);
+    let spi = ExclusiveDevice::new(spi_bus, cs_pin, Delay).expect("Failed to get exclusive device");
+
+    let itf = SpiInterface::new(spi);
+    let mut rfid = Mfrc522::new(itf)
+        .init()
+        .expect("failed to initialize the RFID reader");
+
+    // SD card on SPI1
+    let sd_miso = p.PIN_12;
+    let sd_cs_pin = Output::new(p.PIN_13, Level::High);
+    let sd_clk = p.PIN_10;
+    let sd_mosi = p.PIN_11;
+
+    let mut sd_config = spi::Config::default();
+    sd_config.frequency = 400_000;
+
+    let sd_spi_bus = Spi::new_blocking(p.SPI1, sd_clk, sd_mosi, sd_miso, sd_config);
+    let sd_spi_device =
+        ExclusiveDevice::new(sd_spi_bus, sd_cs_pin, Delay).expect("Failed to get exclusive device");
+
+    let sdcard = SdCard::new(sd_spi_device, Delay);
+
+    info!("Init SD card controller and retrieve card size...");
+    let sd_size = sdcard.num_bytes().expect("failed to get sdcard size");
+    info!("card size is {} bytes", sd_size);
+
+    let volume_mgr = VolumeManager::new(sdcard, RtcTimeSource(&rtc));
+    let volume0 = volume_mgr
+        .open_volume(VolumeIdx(0))
+        .expect("failed to open volume");
+
+    let root_dir = volume0.open_root_dir().expect("failed to open root dir");
+
+    let (log, torn) = CsvLog::open(root_dir, access_log::LOG_NAME, access_log::header())
+        .expect("failed to open the access log");
+    if torn > 0 {
+        warn!("Last row of the log was cut short, dropping {} bytes", torn);
+    }
+
+    loop {
+        if let Ok(atqa) = rfid.reqa()
+            && let Ok(uid) = rfid.select(&atqa)
+        {
+            let uid = uid.as_bytes();
+            let decision = if ALLOWED_UIDS.contains(&uid) {
+                Decision::Granted
+            } else {
+                Decision::Denied
+            };
+            info!("UID: {:02x} {}", uid, decision);
+
+            let row = rtc
+                .now()
+                .map_err(|_| "RTC not running")
+                .and_then(|now| access_log::format_row(&now, uid, decision, READER_ID));
+            match row {
+                Ok(row) => {
+                    if log.append(&row).is_err() {
+                        error!("Unable to write to the access log");
+                    }
+                }
+                Err(e) => error!("Unable to log the scan: {}", e),
+            }
+
+            let _ = rfid.hlta();
+            Timer::after_millis(500).await;
+        }
+
+        Timer::after_millis(200).await;
+    }
+}
@@ -0,0 +1,120 @@
+//! Append-only CSV log of every check-in and check-out, kept on the SD card.
+//!
+//! ```text
+//! timestamp          ,uid                 ,name            ,event
+//! 2026-10-18 08:30:12,A1B2C3D4            ,Alice           ,IN
+//! ```
+//!
+//! Torn rows and rotation to `ATTEND01.CSV` .. `ATTEND99.CSV` are handled
+//! by `rfid_common::csv_log`.
+//!
+//! Who is in is rebuilt from `ATTEND.CSV` at power-up, archived rows are
+//! not replayed. So when the log rotates, everybody who is in gets a
+//! `PRESENT` row at the top of the new log.
+
+use embassy_rp::rtc::DateTime;
+use heapless::{String, Vec};
+use rfid_common::csv_log::{self, TIMESTAMP_WIDTH, UID_WIDTH};
+
+use crate::people::{self, Event, NAME_LEN, People};
+
+/// `ATTEND.CSV`, archived as `ATTEND01.CSV` .. `ATTEND99.CSV`
+pub const LOG_NAME: &str = "ATTEND";
+
+const NAME_WIDTH: usize = NAME_LEN;
+const EVENT_WIDTH: usize = 7;
+
+/// Carried over into a fresh log for everybody who is in
+const PRESENT: &str = "PRESENT";
+
+/// Four fields, three commas and the newline
+pub const ROW_LEN: usize = TIMESTAMP_WIDTH + UID_WIDTH + NAME_WIDTH + EVENT_WIDTH + 4;
+
+pub type Row = String<ROW_LEN>;
+
+fn pad_row(timestamp: &str, uid: &str, name: &str, event: &str) -> Row {
+    csv_log::pad_row(&[
+        (timestamp, TIMESTAMP_WIDTH),
+        (uid, UID_WIDTH),
+        (name, NAME_WIDTH),
+        (event, EVENT_WIDTH),
+    ])
+}
+
+/// First row of a new log.
+pub fn header() -> Row {
+    pad_row("timestamp", "uid", "name", "event")
+}
+
+fn format_timestamp(time: &DateTime) -> Result<String<TIMESTAMP_WIDTH>, &'static str> {
+    csv_log::timestamp(
+        time.year,
+        time.month,
+        time.day,
+        time.hour,
+        time.minute,
+        time.second,
+    )
+}
+
+/// Every row has to be exactly ROW_LEN bytes
+fn check_name(name: &str) -> Result<(), &'static str> {
+    if !name.is_ascii() || name.len() > NAME_WIDTH {
+        return Err("Name too long or not ASCII");
+    }
+    Ok(())
+}
+
+/// Formats one check-in or check-out as a log row. `name` is empty for unknown cards.
+pub fn format_row(
+    time: &DateTime,
+    uid: &[u8],
+    name: &str,
+    event: Event,
+) -> Result<Row, &'static str> {
+    let timestamp = format_timestamp(time)?;
+    let uid_hex = csv_log::uid_hex(uid)?;
+    check_name(name)?;
+
+    Ok(pad_row(&timestamp, &uid_hex, name, event.as_str()))
+}
+
+/// A `PRESENT` row for everybody who is in, to start a fresh log with.
+///
+/// Rows that can't be formatted are left out, those people start the new
+/// log checked out.
+pub fn presence_rows<'p, const N: usize>(
+    time: &DateTime,
+    people: &'p People<N>,
+) -> impl Iterator<Item = Row> + 'p {
+    let timestamp = format_timestamp(time).ok();
+    people
+        .iter()
+        .filter(|person| person.present)
+        .filter_map(move |person| {
+            let uid_hex = csv_log::uid_hex(&person.uid).ok()?;
+            check_name(&person.name).ok()?;
+            Some(pad_row(
+                timestamp.as_ref()?,
+                &uid_hex,
+                &person.name,
+                PRESENT,
+            ))
+        })
+}
+
+/// UID and event of a log row, `None` for the header or a damaged row.
+pub fn parse_row(row: &[u8]) -> Option<(Vec<u8, 10>, Event)> {
+    let row = core::str::from_utf8(row).ok()?;
+    let mut fields = row.trim_end().split(',');
+    let _timestamp = fields.next()?;
+    let uid = people::parse_uid(fields.next()?.trim()).ok()?;
+    let _name = fields.next()?;
+    let event = match fields.next()?.trim() {
+        "IN" | PRESENT => Event::CheckIn,
+        "OUT" => Event::CheckOut,
+        "UNKNOWN" => Event::Unknown,
+        _ => return None,
+    };
+    Some((uid, event))
+}
@@ -0,0 +1,100 @@
+//! What the 16x2 LCD next to the reader says.
+//!
+//! ```text
+//! +----------------+    +----------------+    +----------------+
+//! |Scan your card  |    |Welcome, Alice  |    |Goodbye, Alice  |
+//! |2026-10-18 08:30|    |In at 08:30     |    |Out at 17:02    |
+//! +----------------+    +----------------+    +----------------+
+//! ```
+//!
+//! Lines are padded with spaces instead of clearing the screen first, which
+//! avoids the flicker of a clear on every update.
+
+use core::fmt::Write as _;
+
+use embassy_rp::rtc::DateTime;
+use embassy_time::Delay;
+use heapless::String;
+use liquid_crystal::LiquidCrystal;
+use liquid_crystal::prelude::*;
+
+use crate::people::Event;
+
+pub const COLS: usize = 16;
+
+pub type Line = String<COLS>;
+
+pub type Lcd<'a, T> = LiquidCrystal<'a, T, 16, 2>;
+
+/// Cuts `text` to one line and pads it with spaces, so it overwrites
+/// whatever was there before. The LCD only knows ASCII.
+pub fn fit(text: &str) -> Line {
+    let mut line = Line::new();
+    for c in text.chars().take(COLS) {
+        let _ = line.push(if c.is_ascii() { c } else { '?' });
+    }
+    while line.push(' ').is_ok() {}
+    line
+}
+
+pub fn show<T: Interface>(lcd: &mut Lcd<'_, T>, top: &str, bottom: &str) {
+    lcd.set_cursor(&mut Delay, 0, 0)
+        .write(&mut Delay, Text(&fit(top)));
+    lcd.set_cursor(&mut Delay, 1, 0)
+        .write(&mut Delay, Text(&fit(bottom)));
+}
+
+pub fn show_idle<T: Interface>(lcd: &mut Lcd<'_, T>, now: Option<&DateTime>) {
+    let mut bottom: String<32> = String::new();
+    if let Some(now) = now {
+        let _ = write!(
+            bottom,
+            "{:04}-{:02}-{:02} {:02}:{:02}",
+            now.year, now.month, now.day, now.hour, now.minute
+        );
+    }
+    show(lcd, "Scan your card", &bottom);
+}
+
+/// "Welcome, Alice" or "Goodbye, Alice", with the time underneath. Names
+/// too long for that line get the second line to themselves.
+pub fn show_event<T: Interface>(
+    lcd: &mut Lcd<'_, T>,
+    event: Event,
+    name: &str,
+    uid: &[u8],
+    now: Option<&DateTime>,
+) {
+    let (greeting, direction, checked) = match event {
+        Event::CheckIn => ("Welcome", "In", "Checked in"),
+        Event::CheckOut => ("Goodbye", "Out", "Checked out"),
+        Event::Unknown => {
+            let mut uid_hex: String<32> = String::new();
+            for b in uid {
+                let _ = write!(uid_hex, "{:02X}", b);
+            }
+            show(lcd, "Unknown card", &uid_hex);
+            return;
+        }
+    };
+
+    let mut top: String<32> = String::new();
+    let _ = write!(top, "{}, {}", greeting, name);
+    if top.len() > COLS {
+        top.clear();
+        let _ = write!(top, "{},", greeting);
+        show(lcd, &top, name);
+        return;
+    }
+
+    let mut bottom: String<32> = String::new();
+    match now {
+        Some(now) => {
+            let _ = write!(bottom, "{} at {:02}:{:02}", direction, now.hour, now.minute);
+        }
+        None => {
+            let _ = bottom.push_str(checked);
+        }
+    }
+    show(lcd, &top, &bottom);
+}
@@ -0,0 +1,324 @@
+#![no_std]
+#![no_main]
+
+pub mod attendance_log;
+pub mod display;
+pub mod people;
+
+use embassy_executor::Spawner;
+use embassy_time::{Duration, Instant, Timer};
+
+// defmt Logging
+use defmt::{error, info, warn};
+use defmt_rtt as _;
+
+use panic_probe as _;
+
+// For SPI
+use embassy_rp::spi;
+use embassy_rp::spi::Spi;
+use embassy_time::Delay;
+use embedded_hal_bus::spi::ExclusiveDevice;
+
+// For CS Pin
+use embassy_rp::gpio::{Level, Output};
+
+// I2C
+use embassy_rp::i2c::Config as I2cConfig;
+use embassy_rp::i2c::{self};
+
+// LCD Driver
+use liquid_crystal::I2C;
+use liquid_crystal::LiquidCrystal;
+use liquid_crystal::prelude::*;
+
+// For the timestamps
+use embassy_rp::bind_interrupts;
+use embassy_rp::peripherals::RTC;
+use embassy_rp::rtc::{self, DateTime, DayOfWeek, Rtc};
+
+// Driver for the MFRC522
+use mfrc522::{Mfrc522, comm::blocking::spi::SpiInterface};
+
+// For SdCard
+use embedded_sdmmc::{
+    BlockDevice, Directory, Mode, SdCard, TimeSource, Timestamp, VolumeIdx, VolumeManager,
+};
+
+use heapless::Vec;
+
+use rfid_common::csv_log::CsvLog;
+
+use crate::people::{Event, PEOPLE_FILE, People};
+
+bind_interrupts!(struct Irqs {
+    RTC_IRQ => rtc::InterruptHandler;
+});
+
+const LCD_I2C_ADDRESS: u8 = 0x27;
+
+/// Most people `PEOPLE.CSV` may list
+const MAX_PEOPLE: usize = 64;
+
+/// Largest `PEOPLE.CSV` we read, about 20 bytes per person
+const PEOPLE_FILE_SIZE: usize = 2048;
+
+/// How long a greeting stays on the LCD
+const MESSAGE_TIME: Duration = Duration::from_secs(3);
+
+/// The same card again within this time is a double tap, not a check-out
+const REPEAT_GUARD: Duration = Duration::from_secs(10);
+
+/// The RTC starts from this time at power-up, set it to the current time
+/// before flashing. There is no battery, so it starts over after a power cut.
+fn start_time() -> DateTime {
+    DateTime {
+        year: 2026,
+        month: 1,
+        day: 1,
+        day_of_week: DayOfWeek::Thursday,
+        hour: 0,
+        minute: 0,
+        second: 0,
+    }
+}
+
+/// Timestamps files on the SD card with the RP2040's RTC.
+pub struct RtcTimeSource<'a>(&'a Rtc<'static, RTC>);
+
+impl TimeSource for RtcTimeSource<'_> {
+    fn get_timestamp(&self) -> Timestamp {
+        self.0
+            .now()
+            .ok()
+            .and_then(|now| {
+                Timestamp::from_calendar(
+                    now.year, now.month, now.day, now.hour, now.minute, now.second,
+                )
+                .ok()
+            })
+            .unwrap_or(Timestamp {
+                year_since_1970: 0,
+                zero_indexed_month: 0,
+                zero_indexed_day: 0,
+                hours: 0,
+                minutes: 0,
+                seconds: 0,
+            })
+    }
+}
+
+fn read_file<D, T, const DIRS: usize, const FILES: usize, const VOLUMES: usize>(
+    dir: &Directory<'_, D, T, DIRS, FILES, VOLUMES>,
+    name: &str,
+    buf: &mut [u8],
+) -> Result<usize, &'static str>
+where
+    D: BlockDevice,
+    T: TimeSource,
+{
+    let file = dir
+        .open_file_in_dir(name, Mode::ReadOnly)
+        .map_err(|_| "Can't open file")?;
+
+    let mut len = 0;
+    while !file.is_eof() && len < buf.len() {
+        len += file.read(&mut buf[len..]).map_err(|_| "Can't read file")?;
+    }
+    if !file.is_eof() {
+        return Err("File too large");
+    }
+    Ok(len)
+}
+
+fn load_people<D, T, const DIRS: usize, const FILES: usize, const VOLUMES: usize>(
+    dir: &Directory<'_, D, T, DIRS, FILES, VOLUMES>,
+) -> Result<People<MAX_PEOPLE>, &'static str>
+where
+    D: BlockDevice,
+    T: TimeSource,
+{
+    let mut buf = [0u8; PEOPLE_FILE_SIZE];
+    let len = read_file(dir, PEOPLE_FILE, &mut buf)?;
+    let text = core::str::from_utf8(&buf[..len]).map_err(|_| "Not a text file")?;
+
+    let (people, skipped) = People::parse(text);
+    if skipped > 0 {
+        warn!("Skipped {} line(s) of {}", skipped, PEOPLE_FILE);
+    }
+    Ok(people)
+}
+
+#[embassy_executor::main]
+async fn main(_spawner: Spawner) {
+    let p = embassy_rp::init(Default::default());
+
+    info!("Initializing the program");
+
+    let mut rtc = Rtc::new(p.RTC, Irqs);
+    if !rtc.is_running() {
+        rtc.set_datetime(start_time())
+            .expect("start time is not a valid date");
+    }
+
+    // LCD on I2C0
+    let sda = p.PIN_16;
+    let scl = p.PIN_17;
+
+    let mut i2c_config = I2cConfig::default();
+    i2c_config.frequency = 100_000; //100kHz
+
+    let i2c_bus = i2c::I2c::new_blocking(p.I2C0, scl, sda, i2c_config);
+
+    let mut i2c_interface = I2C::new(i2c_bus, LCD_I2C_ADDRESS);
+    let mut lcd = LiquidCrystal::new(&mut i2c_interface, Bus4Bits, LCD16X2);
+    lcd.begin(&mut Delay);
+    display::show(&mut lcd, "Starting...", "");
+
+    // RFID reader on SPI0
+    let miso = p.PIN_0;
+    let cs_pin = Output::new(p.PIN_1, Level::High);
+    let clk = p.PIN_2;
+    let mosi = p.PIN_3;
+
+    let mut config = spi::Config::default();
+    config.frequency = 1_000_000;
+
+    let spi_bus = Spi::new_blocking(p.SPI0, clk, mosi, miso, config);
+    let spi = ExclusiveDevice::new(spi_bus, cs_pin, Delay).expect("Failed to get exclusive device");
+
+    let itf = SpiInterface::new(spi);
+    let mut rfid = Mfrc522::new(itf)
+        .init()
+        .expect("failed to initialize the RFID reader");
+
+    // SD card on SPI1
+    let sd_miso = p.PIN_12;
+    let sd_cs_pin = Output::new(p.PIN_13, Level::High);
+    let sd_clk = p.PIN_10;
+    let sd_mosi = p.PIN_11;
+
+    let mut sd_config = spi::Config::default();
+    sd_config.frequency = 400_000;
+
+    let sd_spi_bus = Spi::new_blocking(p.SPI1, sd_clk, sd_mosi, sd_miso, sd_config);
+    let sd_spi_device =
+        ExclusiveDevice::new(sd_spi_bus, sd_cs_pin, Delay).expect("Failed to get exclusive device");
+
+    let sdcard = SdCard::new(sd_spi_device, Delay);
+
+    info!("Init SD card controller and retrieve card size...");
+    let sd_size = sdcard.num_bytes().expect("failed to get sdcard size");
+    info!("card size is {} bytes", sd_size);
+
+    let volume_mgr = VolumeManager::new(sdcard, RtcTimeSource(&rtc));
+    let volume0 = volume_mgr
+        .open_volume(VolumeIdx(0))
+        .expect("failed to open volume");
+
+    let root_dir = volume0.open_root_dir().expect("failed to open root dir");
+
+    // Without the list every card is unknown, but scans still get logged
+    let mut people = match load_people(&root_dir) {
+        Ok(people) => people,
+        Err(e) => {
+            error!("Unable to load {}: {}", PEOPLE_FILE, e);
+            display::show(&mut lcd, "No PEOPLE.CSV", e);
+            Timer::after_secs(5).await;
+            People::new()
+        }
+    };
+    info!("{} people listed", people.len());
+
+    let (log, torn) = CsvLog::open(root_dir, attendance_log::LOG_NAME, attendance_log::header())
+        .expect("failed to open the attendance log");
+    if torn > 0 {
+        warn!("Last row of the log was cut short, dropping {} bytes", torn);
+    }
+
+    // Pick up who is in from the log, in case we restarted during the day
+    let replayed = log.replay(|row| {
+        if let Some((uid, event)) = attendance_log::parse_row(row) {
+            people.apply(&uid, event);
+        }
+    });
+    if replayed.is_err() {
+        error!("Unable to read back the attendance log");
+    }
+    info!("{} people checked in", people.present());
+
+    // When the greeting on the LCD goes back to the idle screen
+    let mut message_until: Option<Instant> = None;
+    // Minute shown on the idle screen, 60 when the RTC is not running
+    let mut idle_minute: Option<u8> = None;
+    // Last card we saw, to ignore double taps
+    let mut last_scan: Option<(Vec<u8, 10>, Instant)> = None;
+
+    loop {
+        if message_until.is_some_and(|until| Instant::now() >= until) {
+            message_until = None;
+            idle_minute = None;
+        }
+        if message_until.is_none() {
+            let now = rtc.now().ok();
+            let minute = now.as_ref().map_or(60, |now| now.minute);
+            if idle_minute != Some(minute) {
+                display::show_idle(&mut lcd, now.as_ref());
+                idle_minute = Some(minute);
+            }
+        }
+
+        if let Ok(atqa) = rfid.reqa()
+            && let Ok(uid) = rfid.select(&atqa)
+        {
+            let uid = uid.as_bytes();
+            let now = rtc.now().ok();
+
+            let repeat = last_scan
+                .as_ref()
+                .is_some_and(|(last, at)| last == uid && at.elapsed() < REPEAT_GUARD);
+
+            if repeat {
+                if let Some(person) = people.find(uid) {
+                    let state = if person.present {
+                        "Already in"
+                    } else {
+                        "Already out"
+                    };
+                    display::show(&mut lcd, &person.name, state);
+                }
+            } else {
+                let (name, event) = match people.next_event(uid) {
+                    Some((person, event)) => (person.name.clone(), event),
+                    None => (Default::default(), Event::Unknown),
+                };
+                info!("UID: {:02x} {} {}", uid, name.as_str(), event);
+
+                // Only what made it into the log counts, a restart would forget the rest
+                let logged = now.as_ref().ok_or("RTC not running").and_then(|now| {
+                    let row = attendance_log::format_row(now, uid, &name, event)?;
+                    log.append_with(&row, || attendance_log::presence_rows(now, &people))
+                        .map_err(|_| "Unable to write to the attendance log")
+                });
+                match logged {
+                    Ok(()) => {
+                        people.apply(uid, event);
+                        display::show_event(&mut lcd, event, &name, uid, now.as_ref());
+                    }
+                    Err(e) => {
+                        error!("Scan not recorded: {}", e);
+                        display::show(&mut lcd, "Not recorded", "Please try again");
+                    }
+                }
+            }
+
+            last_scan = Vec::from_slice(uid).ok().map(|uid| (uid, Instant::now()));
+            message_until = Some(Instant::now() + MESSAGE_TIME);
+
+            let _ = rfid.hlta();
+            Timer::after_millis(500).await;
+        }
+
+        Timer::after_millis(200).await;
+    }
+}
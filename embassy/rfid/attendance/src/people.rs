@@ -0,0 +1,169 @@
+//! Who is who, and who is in.
+//!
+//! `PEOPLE.CSV` on the SD card maps card UIDs (hex) to names, one person
+//! per line. A header line and lines starting with `#` are skipped:
+//!
+//! ```text
+//! uid,name
+//! A1B2C3D4,Alice
+//! 04A1B2C3D4E5F6,Bob
+//! ```
+//!
+//! Names go on the 16 column LCD and into the fixed-width log, so they are
+//! limited to `NAME_LEN` ASCII characters and can't contain commas. Lines that break these
+//! rules are skipped with a warning rather than stopping the whole system.
+
+use defmt::warn;
+use heapless::{String, Vec};
+
+pub const PEOPLE_FILE: &str = "PEOPLE.CSV";
+
+/// Longest name, one full line of the LCD
+pub const NAME_LEN: usize = 16;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub enum Event {
+    CheckIn,
+    CheckOut,
+    /// A card that is not in `PEOPLE.CSV`
+    Unknown,
+}
+
+impl Event {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Event::CheckIn => "IN",
+            Event::CheckOut => "OUT",
+            Event::Unknown => "UNKNOWN",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Person {
+    pub uid: Vec<u8, 10>,
+    pub name: String<NAME_LEN>,
+    pub present: bool,
+}
+
+/// Parses a 4, 7 or 10 byte UID written as hex digits.
+pub fn parse_uid(hex: &str) -> Result<Vec<u8, 10>, &'static str> {
+    if !hex.is_ascii() || !hex.len().is_multiple_of(2) {
+        return Err("UID must be an even number of hex digits");
+    }
+    let mut uid = Vec::new();
+    for i in (0..hex.len()).step_by(2) {
+        let byte = u8::from_str_radix(&hex[i..i + 2], 16).map_err(|_| "UID is not hex")?;
+        uid.push(byte).map_err(|_| "UID too long")?;
+    }
+    if ![4, 7, 10].contains(&uid.len()) {
+        return Err("UID must be 4, 7 or 10 bytes");
+    }
+    Ok(uid)
+}
+
+fn parse_line(line: &str) -> Result<Person, &'static str> {
+    let (uid, name) = line.split_once(',').ok_or("Expected uid,name")?;
+    let name = name.trim();
+    if name.is_empty() {
+        return Err("Empty name");
+    }
+    if !name.is_ascii() {
+        return Err("Names must be ASCII, the LCD has no other characters");
+    }
+    if name.contains(',') {
+        return Err("Names can't contain commas");
+    }
+    Ok(Person {
+        uid: parse_uid(uid.trim())?,
+        name: String::try_from(name).map_err(|_| "Name longer than 16 characters")?,
+        present: false,
+    })
+}
+
+pub struct People<const N: usize> {
+    people: Vec<Person, N>,
+}
+
+impl<const N: usize> People<N> {
+    pub const fn new() -> Self {
+        Self { people: Vec::new() }
+    }
+
+    /// Reads the contents of `PEOPLE.CSV`. Everybody starts checked out.
+    ///
+    /// Also returns the number of lines that were skipped.
+    pub fn parse(text: &str) -> (Self, usize) {
+        let mut people = Self::new();
+        let mut skipped = 0;
+
+        for (number, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') || line.eq_ignore_ascii_case("uid,name") {
+                continue;
+            }
+
+            let result = parse_line(line).and_then(|person| {
+                if people.find(&person.uid).is_some() {
+                    return Err("UID listed twice");
+                }
+                people.people.push(person).map_err(|_| "Too many people")
+            });
+            if let Err(e) = result {
+                warn!("{} line {}: {}", PEOPLE_FILE, number + 1, e);
+                skipped += 1;
+            }
+        }
+        (people, skipped)
+    }
+
+    pub fn len(&self) -> usize {
+        self.people.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.people.is_empty()
+    }
+
+    pub fn find(&self, uid: &[u8]) -> Option<&Person> {
+        self.people.iter().find(|person| person.uid == uid)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Person> {
+        self.people.iter()
+    }
+
+    /// What scanning this card does: check the person in if they are out,
+    /// out if they are in. Nothing changes until the event is `apply`d.
+    pub fn next_event(&self, uid: &[u8]) -> Option<(&Person, Event)> {
+        let person = self.find(uid)?;
+        let event = if person.present {
+            Event::CheckOut
+        } else {
+            Event::CheckIn
+        };
+        Some((person, event))
+    }
+
+    /// Applies a check-in or check-out, one that was just logged or one
+    /// replayed from the log to pick up where we left off after a restart.
+    pub fn apply(&mut self, uid: &[u8], event: Event) {
+        if let Some(person) = self.people.iter_mut().find(|person| person.uid == uid) {
+            match event {
+                Event::CheckIn => person.present = true,
+                Event::CheckOut => person.present = false,
+                Event::Unknown => {}
+            }
+        }
+    }
+
+    pub fn present(&self) -> usize {
+        self.people.iter().filter(|person| person.present).count()
+    }
+}
+
+impl<const N: usize> Default for People<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,102 @@
+//! Card presence detection driven by the MFRC522 IRQ pin.
+//!
+//! The MFRC522 can't sense a card on its own, something has to transmit
+//! first. Every scan we start a WUPA through the registers and then sleep
+//! until either the IRQ pin reports an answer or a short timeout passes.
+//! Only when a card answered do we run the full anticollision to get its UID.
+//!
+//! Between scans the executor is idle, and no scan ever busy-waits on the
+//! reader the way `rfid.reqa()` does.
+//!
+//! We still poll, every `SCAN_INTERVAL_MS`, because there is nothing to
+//! wait for in between: the IRQ pin can only report the answer to a
+//! command we sent, and the MFRC522 has no low power card detection
+//! (that came with later chips like the CLRC663). A shorter interval
+//! notices cards sooner but turns the field on more often; 30 ms is
+//! already well below anything a person presenting a card would notice.
+//!
+//! `Debouncer` in `rfid-common` turns the scans into arrival and removal
+//! events, so it is tested on the host.
+
+use embassy_futures::select::{Either, select};
+use embassy_rp::gpio::Input;
+use embassy_sync::blocking_mutex::raw::RawMutex;
+use embassy_sync::channel::Sender;
+use embassy_time::Timer;
+use embedded_hal::spi::SpiDevice;
+use heapless::Vec;
+use mfrc522::{Initialized, Mfrc522};
+use rfid_common::debounce::{CardEvent, CardUid, Debouncer};
+use rfid_common::pcd::SharedSpi;
+
+use crate::irq;
+
+/// Time between two scans of the field
+const SCAN_INTERVAL_MS: u64 = 30;
+/// A card answers a WUPA within a few hundred microseconds
+const ANSWER_TIMEOUT_MS: u64 = 2;
+
+/// Starts a WUPA and waits for the IRQ pin to report an answer.
+///
+/// The pin stays low until `irq::stop` clears the request bits, so we wait
+/// for the level rather than the edge. An answer that arrives before the
+/// future is first polled would otherwise be missed.
+async fn card_in_field<D: SpiDevice>(
+    irq: &mut Input<'_>,
+    pcd: &mut SharedSpi<'_, D>,
+) -> Result<bool, &'static str> {
+    irq::start_wakeup(pcd)?;
+    let answered = matches!(
+        select(irq.wait_for_low(), Timer::after_millis(ANSWER_TIMEOUT_MS)).await,
+        Either::First(_)
+    );
+    irq::stop(pcd)?;
+    Ok(answered)
+}
+
+/// Reads the UID of the card that just answered, then halts it.
+fn read_uid<E, COMM>(rfid: &mut Mfrc522<COMM, Initialized>) -> Option<CardUid>
+where
+    COMM: mfrc522::comm::Interface<Error = E>,
+{
+    // Our WUPA left the card waiting for an anticollision frame. Anything
+    // else sends it back to sleep, so the first WUPA here goes unanswered.
+    let _ = rfid.wupa();
+    let atqa = rfid.wupa().ok()?;
+    let uid = rfid.select(&atqa).ok()?;
+    let _ = rfid.hlta();
+
+    Vec::from_slice(uid.as_bytes()).ok()
+}
+
+/// Scans the field forever and sends card events to `events`.
+pub async fn run<E, COMM, D, M, const N: usize>(
+    irq: &mut Input<'_>,
+    rfid: &mut Mfrc522<COMM, Initialized>,
+    pcd: &mut SharedSpi<'_, D>,
+    events: Sender<'_, M, CardEvent, N>,
+) -> !
+where
+    COMM: mfrc522::comm::Interface<Error = E>,
+    D: SpiDevice,
+    M: RawMutex,
+{
+    let mut debouncer = Debouncer::new();
+
+    loop {
+        let seen = match card_in_field(irq, pcd).await {
+            Ok(true) => read_uid(rfid),
+            Ok(false) => None,
+            Err(e) => {
+                defmt::error!("Error scanning for cards: {:?}", e);
+                None
+            }
+        };
+
+        for event in debouncer.update(seen) {
+            events.send(event).await;
+        }
+
+        Timer::after_millis(SCAN_INTERVAL_MS).await;
+    }
+}
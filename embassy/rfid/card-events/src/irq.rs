@@ -0,0 +1,66 @@
+//! Card detection through the MFRC522's IRQ pin.
+//!
+//! The `mfrc522` driver has no interrupt support, so these go through
+//! `SharedSpi` and set up the registers themselves.
+
+use embedded_hal::spi::SpiDevice;
+use rfid_common::pcd::{
+    BIT_FRAMING_REG, COM_I_EN_REG, COM_IRQ_REG, COMMAND_REG, DIV_I_EN_REG, FIFO_LEVEL_REG,
+    SharedSpi,
+};
+
+// PCD commands
+const CMD_IDLE: u8 = 0x00;
+const CMD_TRANSCEIVE: u8 = 0x0C;
+
+// ComIEnReg bits
+const IRQ_INV: u8 = 1 << 7;
+const RX_IEN: u8 = 1 << 5;
+
+// DivIEnReg: drive the IRQ pin instead of leaving it open drain
+const IRQ_PUSH_PULL: u8 = 1 << 7;
+
+// BitFramingReg: start the transmission, last byte has 7 bits
+const START_SEND: u8 = 1 << 7;
+const SHORT_FRAME: u8 = 0x07;
+
+// PICC command
+const PICC_WUPA: u8 = 0x52;
+
+/// Routes "data received" to the IRQ pin, active low and push-pull.
+///
+/// The pin stays low until the interrupt request bits are cleared again.
+pub fn enable_rx_irq<D: SpiDevice>(pcd: &mut SharedSpi<'_, D>) -> Result<(), &'static str> {
+    let spi_err = |_| "SPI error";
+
+    pcd.write_register(COM_I_EN_REG, IRQ_INV | RX_IEN)
+        .map_err(spi_err)?;
+    pcd.write_register(DIV_I_EN_REG, IRQ_PUSH_PULL)
+        .map_err(spi_err)?;
+    pcd.write_register(COM_IRQ_REG, 0x7F).map_err(spi_err)
+}
+
+/// Sends a WUPA and returns right away. If a card answers, the IRQ pin goes low.
+///
+/// WUPA instead of REQA, so cards we halted after reading them answer too.
+pub fn start_wakeup<D: SpiDevice>(pcd: &mut SharedSpi<'_, D>) -> Result<(), &'static str> {
+    let spi_err = |_| "SPI error";
+
+    pcd.write_register(COMMAND_REG, CMD_IDLE).map_err(spi_err)?;
+    pcd.write_register(COM_IRQ_REG, 0x7F).map_err(spi_err)?;
+    pcd.write_register(FIFO_LEVEL_REG, 0x80).map_err(spi_err)?;
+    pcd.write_fifo(&[PICC_WUPA]).map_err(spi_err)?;
+    pcd.write_register(COMMAND_REG, CMD_TRANSCEIVE)
+        .map_err(spi_err)?;
+    pcd.write_register(BIT_FRAMING_REG, START_SEND | SHORT_FRAME)
+        .map_err(spi_err)
+}
+
+/// Stops a pending transceive and releases the IRQ pin.
+pub fn stop<D: SpiDevice>(pcd: &mut SharedSpi<'_, D>) -> Result<(), &'static str> {
+    let spi_err = |_| "SPI error";
+
+    pcd.write_register(COMMAND_REG, CMD_IDLE).map_err(spi_err)?;
+    pcd.write_register(BIT_FRAMING_REG, 0).map_err(spi_err)?;
+    pcd.write_register(COM_IRQ_REG, 0x7F).map_err(spi_err)
+}
@@ -0,0 +1,83 @@
+#![no_std]
+#![no_main]
+
+pub mod detector;
+pub mod irq;
+
+use embassy_executor::Spawner;
+
+// defmt Logging
+use defmt::info;
+use defmt_rtt as _;
+
+use panic_probe as _;
+
+// For SPI
+use embassy_rp::spi;
+use embassy_rp::spi::Spi;
+use embassy_time::Delay;
+use embedded_hal_bus::spi::ExclusiveDevice;
+
+// For CS and IRQ Pins
+use embassy_rp::gpio::{Input, Level, Output, Pull};
+
+// Driver for the MFRC522
+use mfrc522::{Mfrc522, comm::blocking::spi::SpiInterface};
+
+// For passing events between tasks
+use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+use embassy_sync::channel::Channel;
+
+use core::cell::RefCell;
+
+use rfid_common::debounce::CardEvent;
+use rfid_common::pcd::SharedSpi;
+
+static CARD_EVENTS: Channel<CriticalSectionRawMutex, CardEvent, 4> = Channel::new();
+
+/// The application side: it only ever waits for the next card event.
+#[embassy_executor::task]
+async fn card_task() {
+    loop {
+        match CARD_EVENTS.receive().await {
+            CardEvent::CardArrived(uid) => info!("Card arrived: {=[u8]:02x}", uid),
+            CardEvent::CardRemoved(uid) => info!("Card removed: {=[u8]:02x}", uid),
+        }
+    }
+}
+
+#[embassy_executor::main]
+async fn main(spawner: Spawner) {
+    let p = embassy_rp::init(Default::default());
+
+    info!("Initializing the program");
+
+    let miso = p.PIN_0;
+    let cs_pin = Output::new(p.PIN_1, Level::High);
+    let clk = p.PIN_2;
+    let mosi = p.PIN_3;
+
+    // IRQ pin of the MFRC522
+    let mut irq = Input::new(p.PIN_4, Pull::Up);
+
+    let mut config = spi::Config::default();
+    config.frequency = 1_000_000;
+
+    let spi_bus = Spi::new_blocking(p.SPI0, clk, mosi, miso, config);
+    let spi = ExclusiveDevice::new(spi_bus, cs_pin, Delay).expect("Failed to get exclusive device");
+
+    // Shared between the driver and our own interrupt setup
+    let spi = RefCell::new(spi);
+    let mut pcd = SharedSpi::new(&spi);
+
+    let itf = SpiInterface::new(pcd);
+    let mut rfid = Mfrc522::new(itf)
+        .init()
+        .expect("failed to initialize the RFID reader");
+
+    irq::enable_rx_irq(&mut pcd).expect("failed to enable the IRQ pin");
+
+    spawner.must_spawn(card_task());
+
+    detector::run(&mut irq, &mut rfid, &mut pcd, CARD_EVENTS.sender()).await
+}
@@ -0,0 +1,8 @@
+//! A simulated MIFARE Classic card, to test the RFID code on the host.
+//!
+//! `SimulatedCard` implements `rfid_common::reader::CardReader`, so the
+//! retry, key rotation, storage, dump and restore code of the firmware runs
+//! against it unchanged. No reader or card needed: `cargo test` runs the
+//! dump, restore, write and key change flows in `tests/`.
+
+pub mod sim;
@@ -0,0 +1,308 @@
+//! A MIFARE Classic 1K card that lives in memory.
+//!
+//! It behaves like a card lying on the reader: it has to be woken up with
+//! REQA and selected before it can be authenticated, it only answers
+//! commands for the sector it is authenticated to, and it enforces the keys
+//! and access bits stored in each sector trailer. A failed authentication
+//! or a refused command drops the card back to idle, like the real thing.
+//! A halted card only wakes up again for WUPA.
+//!
+//! Both keys work through `mf_authenticate_with`. As on a real card, key B
+//! still authenticates while the access bits make it readable, but the
+//! card then refuses every command.
+
+use core::convert::Infallible;
+
+use mfrc522::{Error, GenericUid, Uid};
+use rfid_common::access::{AccessConditions, Permission};
+use rfid_common::pcd::KeyType;
+use rfid_common::reader::CardReader;
+
+pub const IMAGE_SIZE: usize = 1024;
+
+/// The card never talks back to the simulated reader over SPI.
+pub type SimError = Error<Infallible>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum State {
+    Idle,
+    Ready,
+    Active,
+    Authenticated { sector: u8, key_type: KeyType },
+    Halted,
+}
+
+pub struct SimulatedCard {
+    uid: [u8; 4],
+    image: [u8; IMAGE_SIZE],
+    state: State,
+    /// Whether the card is on the reader at all
+    pub present: bool,
+    /// Reads and writes that time out before the card answers again, like
+    /// a card at the edge of the field
+    pub dropped_frames: u8,
+}
+
+impl SimulatedCard {
+    /// A blank card as it comes from the factory: transport keys and access bits.
+    pub fn blank(uid: [u8; 4]) -> Self {
+        let mut image = [0u8; IMAGE_SIZE];
+
+        // Block 0: UID, BCC, SAK, ATQA and some manufacturer data
+        image[..4].copy_from_slice(&uid);
+        image[4] = uid.iter().fold(0, |bcc, b| bcc ^ b);
+        image[5] = 0x08;
+        image[6..8].copy_from_slice(&[0x04, 0x00]);
+
+        let trailer = AccessConditions::TRANSPORT.to_trailer([0xFF; 6], 0x69, [0xFF; 6]);
+        for sector in 0..16 {
+            let start = (sector * 4 + 3) * 16;
+            image[start..start + 16].copy_from_slice(&trailer);
+        }
+
+        Self::from_image(image)
+    }
+
+    /// A card holding a dump, for example one saved by dump-sdcard.
+    pub fn from_image(image: [u8; IMAGE_SIZE]) -> Self {
+        Self {
+            uid: image[..4].try_into().expect("4 bytes"),
+            image,
+            state: State::Idle,
+            present: true,
+            dropped_frames: 0,
+        }
+    }
+
+    pub fn image(&self) -> &[u8; IMAGE_SIZE] {
+        &self.image
+    }
+
+    fn block(&self, block: u8) -> &[u8; 16] {
+        let start = block as usize * 16;
+        self.image[start..start + 16].try_into().expect("16 bytes")
+    }
+
+    fn block_mut(&mut self, block: u8) -> &mut [u8; 16] {
+        let start = block as usize * 16;
+        (&mut self.image[start..start + 16])
+            .try_into()
+            .expect("16 bytes")
+    }
+
+    fn trailer(&self, sector: u8) -> &[u8; 16] {
+        self.block(sector * 4 + 3)
+    }
+
+    /// A trailer with broken access bits locks the whole sector.
+    fn conditions(&self, sector: u8) -> Option<AccessConditions> {
+        AccessConditions::from_trailer(self.trailer(sector)).ok()
+    }
+
+    /// Any error leaves the card in idle, it has to be selected again.
+    fn fail(&mut self, err: SimError) -> SimError {
+        self.state = State::Idle;
+        err
+    }
+
+    /// The sector the card is authenticated to and the key used, if
+    /// `block` is in that sector.
+    fn check_auth(&mut self, block: u8) -> Result<(u8, KeyType), SimError> {
+        match self.state {
+            State::Authenticated { sector, key_type } if sector == block / 4 && block < 64 => {
+                Ok((sector, key_type))
+            }
+            _ => Err(self.fail(Error::Nak)),
+        }
+    }
+
+    /// The access conditions of `sector`, unless they lock out `key_type`
+    /// altogether: broken access bits, or key B while it is readable.
+    fn usable_conditions(
+        &mut self,
+        sector: u8,
+        key_type: KeyType,
+    ) -> Result<AccessConditions, SimError> {
+        match self.conditions(sector) {
+            Some(conditions)
+                if key_type == KeyType::A
+                    || !conditions.trailer.trailer_permissions().key_b_readable() =>
+            {
+                Ok(conditions)
+            }
+            _ => Err(self.fail(Error::Nak)),
+        }
+    }
+
+    /// Times out and drops to idle while there are frames left to drop.
+    fn check_field(&mut self) -> Result<(), SimError> {
+        if !self.present {
+            return Err(Error::Timeout);
+        }
+        if self.dropped_frames > 0 {
+            self.dropped_frames -= 1;
+            return Err(self.fail(Error::Timeout));
+        }
+        Ok(())
+    }
+
+    fn read_block(&mut self, block: u8) -> Result<[u8; 16], SimError> {
+        let (sector, key_type) = self.check_auth(block)?;
+        let conditions = self.usable_conditions(sector, key_type)?;
+
+        let rel_block = block % 4;
+        if rel_block < 3 {
+            if !allowed(
+                key_type,
+                conditions.data[rel_block as usize].data_permissions().read,
+            ) {
+                return Err(self.fail(Error::Nak));
+            }
+            return Ok(*self.block(block));
+        }
+
+        // Key A always reads back as zeros, the rest depends on the access bits
+        let permissions = conditions.trailer.trailer_permissions();
+        let mut data = *self.block(block);
+        data[..6].fill(0);
+        if !allowed(key_type, permissions.access_bits_read) {
+            data[6..10].fill(0);
+        }
+        if !allowed(key_type, permissions.key_b_read) {
+            data[10..].fill(0);
+        }
+        Ok(data)
+    }
+
+    fn write_block(&mut self, block: u8, data: [u8; 16]) -> Result<(), SimError> {
+        let (sector, key_type) = self.check_auth(block)?;
+        let conditions = self.usable_conditions(sector, key_type)?;
+
+        // The manufacturer block is read-only on a genuine card
+        if block == 0 {
+            return Err(self.fail(Error::Nak));
+        }
+
+        let rel_block = block % 4;
+        if rel_block < 3 {
+            if !allowed(
+                key_type,
+                conditions.data[rel_block as usize].data_permissions().write,
+            ) {
+                return Err(self.fail(Error::Nak));
+            }
+            *self.block_mut(block) = data;
+            return Ok(());
+        }
+
+        // The card accepts a trailer write and only updates the parts it may.
+        // It does not check the new access bits, a bad copy locks the sector.
+        let permissions = conditions.trailer.trailer_permissions();
+        let trailer = self.block_mut(block);
+        if allowed(key_type, permissions.key_a_write) {
+            trailer[..6].copy_from_slice(&data[..6]);
+        }
+        if allowed(key_type, permissions.access_bits_write) {
+            trailer[6..10].copy_from_slice(&data[6..10]);
+        }
+        if allowed(key_type, permissions.key_b_write) {
+            trailer[10..].copy_from_slice(&data[10..]);
+        }
+        Ok(())
+    }
+}
+
+fn allowed(key_type: KeyType, permission: Permission) -> bool {
+    match permission {
+        Permission::Never => false,
+        Permission::KeyA => key_type == KeyType::A,
+        Permission::KeyB => key_type == KeyType::B,
+        Permission::KeyAOrB => true,
+    }
+}
+
+impl CardReader for SimulatedCard {
+    type Atqa = [u8; 2];
+    type CommError = Infallible;
+
+    fn reqa(&mut self) -> Result<Self::Atqa, SimError> {
+        // A halted card only answers WUPA
+        if !self.present || self.state != State::Idle {
+            return Err(Error::Timeout);
+        }
+        self.state = State::Ready;
+        Ok([0x04, 0x00])
+    }
+
+    fn wupa(&mut self) -> Result<Self::Atqa, SimError> {
+        if !self.present {
+            return Err(Error::Timeout);
+        }
+        // A selected card takes it as a bad frame and goes back to idle
+        if !matches!(self.state, State::Idle | State::Halted) {
+            return Err(self.fail(Error::Timeout));
+        }
+        self.state = State::Ready;
+        Ok([0x04, 0x00])
+    }
+
+    fn select(&mut self, _atqa: &Self::Atqa) -> Result<Uid, SimError> {
+        if !self.present || self.state != State::Ready {
+            return Err(Error::Timeout);
+        }
+        self.state = State::Active;
+        Ok(Uid::Single(GenericUid::new(self.uid, 0x08)))
+    }
+
+    fn hlta(&mut self) -> Result<(), SimError> {
+        // Only a selected card halts, and it never answers
+        if self.present && matches!(self.state, State::Active | State::Authenticated { .. }) {
+            self.state = State::Halted;
+        }
+        Ok(())
+    }
+
+    fn stop_crypto1(&mut self) -> Result<(), SimError> {
+        // Only clears a flag in the reader, the card does not notice
+        Ok(())
+    }
+
+    fn mf_authenticate(&mut self, uid: &Uid, block: u8, key: &[u8; 6]) -> Result<(), SimError> {
+        self.mf_authenticate_with(uid, block, KeyType::A, key)
+    }
+
+    fn mf_authenticate_with(
+        &mut self,
+        uid: &Uid,
+        block: u8,
+        key_type: KeyType,
+        key: &[u8; 6],
+    ) -> Result<(), SimError> {
+        let selected = matches!(self.state, State::Active | State::Authenticated { .. });
+        if !self.present || !selected || uid.as_bytes() != self.uid || block >= 64 {
+            return Err(self.fail(Error::Timeout));
+        }
+
+        let sector = block / 4;
+        let trailer = self.trailer(sector);
+        let stored_key = match key_type {
+            KeyType::A => &trailer[..6],
+            KeyType::B => &trailer[10..],
+        };
+        if self.conditions(sector).is_none() || stored_key != key {
+            return Err(self.fail(Error::Timeout));
+        }
+        self.state = State::Authenticated { sector, key_type };
+        Ok(())
+    }
+
+    fn mf_read(&mut self, block: u8) -> Result<[u8; 16], SimError> {
+        self.check_field()?;
+        self.read_block(block)
+    }
+
+    fn mf_write(&mut self, block: u8, data: [u8; 16]) -> Result<(), SimError> {
+        self.check_field()?;
+        self.write_block(block, data)
+    }
+}
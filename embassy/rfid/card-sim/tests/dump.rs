@@ -0,0 +1,188 @@
+//! Reading a card sector by sector, as the dump examples do.
+
+use card_sim::sim::{IMAGE_SIZE, SimulatedCard};
+use mfrc522::Uid;
+use rfid_common::access::{AccessBits, AccessConditions};
+use rfid_common::dump;
+use rfid_common::error::RfidError;
+use rfid_common::keyring::{KeyRing, SectorKey};
+use rfid_common::pcd::KeyType;
+use rfid_common::reader::{self, CardReader};
+use rfid_common::retry::{self, RetryPolicy};
+
+const UID: [u8; 4] = [0xDE, 0xAD, 0xBE, 0xEF];
+const DEFAULT_KEY: [u8; 6] = [0xFF; 6];
+const KEY_A: [u8; 6] = [0x52, 0x75, 0x73, 0x74, 0x65, 0x64]; // "Rusted"
+/// Not in the default key ring
+const SECRET_KEY: [u8; 6] = [0x13, 0x37, 0x13, 0x37, 0x13, 0x37];
+
+fn select(card: &mut SimulatedCard) -> Uid {
+    let atqa = card.reqa().expect("card answers REQA");
+    card.select(&atqa).expect("card can be selected")
+}
+
+/// A blank card with a different trailer in `sector`.
+fn card_with_trailer(sector: usize, trailer: [u8; 16]) -> SimulatedCard {
+    let mut image: [u8; IMAGE_SIZE] = *SimulatedCard::blank(UID).image();
+    let start = (sector * 4 + 3) * 16;
+    image[start..start + 16].copy_from_slice(&trailer);
+    SimulatedCard::from_image(image)
+}
+
+#[test]
+fn every_sector_of_a_blank_card_opens_with_the_default_key() {
+    let mut card = SimulatedCard::blank(UID);
+    let uid = select(&mut card);
+
+    for sector in 0..16 {
+        let blocks = reader::read_sector(&uid, sector, &DEFAULT_KEY, &RetryPolicy::NONE, &mut card);
+        assert!(blocks.is_ok(), "sector {sector}: {blocks:?}");
+    }
+}
+
+#[test]
+fn block_0_holds_the_uid() {
+    let mut card = SimulatedCard::blank(UID);
+    let uid = select(&mut card);
+
+    let blocks = reader::read_sector(&uid, 0, &DEFAULT_KEY, &RetryPolicy::NONE, &mut card).unwrap();
+    assert_eq!(blocks[0][..4], UID);
+    // BCC, the XOR of the UID bytes
+    assert_eq!(blocks[0][4], 0x22);
+}
+
+#[test]
+fn key_a_reads_back_as_zeros() {
+    let mut card = SimulatedCard::blank(UID);
+    let uid = select(&mut card);
+
+    let blocks = reader::read_sector(&uid, 1, &DEFAULT_KEY, &RetryPolicy::NONE, &mut card).unwrap();
+    assert_eq!(blocks[3][..6], [0; 6]);
+    // Transport access bits and the general purpose byte
+    assert_eq!(blocks[3][6..10], [0xFF, 0x07, 0x80, 0x69]);
+    // Key B is readable with the transport access bits
+    assert_eq!(blocks[3][10..], DEFAULT_KEY);
+}
+
+#[test]
+fn wrong_key_fails_authentication() {
+    let mut card = SimulatedCard::blank(UID);
+    let uid = select(&mut card);
+
+    let result = reader::read_sector(&uid, 2, &KEY_A, &RetryPolicy::default(), &mut card);
+    assert_eq!(result, Err(RfidError::AuthFailed { sector: 2 }));
+
+    // The card dropped to idle, but can be woken up for the next sector
+    retry::reselect(&uid, &mut card).unwrap();
+    assert!(reader::read_sector(&uid, 3, &DEFAULT_KEY, &RetryPolicy::NONE, &mut card).is_ok());
+}
+
+#[test]
+fn halted_card_only_wakes_up_for_wupa() {
+    let mut card = SimulatedCard::blank(UID);
+    select(&mut card);
+
+    card.hlta().unwrap();
+    assert!(card.reqa().is_err());
+
+    let atqa = card.wupa().unwrap();
+    assert_eq!(card.select(&atqa).unwrap().as_bytes(), UID);
+}
+
+#[test]
+fn card_off_the_reader_does_not_answer() {
+    let mut card = SimulatedCard::blank(UID);
+    let uid = select(&mut card);
+
+    card.present = false;
+    let result = reader::read_sector(&uid, 1, &DEFAULT_KEY, &RetryPolicy::NONE, &mut card);
+    assert_eq!(result, Err(RfidError::AuthFailed { sector: 1 }));
+    assert!(card.wupa().is_err());
+}
+
+#[test]
+fn dump_of_a_blank_card_matches_the_card() {
+    let mut card = SimulatedCard::blank(UID);
+    let uid = select(&mut card);
+
+    let dump = dump::dump_card(&uid, &KeyRing::<16>::with_defaults(), &mut card).unwrap();
+    assert_eq!(dump.failed, [false; 16]);
+    // Key A is patched back into the trailers
+    assert_eq!(&dump.data, card.image());
+    assert_eq!(
+        dump.blocks[7].key,
+        Some(SectorKey {
+            key_type: KeyType::A,
+            key: DEFAULT_KEY
+        })
+    );
+}
+
+#[test]
+fn dump_skips_a_sector_no_key_opens() {
+    let trailer = AccessConditions::TRANSPORT.to_trailer(SECRET_KEY, 0x69, SECRET_KEY);
+    let mut card = card_with_trailer(5, trailer);
+    let uid = select(&mut card);
+
+    let dump = dump::dump_card(&uid, &KeyRing::<16>::with_defaults(), &mut card).unwrap();
+    for sector in 0..16 {
+        assert_eq!(dump.failed[sector], sector == 5, "sector {sector}");
+    }
+    for abs_block in 20..24 {
+        assert_eq!(
+            dump.blocks[abs_block].error,
+            Some("No key opened the sector")
+        );
+        assert_eq!(dump.block(abs_block), [0; 16]);
+    }
+    // The card is still selected for the sectors after it
+    assert_eq!(dump.block(27), &card.image()[27 * 16..28 * 16]);
+}
+
+#[test]
+fn dump_falls_back_to_key_b() {
+    // Data readable with either key, key B not readable, key A unknown
+    let conditions = AccessConditions {
+        data: [AccessBits::new(true, false, false); 3],
+        trailer: AccessBits::new(false, true, true),
+    };
+    let trailer = conditions.to_trailer(SECRET_KEY, 0x69, DEFAULT_KEY);
+    let mut card = card_with_trailer(2, trailer);
+    let uid = select(&mut card);
+
+    let dump = dump::dump_card(&uid, &KeyRing::<16>::with_defaults(), &mut card).unwrap();
+    assert!(!dump.failed[2]);
+    assert_eq!(
+        dump.blocks[11].key,
+        Some(SectorKey {
+            key_type: KeyType::B,
+            key: DEFAULT_KEY
+        })
+    );
+    // Key A stays unknown, the key B that opened the sector is patched in
+    assert_eq!(dump.block(11)[..6], [0; 6]);
+    assert_eq!(dump.block(11)[6..], trailer[6..]);
+}
+
+#[test]
+fn dump_loses_only_the_sector_with_a_failed_read() {
+    let mut card = SimulatedCard::blank(UID);
+    let uid = select(&mut card);
+
+    card.dropped_frames = 1;
+    let dump = dump::dump_card(&uid, &KeyRing::<16>::with_defaults(), &mut card).unwrap();
+    assert!(dump.failed[0]);
+    assert_eq!(dump.blocks[0].error, Some("Read failed"));
+    assert_eq!(dump.blocks[3].error, Some("Read failed"));
+    assert_eq!(dump.failed[1..], [false; 15]);
+}
+
+#[test]
+fn dump_stops_when_the_card_is_gone() {
+    let mut card = SimulatedCard::blank(UID);
+    let uid = select(&mut card);
+
+    card.present = false;
+    let result = dump::dump_card(&uid, &KeyRing::<16>::with_defaults(), &mut card);
+    assert!(matches!(result, Err("Card lost")));
+}
@@ -0,0 +1,133 @@
+//! Writing a saved image back onto a card, as the restore-card example does.
+
+use card_sim::sim::{IMAGE_SIZE, SimulatedCard};
+use mfrc522::Uid;
+use rfid_common::access::{AccessBits, AccessConditions};
+use rfid_common::dump;
+use rfid_common::keyring::KeyRing;
+use rfid_common::reader::CardReader;
+use rfid_common::restore::{self, BlockStatus, RestoreOptions};
+use rfid_common::retry;
+
+const UID: [u8; 4] = [0xDE, 0xAD, 0xBE, 0xEF];
+const KEY_A: [u8; 6] = [0x52, 0x75, 0x73, 0x74, 0x65, 0x64]; // "Rusted"
+/// Not in the default key ring
+const SECRET_KEY: [u8; 6] = [0x13, 0x37, 0x13, 0x37, 0x13, 0x37];
+
+const OPTIONS: RestoreOptions = RestoreOptions {
+    allow_block0: false,
+};
+
+fn select(card: &mut SimulatedCard) -> Uid {
+    let atqa = card.reqa().expect("card answers REQA");
+    card.select(&atqa).expect("card can be selected")
+}
+
+/// A blank card's image with every data block but block 0 filled in.
+fn image_with_data() -> [u8; IMAGE_SIZE] {
+    let mut image = *SimulatedCard::blank(UID).image();
+    for abs_block in (1..64).filter(|abs_block| abs_block % 4 != 3) {
+        image[abs_block * 16..abs_block * 16 + 16].fill(abs_block as u8);
+    }
+    image
+}
+
+#[test]
+fn restore_writes_every_block_but_block_0() {
+    let image = image_with_data();
+    let mut card = SimulatedCard::blank(UID);
+    let uid = select(&mut card);
+
+    let status = restore::restore_card(
+        &uid,
+        &image,
+        &KeyRing::<16>::with_defaults(),
+        &OPTIONS,
+        &mut card,
+    )
+    .unwrap();
+    assert_eq!(status[0], BlockStatus::Skipped);
+    assert_eq!(status[1..], [BlockStatus::Verified; 63]);
+    assert_eq!(card.image(), &image);
+}
+
+#[test]
+fn restore_reports_a_sector_no_key_opens() {
+    let image = image_with_data();
+
+    let mut blank = *SimulatedCard::blank(UID).image();
+    let trailer = AccessConditions::TRANSPORT.to_trailer(SECRET_KEY, 0x69, SECRET_KEY);
+    blank[11 * 16..12 * 16].copy_from_slice(&trailer);
+    let mut card = SimulatedCard::from_image(blank);
+    let uid = select(&mut card);
+
+    let status = restore::restore_card(
+        &uid,
+        &image,
+        &KeyRing::<16>::with_defaults(),
+        &OPTIONS,
+        &mut card,
+    )
+    .unwrap();
+    for (abs_block, block_status) in status.iter().enumerate().skip(1) {
+        let expected = match abs_block / 4 {
+            2 => BlockStatus::Failed("No key opened the sector"),
+            _ => BlockStatus::Verified,
+        };
+        assert_eq!(*block_status, expected, "block {abs_block}");
+    }
+    // The locked sector is left as it was
+    assert_eq!(card.image()[8 * 16..12 * 16], blank[8 * 16..12 * 16]);
+}
+
+#[test]
+fn restore_writes_new_keys_and_access_bits() {
+    // Data blocks with either key, key B no longer readable
+    let conditions = AccessConditions {
+        data: [AccessBits::new(false, false, false); 3],
+        trailer: AccessBits::new(false, true, true),
+    };
+    let mut image = image_with_data();
+    for sector in 0..16 {
+        let start = (sector * 4 + 3) * 16;
+        image[start..start + 16].copy_from_slice(&conditions.to_trailer(KEY_A, 0x69, SECRET_KEY));
+    }
+    restore::check_mfd_keys(&image).unwrap();
+
+    let mut card = SimulatedCard::blank(UID);
+    let uid = select(&mut card);
+    let status = restore::restore_card(
+        &uid,
+        &image,
+        &KeyRing::<16>::with_defaults(),
+        &OPTIONS,
+        &mut card,
+    )
+    .unwrap();
+    assert_eq!(status[0], BlockStatus::Skipped);
+    assert_eq!(status[1..], [BlockStatus::Verified; 63]);
+    assert_eq!(card.image(), &image);
+
+    // The new keys open the card, the old ones don't
+    retry::reselect(&uid, &mut card).unwrap();
+    let mut keyring = KeyRing::<16>::new();
+    restore::add_image_keys(&image, &mut keyring).unwrap();
+    let dump = dump::dump_card(&uid, &keyring, &mut card).unwrap();
+    assert_eq!(dump.failed, [false; 16]);
+    // Key B can't be read any more
+    let mut expected = image;
+    for sector in 0..16 {
+        let start = (sector * 4 + 3) * 16;
+        expected[start + 10..start + 16].fill(0);
+    }
+    assert_eq!(dump.data, expected);
+    let dump = dump::dump_card(&uid, &KeyRing::<16>::with_defaults(), &mut card).unwrap();
+    assert_eq!(dump.failed, [true; 16]);
+}
+
+#[test]
+fn mfd_image_without_key_a_is_rejected() {
+    let mut image = image_with_data();
+    image[7 * 16..7 * 16 + 6].fill(0);
+    assert!(restore::check_mfd_keys(&image).is_err());
+}
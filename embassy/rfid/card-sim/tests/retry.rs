@@ -0,0 +1,78 @@
+//! Retrying operations on a card at the edge of the field.
+
+use card_sim::sim::SimulatedCard;
+use mfrc522::{Error, Uid};
+use rfid_common::error::RfidError;
+use rfid_common::reader::{self, CardReader};
+use rfid_common::retry::{self, RetryPolicy};
+
+const UID: [u8; 4] = [0xDE, 0xAD, 0xBE, 0xEF];
+const DEFAULT_KEY: [u8; 6] = [0xFF; 6];
+
+fn select(card: &mut SimulatedCard) -> Uid {
+    let atqa = card.reqa().expect("card answers REQA");
+    card.select(&atqa).expect("card can be selected")
+}
+
+#[test]
+fn dropped_frames_are_retried() {
+    let mut card = SimulatedCard::blank(UID);
+    let uid = select(&mut card);
+
+    card.dropped_frames = 2;
+    let blocks = reader::read_sector(&uid, 0, &DEFAULT_KEY, &RetryPolicy::default(), &mut card);
+    assert_eq!(blocks.unwrap()[0][..4], UID);
+    assert_eq!(card.dropped_frames, 0);
+}
+
+#[test]
+fn gives_up_after_the_last_attempt() {
+    let mut card = SimulatedCard::blank(UID);
+    let uid = select(&mut card);
+
+    card.dropped_frames = 3;
+    let result = reader::read_sector(&uid, 1, &DEFAULT_KEY, &RetryPolicy::default(), &mut card);
+    assert_eq!(result, Err(RfidError::Timeout { block: 4 }));
+}
+
+#[test]
+fn no_retry_without_a_policy() {
+    let mut card = SimulatedCard::blank(UID);
+    let uid = select(&mut card);
+
+    card.dropped_frames = 1;
+    let result = reader::read_sector(&uid, 1, &DEFAULT_KEY, &RetryPolicy::NONE, &mut card);
+    assert_eq!(result, Err(RfidError::Timeout { block: 4 }));
+}
+
+#[test]
+fn card_removed_between_attempts() {
+    let mut card = SimulatedCard::blank(UID);
+    let uid = select(&mut card);
+
+    card.dropped_frames = 1;
+    let mut attempts = 0;
+    let result = retry::with_retry(&RetryPolicy::default(), &uid, &mut card, |card| {
+        attempts += 1;
+        card.mf_authenticate(&uid, 4, &DEFAULT_KEY).unwrap();
+        let result = card.mf_read(4);
+        card.present = false;
+        result.map_err(|_| RfidError::Timeout { block: 4 })
+    });
+
+    // The reselect fails, so the read error is what comes back
+    assert_eq!(result, Err(RfidError::Timeout { block: 4 }));
+    assert_eq!(attempts, 1);
+}
+
+#[test]
+fn reselect_notices_a_different_card() {
+    let mut card = SimulatedCard::blank(UID);
+    let uid = select(&mut card);
+
+    let mut other = SimulatedCard::blank([0x01, 0x02, 0x03, 0x04]);
+    assert!(matches!(
+        retry::reselect(&uid, &mut other),
+        Err(Error::Collision)
+    ));
+}
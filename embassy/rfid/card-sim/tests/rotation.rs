@@ -0,0 +1,193 @@
+//! Moving every sector to new keys, as `change-key` does.
+
+use core::convert::Infallible;
+
+use card_sim::sim::{SimError, SimulatedCard};
+use mfrc522::{Error, Uid};
+use rfid_common::access::{AccessBits, AccessConditions};
+use rfid_common::error::RfidError;
+use rfid_common::keys::{Diversified, KeySchedule, MASTER_SECRET, SectorKeys};
+use rfid_common::reader::{self, CardReader};
+use rfid_common::retry::{self, RetryPolicy};
+use rfid_common::rotation;
+
+const UID: [u8; 4] = [0xDE, 0xAD, 0xBE, 0xEF];
+const OLD_KEYS: SectorKeys = SectorKeys {
+    key_a: [0xFF; 6],
+    key_b: [0xFF; 6],
+};
+const NEW_KEYS: SectorKeys = SectorKeys {
+    key_a: [0x52, 0x75, 0x73, 0x74, 0x65, 0x64], // "Rusted"
+    key_b: [0x46, 0x65, 0x72, 0x72, 0x69, 0x73], // "Ferris"
+};
+const SECTORS: [u8; 16] = [0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15];
+
+fn select(card: &mut SimulatedCard) -> Uid {
+    let atqa = card.reqa().expect("card answers REQA");
+    card.select(&atqa).expect("card can be selected")
+}
+
+/// Opens every sector with its key A from `keys`.
+fn assert_on_keys(card: &mut SimulatedCard, uid: &Uid, keys: &impl KeySchedule) {
+    for sector in SECTORS {
+        retry::reselect(uid, card).unwrap();
+        let key = keys.keys(uid.as_bytes(), sector).key_a;
+        let blocks = reader::read_sector(uid, sector, &key, &RetryPolicy::NONE, card);
+        assert!(blocks.is_ok(), "sector {sector}: {blocks:?}");
+    }
+}
+
+#[test]
+fn every_sector_moves_to_the_new_keys() {
+    let mut card = SimulatedCard::blank(UID);
+    let uid = select(&mut card);
+    let policy = RetryPolicy::default();
+
+    rotation::rotate_keys(&uid, &SECTORS, &OLD_KEYS, &NEW_KEYS, &policy, &mut card).unwrap();
+    assert_on_keys(&mut card, &uid, &NEW_KEYS);
+
+    // Access bits and the general purpose byte are kept, key B is readable
+    retry::reselect(&uid, &mut card).unwrap();
+    let blocks = reader::read_sector(&uid, 1, &NEW_KEYS.key_a, &policy, &mut card).unwrap();
+    assert_eq!(blocks[3][6..10], [0xFF, 0x07, 0x80, 0x69]);
+    assert_eq!(blocks[3][10..], NEW_KEYS.key_b);
+
+    // The old key no longer opens it
+    retry::reselect(&uid, &mut card).unwrap();
+    assert_eq!(
+        reader::read_sector(&uid, 1, &OLD_KEYS.key_a, &policy, &mut card),
+        Err(RfidError::AuthFailed { sector: 1 })
+    );
+}
+
+#[test]
+fn diversified_keys_differ_per_sector() {
+    let mut card = SimulatedCard::blank(UID);
+    let uid = select(&mut card);
+    let new = Diversified::new(MASTER_SECRET);
+
+    rotation::rotate_keys(
+        &uid,
+        &SECTORS,
+        &OLD_KEYS,
+        &new,
+        &RetryPolicy::default(),
+        &mut card,
+    )
+    .unwrap();
+    assert_on_keys(&mut card, &uid, &new);
+
+    assert_ne!(new.keys(&UID, 1).key_a, new.keys(&UID, 2).key_a);
+}
+
+#[test]
+fn nothing_is_written_if_a_sector_cannot_be_rotated() {
+    let mut card = SimulatedCard::blank(UID);
+    let uid = select(&mut card);
+
+    // Only key B may change the keys of sector 3
+    let key_b_only = AccessConditions {
+        trailer: AccessBits::new(false, true, true),
+        ..AccessConditions::TRANSPORT
+    };
+    card.mf_authenticate(&uid, 15, &OLD_KEYS.key_a).unwrap();
+    card.mf_write(
+        15,
+        key_b_only.to_trailer(OLD_KEYS.key_a, 0x69, OLD_KEYS.key_b),
+    )
+    .unwrap();
+    let before = *card.image();
+
+    let error = rotation::rotate_keys(
+        &uid,
+        &SECTORS,
+        &OLD_KEYS,
+        &NEW_KEYS,
+        &RetryPolicy::default(),
+        &mut card,
+    )
+    .unwrap_err();
+
+    assert_eq!(error.sector, 3);
+    assert!(matches!(
+        error.error,
+        RfidError::InvalidTrailer { sector: 3, .. }
+    ));
+    assert!(error.stuck.is_empty());
+    assert_eq!(*card.image(), before);
+}
+
+/// Refuses every write to one block, the way a card does when its access
+/// bits forbid it.
+struct RefuseBlock<'a> {
+    card: &'a mut SimulatedCard,
+    block: u8,
+}
+
+impl CardReader for RefuseBlock<'_> {
+    type Atqa = [u8; 2];
+    type CommError = Infallible;
+
+    fn reqa(&mut self) -> Result<Self::Atqa, SimError> {
+        self.card.reqa()
+    }
+
+    fn wupa(&mut self) -> Result<Self::Atqa, SimError> {
+        self.card.wupa()
+    }
+
+    fn select(&mut self, atqa: &Self::Atqa) -> Result<Uid, SimError> {
+        self.card.select(atqa)
+    }
+
+    fn hlta(&mut self) -> Result<(), SimError> {
+        self.card.hlta()
+    }
+
+    fn stop_crypto1(&mut self) -> Result<(), SimError> {
+        self.card.stop_crypto1()
+    }
+
+    fn mf_authenticate(&mut self, uid: &Uid, block: u8, key: &[u8; 6]) -> Result<(), SimError> {
+        self.card.mf_authenticate(uid, block, key)
+    }
+
+    fn mf_read(&mut self, block: u8) -> Result<[u8; 16], SimError> {
+        self.card.mf_read(block)
+    }
+
+    fn mf_write(&mut self, block: u8, data: [u8; 16]) -> Result<(), SimError> {
+        if block == self.block {
+            return Err(Error::Nak);
+        }
+        self.card.mf_write(block, data)
+    }
+}
+
+#[test]
+fn failed_sector_rolls_back_the_ones_before_it() {
+    let mut card = SimulatedCard::blank(UID);
+    let uid = select(&mut card);
+    let before = *card.image();
+
+    // Sectors 0 to 4 are rotated before the trailer of sector 5 is refused
+    let mut reader = RefuseBlock {
+        card: &mut card,
+        block: 23,
+    };
+    let error = rotation::rotate_keys(
+        &uid,
+        &SECTORS,
+        &OLD_KEYS,
+        &NEW_KEYS,
+        &RetryPolicy::default(),
+        &mut reader,
+    )
+    .unwrap_err();
+
+    assert_eq!(error.sector, 5);
+    assert_eq!(error.error, RfidError::WriteFailed { block: 23 });
+    assert!(error.stuck.is_empty());
+    assert_eq!(*card.image(), before);
+    assert_on_keys(&mut card, &uid, &OLD_KEYS);
+}
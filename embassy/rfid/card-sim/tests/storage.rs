@@ -0,0 +1,183 @@
+//! Writing payloads that span several blocks, as `write-data` does.
+
+use card_sim::sim::SimulatedCard;
+use mfrc522::Uid;
+use rfid_common::access::{AccessBits, AccessConditions};
+use rfid_common::error::RfidError;
+use rfid_common::keys::SectorKeys;
+use rfid_common::reader::CardReader;
+use rfid_common::retry::{self, RetryPolicy};
+use rfid_common::storage::{self, LAST_BLOCK_1K, StorageError};
+
+const UID: [u8; 4] = [0xDE, 0xAD, 0xBE, 0xEF];
+const DEFAULT_KEYS: SectorKeys = SectorKeys {
+    key_a: [0xFF; 6],
+    key_b: [0xFF; 6],
+};
+const KEY_B: [u8; 6] = [0x46, 0x65, 0x72, 0x72, 0x69, 0x73]; // "Ferris"
+
+// Long enough to run past the sector trailer into the next sector
+const RECORD: &str = r#"{"name":"Ferris the Crab","role":"mascot","lang":"Rust"}"#;
+
+fn select(card: &mut SimulatedCard) -> Uid {
+    let atqa = card.reqa().expect("card answers REQA");
+    card.select(&atqa).expect("card can be selected")
+}
+
+fn block(card: &SimulatedCard, block: usize) -> &[u8] {
+    &card.image()[block * 16..block * 16 + 16]
+}
+
+#[test]
+fn text_round_trip_across_a_trailer() {
+    let mut card = SimulatedCard::blank(UID);
+    let uid = select(&mut card);
+    let policy = RetryPolicy::NONE;
+
+    storage::write_str(
+        &uid,
+        4,
+        LAST_BLOCK_1K,
+        RECORD,
+        &DEFAULT_KEYS,
+        &policy,
+        &mut card,
+    )
+    .unwrap();
+
+    // 4 header bytes and 56 payload bytes: blocks 4, 5, 6 and then 8
+    assert_eq!(block(&card, 4)[..2], (RECORD.len() as u16).to_le_bytes());
+    assert_eq!(block(&card, 4)[4..], RECORD.as_bytes()[..12]);
+    assert_eq!(block(&card, 8)[..12], RECORD.as_bytes()[44..]);
+    assert_eq!(block(&card, 8)[12..], [0; 4]);
+    // The trailer in between is left alone
+    assert_eq!(block(&card, 7)[6..10], [0xFF, 0x07, 0x80, 0x69]);
+
+    let text =
+        storage::read_str::<128, _>(&uid, 4, LAST_BLOCK_1K, &DEFAULT_KEYS, &policy, &mut card)
+            .unwrap();
+    assert_eq!(text, RECORD);
+}
+
+#[test]
+fn manufacturer_block_is_skipped() {
+    let mut card = SimulatedCard::blank(UID);
+    let uid = select(&mut card);
+    let block0: [u8; 16] = block(&card, 0).try_into().unwrap();
+
+    storage::write_str(
+        &uid,
+        0,
+        LAST_BLOCK_1K,
+        "hello",
+        &DEFAULT_KEYS,
+        &RetryPolicy::NONE,
+        &mut card,
+    )
+    .unwrap();
+
+    assert_eq!(block(&card, 0), block0);
+    assert_eq!(block(&card, 1)[4..9], *b"hello");
+}
+
+#[test]
+fn payload_past_the_end_of_the_card_is_refused() {
+    let mut card = SimulatedCard::blank(UID);
+    let uid = select(&mut card);
+
+    // Blocks 60, 61 and 62 hold 48 bytes, less the header
+    let result = storage::write_str(
+        &uid,
+        60,
+        LAST_BLOCK_1K,
+        RECORD,
+        &DEFAULT_KEYS,
+        &RetryPolicy::NONE,
+        &mut card,
+    );
+    assert_eq!(result, Err(StorageError::TooLarge));
+    assert_eq!(block(&card, 60), [0; 16]);
+}
+
+#[test]
+fn damaged_payload_is_reported() {
+    let mut card = SimulatedCard::blank(UID);
+    let uid = select(&mut card);
+    let policy = RetryPolicy::NONE;
+
+    storage::write_str(
+        &uid,
+        4,
+        LAST_BLOCK_1K,
+        RECORD,
+        &DEFAULT_KEYS,
+        &policy,
+        &mut card,
+    )
+    .unwrap();
+
+    // Overwrite the last block, as if the write had been cut short there
+    card.mf_authenticate(&uid, 8, &DEFAULT_KEYS.key_a).unwrap();
+    card.mf_write(8, [0; 16]).unwrap();
+
+    let result =
+        storage::read_str::<128, _>(&uid, 4, LAST_BLOCK_1K, &DEFAULT_KEYS, &policy, &mut card);
+    assert_eq!(result, Err(StorageError::Corrupt));
+}
+
+#[test]
+fn header_longer_than_the_buffer_is_reported() {
+    let mut card = SimulatedCard::blank(UID);
+    let uid = select(&mut card);
+    let policy = RetryPolicy::NONE;
+
+    // A length of 0xFFFF, as left behind by anything but write-data
+    card.mf_authenticate(&uid, 4, &DEFAULT_KEYS.key_a).unwrap();
+    card.mf_write(4, [0xFF; 16]).unwrap();
+
+    let result =
+        storage::read_str::<128, _>(&uid, 4, LAST_BLOCK_1K, &DEFAULT_KEYS, &policy, &mut card);
+    assert_eq!(result, Err(StorageError::Corrupt));
+}
+
+#[test]
+fn access_bits_stop_key_a_from_writing() {
+    let mut card = SimulatedCard::blank(UID);
+    let uid = select(&mut card);
+
+    // Data blocks readable with A or B, but only key B may write them
+    let read_only = AccessConditions {
+        data: [AccessBits::new(true, false, false); 3],
+        trailer: AccessBits::new(false, true, true),
+    };
+    card.mf_authenticate(&uid, 11, &DEFAULT_KEYS.key_a).unwrap();
+    card.mf_write(11, read_only.to_trailer(DEFAULT_KEYS.key_a, 0x69, KEY_B))
+        .unwrap();
+
+    let result = storage::write_str(
+        &uid,
+        8,
+        LAST_BLOCK_1K,
+        "hello",
+        &DEFAULT_KEYS,
+        &RetryPolicy::default(),
+        &mut card,
+    );
+    assert_eq!(
+        result,
+        Err(StorageError::Rfid(RfidError::WriteFailed { block: 8 }))
+    );
+    assert_eq!(block(&card, 8), [0; 16]);
+
+    // Reading is still allowed
+    retry::reselect(&uid, &mut card).unwrap();
+    let result = storage::read_str::<16, _>(
+        &uid,
+        8,
+        LAST_BLOCK_1K,
+        &DEFAULT_KEYS,
+        &RetryPolicy::NONE,
+        &mut card,
+    );
+    assert_eq!(result, Err(StorageError::Corrupt));
+}
@@ -26,6 +26,13 @@ use mfrc522::{Mfrc522, comm::blocking::spi::SpiInterface};
 use core::fmt::Write;
 use heapless::String;
 
+use rfid_common::access::AccessConditions;
+use rfid_common::error::RfidError;
+use rfid_common::keys::{Diversified, KeySchedule, MASTER_SECRET, SectorKeys};
+use rfid_common::reader;
+use rfid_common::retry::RetryPolicy;
+use rfid_common::rotation;
+
 fn print_hex(data: &[u8]) {
     let mut buff: String<64> = String::new();
     for &d in data.iter() {
@@ -37,42 +44,24 @@ fn print_hex(data: &[u8]) {
 fn read_sector<E, COMM>(
     uid: &mfrc522::Uid,
     sector: u8,
-    key: &[u8; 6],
+    keys: &impl KeySchedule,
+    policy: &RetryPolicy,
     rfid: &mut Mfrc522<COMM, mfrc522::Initialized>,
-) -> Result<(), &'static str>
+) -> Result<(), RfidError>
 where
     COMM: mfrc522::comm::Interface<Error = E>,
 {
-    let block_offset = sector * 4;
-    rfid.mf_authenticate(uid, block_offset, key)
-        .map_err(|_| "Auth failed")?;
+    let key = keys.keys(uid.as_bytes(), sector).key_a;
+    let blocks = reader::read_sector(uid, sector, &key, policy, rfid)?;
 
-    for abs_block in block_offset..block_offset + 4 {
-        let data = rfid.mf_read(abs_block).map_err(|_| "Read failed")?;
-        print_hex(&data);
+    for data in blocks.iter() {
+        print_hex(data);
     }
-    Ok(())
-}
-
-fn write_block<E, COMM>(
-    uid: &mfrc522::Uid,
-    sector: u8,
-    rel_block: u8,
-    data: [u8; 16],
-    key: &[u8; 6],
-    rfid: &mut Mfrc522<COMM, mfrc522::Initialized>,
-) -> Result<(), &'static str>
-where
-    COMM: mfrc522::comm::Interface<Error = E>,
-{
-    let block_offset = sector * 4;
-    let abs_block = block_offset + rel_block;
-
-    rfid.mf_authenticate(uid, block_offset, key)
-        .map_err(|_| "Auth failed")?;
-
-    rfid.mf_write(abs_block, data).map_err(|_| "Write failed")?;
 
+    match AccessConditions::from_trailer(&blocks[3]) {
+        Ok(conditions) => info!("Access conditions: {:?}", conditions),
+        Err(e) => error!("Invalid trailer: {:?}", e),
+    }
     Ok(())
 }
 
@@ -98,34 +87,41 @@ async fn main(_spawner: Spawner) {
         .init()
         .expect("failed to initialize the RFID reader");
 
+    // Sectors to move to the new keys, here every sector of a 1K
+    const SECTORS: [u8; 16] = [0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15];
+    // Sector shown before and after
     let target_sector = 1;
-    let rel_block = 3;
-    const DATA: [u8; 16] = [
-        0x52, 0x75, 0x73, 0x74, 0x65, 0x64, // Key A: "Rusted"
-        0xFF, 0x07, 0x80, 0x69, // Access bits and trailer byte
-        0x46, 0x65, 0x72, 0x72, 0x69, 0x73, // Key B: "Ferris"
-    ];
-    let current_key = &[0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF];
-    let new_key: &[u8; 6] = &DATA[..6].try_into().expect("have enough data");
+
+    const OLD_KEYS: SectorKeys = SectorKeys {
+        key_a: [0xFF; 6],
+        key_b: [0xFF; 6],
+    };
+    // Every card gets its own keys, see `keys`
+    const NEW_KEYS: Diversified = Diversified::new(MASTER_SECRET);
+    let policy = RetryPolicy::default();
 
     loop {
         if let Ok(atqa) = rfid.reqa() {
             if let Ok(uid) = rfid.select(&atqa) {
-                defmt::println!("\r\n----Before Write----\r\n");
-                if let Err(e) = read_sector(&uid, target_sector, current_key, &mut rfid) {
+                defmt::println!("\r\n----Before Rotation----\r\n");
+                if let Err(e) = read_sector(&uid, target_sector, &OLD_KEYS, &policy, &mut rfid) {
                     error!("Error reading sector: {:?}", e);
                 }
                 Timer::after_millis(200).await;
 
-                if let Err(e) =
-                    write_block(&uid, target_sector, rel_block, DATA, current_key, &mut rfid)
-                {
-                    error!("Error writing block: {:?}", e);
+                match rotation::rotate_keys(
+                    &uid, &SECTORS, &OLD_KEYS, &NEW_KEYS, &policy, &mut rfid,
+                ) {
+                    Ok(()) => info!("All {} sectors are on the new keys", SECTORS.len()),
+                    Err(e) if e.stuck.is_empty() => {
+                        error!("Rotation failed, card left on the old keys: {:?}", e)
+                    }
+                    Err(e) => error!("Rotation failed, sectors {} are stuck: {:?}", e.stuck, e),
                 }
                 Timer::after_millis(200).await;
 
-                defmt::println!("\r\n----After Write----\r\n");
-                if let Err(e) = read_sector(&uid, target_sector, new_key, &mut rfid) {
+                defmt::println!("\r\n----After Rotation----\r\n");
+                if let Err(e) = read_sector(&uid, target_sector, &NEW_KEYS, &policy, &mut rfid) {
                     error!("Error reading sector: {:?}", e);
                 }
 
@@ -0,0 +1,223 @@
+//! Telling a wiring fault from a bad card or poor range.
+//!
+//! `Mfrc522::new(itf).init()` only tells us that something went wrong. The
+//! checks here go one step at a time:
+//!
+//! 1. VersionReg: `0x00` or `0xFF` means nobody answers on the SPI bus
+//!    (MISO, SCK, CS, power or RST), anything else means the chip is there.
+//! 2. The digital self-test from section 16.1.1 of the datasheet: the chip
+//!    runs its CRC coprocessor over a known buffer and fills the FIFO with
+//!    64 bytes that only depend on the chip version. A mismatch points at a
+//!    damaged chip or a noisy bus.
+//! 3. Antenna and receiver gain: if the chip is fine but cards answer only
+//!    at high gain, or only when pressed on the antenna, the range is poor.
+//!    A card that never answers at any gain is likely dead.
+//!
+//! The self-test resets the chip, so run it before initializing the driver.
+
+use embassy_time::{Duration, block_for};
+use embedded_hal::spi::SpiDevice;
+use mfrc522::RxGain;
+use rfid_common::pcd::{
+    AUTO_TEST_REG, COMMAND_REG, FIFO_LEVEL_REG, RF_CFG_REG, SharedSpi, TX_CONTROL_REG, VERSION_REG,
+};
+
+// Commands (see section 10.3 of the MFRC522 datasheet)
+const IDLE: u8 = 0x00;
+const MEM: u8 = 0x01;
+const CALC_CRC: u8 = 0x03;
+const SOFT_RESET: u8 = 0x0F;
+
+/// CommandReg bit set while the chip is powered down or still resetting
+const POWER_DOWN: u8 = 0x10;
+/// FIFOLevelReg bit that empties the FIFO
+const FLUSH_BUFFER: u8 = 0x80;
+/// AutoTestReg value that enables the digital self-test
+const SELF_TEST_ENABLE: u8 = 0x09;
+
+/// TX1 and TX2 drive the antenna
+const ANTENNA_BITS: u8 = 0b11;
+/// RxGain bits of RFCfgReg
+const RX_GAIN_MASK: u8 = 0x70;
+
+/// FIFO content after the self-test of a version 1.0 chip (VersionReg 0x91)
+const SELF_TEST_V1: [u8; 64] = [
+    0x00, 0xC6, 0x37, 0xD5, 0x32, 0xB7, 0x57, 0x5C, 0xC2, 0xD8, 0x7C, 0x4D, 0xD9, 0x70, 0xC7, 0x73,
+    0x10, 0xE6, 0xD2, 0xAA, 0x5E, 0xA1, 0x3E, 0x5A, 0x14, 0xAF, 0x30, 0x61, 0xC9, 0x70, 0xDB, 0x2E,
+    0x64, 0x22, 0x72, 0xB5, 0xBD, 0x65, 0xF4, 0xEC, 0x22, 0xBC, 0xD3, 0x72, 0x35, 0xCD, 0xAA, 0x41,
+    0x1F, 0xA7, 0xF3, 0x53, 0x14, 0xDE, 0x7E, 0x02, 0xD9, 0x0F, 0xB5, 0x5E, 0x25, 0x1D, 0x29, 0x79,
+];
+
+/// FIFO content after the self-test of a version 2.0 chip (VersionReg 0x92)
+const SELF_TEST_V2: [u8; 64] = [
+    0x00, 0xEB, 0x66, 0xBA, 0x57, 0xBF, 0x23, 0x95, 0xD0, 0xE3, 0x0D, 0x3D, 0x27, 0x89, 0x5C, 0xDE,
+    0x9D, 0x3B, 0xA7, 0x00, 0x21, 0x5B, 0x89, 0x82, 0x51, 0x3A, 0xEB, 0x02, 0x0C, 0xA5, 0x00, 0x49,
+    0x7C, 0x84, 0x4D, 0xB3, 0xCC, 0xD2, 0x1B, 0x81, 0x5D, 0x48, 0x76, 0xD5, 0x71, 0x61, 0x21, 0xA9,
+    0x86, 0x96, 0x83, 0x38, 0xCF, 0x9D, 0x5B, 0x6D, 0xDC, 0x15, 0xBA, 0x3E, 0x7D, 0x95, 0x3B, 0x2F,
+];
+
+/// Every receiver gain, weakest first
+pub const RX_GAINS: [RxGain; 6] = [
+    RxGain::DB18,
+    RxGain::DB23,
+    RxGain::DB33,
+    RxGain::DB38,
+    RxGain::DB43,
+    RxGain::DB48,
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub enum SelfTest {
+    Passed,
+    /// The FIFO differs from the reference, first at `index`
+    Failed {
+        index: usize,
+        got: u8,
+        expected: u8,
+    },
+    /// The chip never produced the 64 bytes
+    Timeout,
+    /// No reference output for this VersionReg value
+    NoReference(u8),
+}
+
+pub fn read_version<D: SpiDevice>(pcd: &mut SharedSpi<'_, D>) -> Result<u8, D::Error> {
+    pcd.read_register(VERSION_REG)
+}
+
+/// `0x00` or `0xFF` is what the bus reads with no chip driving MISO.
+pub fn is_wiring_fault(version: u8) -> bool {
+    matches!(version, 0x00 | 0xFF)
+}
+
+pub fn chip_name(version: u8) -> &'static str {
+    match version {
+        0x00 | 0xFF => "no answer, check the wiring",
+        0x88 => "FM17522 (clone)",
+        0x89 => "FM17522E (clone)",
+        0x90 => "MFRC522 v0.0",
+        0x91 => "MFRC522 v1.0",
+        0x92 => "MFRC522 v2.0",
+        0x12 | 0xB2 => "counterfeit chip",
+        _ => "unknown chip",
+    }
+}
+
+fn reference(version: u8) -> Option<&'static [u8; 64]> {
+    match version {
+        0x91 => Some(&SELF_TEST_V1),
+        0x92 => Some(&SELF_TEST_V2),
+        _ => None,
+    }
+}
+
+fn soft_reset<D: SpiDevice>(pcd: &mut SharedSpi<'_, D>) -> Result<(), D::Error> {
+    pcd.write_register(COMMAND_REG, SOFT_RESET)?;
+    // The oscillator needs a moment to start up again
+    for _ in 0..10 {
+        block_for(Duration::from_millis(5));
+        if pcd.read_register(COMMAND_REG)? & POWER_DOWN == 0 {
+            break;
+        }
+    }
+    Ok(())
+}
+
+/// Runs the digital self-test and compares the FIFO with the reference of
+/// the chip version.
+///
+/// Leaves the chip reset, so the driver has to be initialized afterwards.
+pub fn self_test<D: SpiDevice>(pcd: &mut SharedSpi<'_, D>) -> Result<SelfTest, D::Error> {
+    let version = read_version(pcd)?;
+    let Some(expected) = reference(version) else {
+        return Ok(SelfTest::NoReference(version));
+    };
+
+    soft_reset(pcd)?;
+
+    // Clear the internal buffer
+    pcd.write_register(FIFO_LEVEL_REG, FLUSH_BUFFER)?;
+    pcd.write_fifo(&[0; 25])?;
+    pcd.write_register(COMMAND_REG, MEM)?;
+
+    pcd.write_register(AUTO_TEST_REG, SELF_TEST_ENABLE)?;
+    pcd.write_fifo(&[0])?;
+    pcd.write_register(COMMAND_REG, CALC_CRC)?;
+
+    let mut done = false;
+    for _ in 0..100 {
+        if pcd.read_register(FIFO_LEVEL_REG)? >= 64 {
+            done = true;
+            break;
+        }
+        block_for(Duration::from_millis(1));
+    }
+    pcd.write_register(COMMAND_REG, IDLE)?;
+
+    let mut result = [0u8; 64];
+    let len = pcd.read_fifo(&mut result)?;
+
+    pcd.write_register(AUTO_TEST_REG, 0x00)?;
+    soft_reset(pcd)?;
+
+    if !done || len < result.len() {
+        return Ok(SelfTest::Timeout);
+    }
+    let mismatch = result
+        .iter()
+        .zip(expected.iter())
+        .position(|(got, expected)| got != expected);
+    Ok(match mismatch {
+        Some(index) => SelfTest::Failed {
+            index,
+            got: result[index],
+            expected: expected[index],
+        },
+        None => SelfTest::Passed,
+    })
+}
+
+pub fn is_antenna_on<D: SpiDevice>(pcd: &mut SharedSpi<'_, D>) -> Result<bool, D::Error> {
+    Ok(pcd.read_register(TX_CONTROL_REG)? & ANTENNA_BITS == ANTENNA_BITS)
+}
+
+pub fn antenna_on<D: SpiDevice>(pcd: &mut SharedSpi<'_, D>) -> Result<(), D::Error> {
+    let value = pcd.read_register(TX_CONTROL_REG)?;
+    pcd.write_register(TX_CONTROL_REG, value | ANTENNA_BITS)
+}
+
+/// Switching the field off powers down every card in it, which sends them
+/// back to IDLE.
+pub fn antenna_off<D: SpiDevice>(pcd: &mut SharedSpi<'_, D>) -> Result<(), D::Error> {
+    let value = pcd.read_register(TX_CONTROL_REG)?;
+    pcd.write_register(TX_CONTROL_REG, value & !ANTENNA_BITS)
+}
+
+pub fn rx_gain<D: SpiDevice>(pcd: &mut SharedSpi<'_, D>) -> Result<RxGain, D::Error> {
+    // 0b010 and 0b011 are aliases of 18 dB and 23 dB
+    Ok(match (pcd.read_register(RF_CFG_REG)? & RX_GAIN_MASK) >> 4 {
+        0 | 2 => RxGain::DB18,
+        1 | 3 => RxGain::DB23,
+        4 => RxGain::DB33,
+        5 => RxGain::DB38,
+        6 => RxGain::DB43,
+        _ => RxGain::DB48,
+    })
+}
+
+/// Sets the receiver gain, keeping the other bits of RFCfgReg.
+pub fn set_rx_gain<D: SpiDevice>(pcd: &mut SharedSpi<'_, D>, gain: RxGain) -> Result<(), D::Error> {
+    let value = pcd.read_register(RF_CFG_REG)?;
+    pcd.write_register(RF_CFG_REG, (value & !RX_GAIN_MASK) | u8::from(gain))
+}
+
+pub fn gain_db(gain: RxGain) -> u8 {
+    match gain {
+        RxGain::DB18 => 18,
+        RxGain::DB23 => 23,
+        RxGain::DB33 => 33,
+        RxGain::DB38 => 38,
+        RxGain::DB43 => 43,
+        RxGain::DB48 => 48,
+    }
+}
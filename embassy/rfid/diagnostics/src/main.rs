@@ -0,0 +1,175 @@
+#![no_std]
+#![no_main]
+
+pub mod diagnostics;
+
+use embassy_executor::Spawner;
+use embassy_time::Timer;
+
+// defmt Logging
+use defmt::{error, info, warn};
+use defmt_rtt as _;
+
+use panic_probe as _;
+
+// For SPI
+use embassy_rp::spi;
+use embassy_rp::spi::Spi;
+use embassy_time::Delay;
+use embedded_hal_bus::spi::ExclusiveDevice;
+
+// For CS Pin
+use embassy_rp::gpio::{Level, Output};
+
+// Driver for the MFRC522
+use mfrc522::{Mfrc522, comm::blocking::spi::SpiInterface};
+
+use core::cell::RefCell;
+
+use rfid_common::pcd::SharedSpi;
+
+use crate::diagnostics::SelfTest;
+
+/// Attempts per gain in the range sweep
+const ATTEMPTS: u8 = 10;
+
+#[embassy_executor::main]
+async fn main(_spawner: Spawner) {
+    let p = embassy_rp::init(Default::default());
+
+    info!("Initializing the program");
+
+    let miso = p.PIN_0;
+    let cs_pin = Output::new(p.PIN_1, Level::High);
+    let clk = p.PIN_2;
+    let mosi = p.PIN_3;
+
+    let mut config = spi::Config::default();
+    config.frequency = 1_000_000;
+
+    let spi_bus = Spi::new_blocking(p.SPI0, clk, mosi, miso, config);
+    let spi = ExclusiveDevice::new(spi_bus, cs_pin, Delay).expect("Failed to get exclusive device");
+
+    // Shared between the driver and our register reads
+    let spi = RefCell::new(spi);
+    let mut pcd = SharedSpi::new(&spi);
+
+    // Step 1: is there a chip on the bus at all?
+    let version = loop {
+        match diagnostics::read_version(&mut pcd) {
+            Ok(version) if !diagnostics::is_wiring_fault(version) => break version,
+            Ok(version) => error!(
+                "VersionReg reads {:02x}: {}",
+                version,
+                diagnostics::chip_name(version)
+            ),
+            Err(_) => error!("SPI transfer failed"),
+        }
+        Timer::after_secs(2).await;
+    };
+    info!(
+        "VersionReg {:02x}: {}",
+        version,
+        diagnostics::chip_name(version)
+    );
+
+    // Step 2: the digital self-test, before the driver configures the chip
+    match diagnostics::self_test(&mut pcd) {
+        Ok(SelfTest::Passed) => info!("Self-test passed"),
+        Ok(SelfTest::Failed {
+            index,
+            got,
+            expected,
+        }) => error!(
+            "Self-test failed: byte {} is {:02x}, expected {:02x}",
+            index, got, expected
+        ),
+        Ok(SelfTest::Timeout) => error!("Self-test did not finish"),
+        Ok(SelfTest::NoReference(version)) => {
+            warn!("No self-test reference for version {:02x}", version)
+        }
+        Err(_) => error!("SPI transfer failed during the self-test"),
+    }
+
+    let itf = SpiInterface::new(pcd);
+    let mut rfid = match Mfrc522::new(itf).init() {
+        Ok(rfid) => rfid,
+        Err(e) => {
+            error!(
+                "failed to initialize the RFID reader: {:?}",
+                defmt::Debug2Format(&e)
+            );
+            loop {
+                Timer::after_secs(1).await;
+            }
+        }
+    };
+
+    // Step 3: antenna and gain, then how well a card answers at each gain
+    match (
+        diagnostics::is_antenna_on(&mut pcd),
+        diagnostics::rx_gain(&mut pcd),
+    ) {
+        (Ok(on), Ok(gain)) => info!(
+            "Antenna {}, receiver gain {} dB",
+            if on { "on" } else { "off" },
+            diagnostics::gain_db(gain)
+        ),
+        _ => error!("SPI transfer failed"),
+    }
+    let Ok(default_gain) = diagnostics::rx_gain(&mut pcd) else {
+        error!("SPI transfer failed");
+        return;
+    };
+
+    info!("Hold a card over the reader");
+    loop {
+        if rfid.wupa().is_err() {
+            Timer::after_millis(500).await;
+            continue;
+        }
+
+        info!("Card found, sweeping the receiver gain");
+        let mut best = 0;
+        for gain in diagnostics::RX_GAINS {
+            if diagnostics::set_rx_gain(&mut pcd, gain).is_err() {
+                error!("SPI transfer failed");
+                break;
+            }
+
+            let mut answers = 0;
+            for _ in 0..ATTEMPTS {
+                // Power cycle the card so it starts from IDLE on every attempt
+                let _ = diagnostics::antenna_off(&mut pcd);
+                Timer::after_millis(5).await;
+                let _ = diagnostics::antenna_on(&mut pcd);
+                Timer::after_millis(5).await;
+
+                if let Ok(atqa) = rfid.reqa()
+                    && rfid.select(&atqa).is_ok()
+                {
+                    answers += 1;
+                }
+            }
+            best = best.max(answers);
+            info!(
+                "{} dB: {}/{} selects",
+                diagnostics::gain_db(gain),
+                answers,
+                ATTEMPTS
+            );
+        }
+        let _ = diagnostics::set_rx_gain(&mut pcd, default_gain);
+
+        if best == 0 {
+            warn!("The card never completed a select: bad card or far out of range");
+        } else if best < ATTEMPTS {
+            warn!("The card answers unreliably: poor range, move it closer");
+        } else {
+            info!("The card answers reliably");
+        }
+
+        let _ = rfid.hlta();
+        Timer::after_secs(2).await;
+    }
+}
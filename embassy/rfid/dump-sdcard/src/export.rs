@@ -0,0 +1,106 @@
+//! Writes a [`CardDump`] out as a `.mfd` image and a JSON description.
+//!
+//! Both writers take anything that implements `embedded_io::Write`,
+//! which includes the files returned by `embedded-sdmmc`.
+
+use core::fmt::Write as _;
+
+use embedded_io::Write;
+use heapless::String;
+use rfid_common::dump::{BLOCKS, CardDump, SECTORS};
+use rfid_common::layout::get_block_type;
+
+/// Builds an 8.3 file name from the UID, e.g. `A1B2C3D4.MFD`.
+///
+/// FAT short names only have room for four UID bytes, so 7 and 10 byte
+/// UIDs use their last four bytes.
+pub fn file_name(uid: &[u8], extension: &str) -> String<12> {
+    let mut name = String::new();
+    for &d in uid[uid.len() - 4..].iter() {
+        write!(name, "{:02X}", d).expect("failed to write into file name");
+    }
+    write!(name, ".{}", extension).expect("failed to write into file name");
+    name
+}
+
+/// Writes the raw 1024 byte image, the layout used by `nfc-mfclassic` and friends.
+pub fn write_mfd<W: Write>(dump: &CardDump, out: &mut W) -> Result<(), W::Error> {
+    out.write_all(&dump.data)
+}
+
+/// Writes the per-block metadata as JSON, one block per line.
+pub fn write_json<W: Write>(dump: &CardDump, out: &mut W) -> Result<(), W::Error> {
+    let mut line: String<192> = String::new();
+
+    line.push_str("{\n  \"uid\": \"").expect("line too long");
+    write_hex(&mut line, &dump.uid);
+    line.push_str("\",\n  \"failed_sectors\": [")
+        .expect("line too long");
+    let mut failed = (0..SECTORS).filter(|&sector| dump.failed[sector]);
+    if let Some(sector) = failed.next() {
+        write!(line, "{}", sector).expect("line too long");
+    }
+    for sector in failed {
+        write!(line, ", {}", sector).expect("line too long");
+    }
+    line.push_str("],\n  \"blocks\": [\n")
+        .expect("line too long");
+    out.write_all(line.as_bytes())?;
+
+    for abs_block in 0..BLOCKS {
+        line.clear();
+        let info = &dump.blocks[abs_block];
+        let sector = (abs_block / 4) as u8;
+        let rel_block = (abs_block % 4) as u8;
+
+        write!(
+            line,
+            "    {{\"block\": {}, \"sector\": {}, \"type\": \"{}\", ",
+            abs_block,
+            sector,
+            get_block_type(sector, rel_block)
+        )
+        .expect("line too long");
+
+        match info.key {
+            Some(sector_key) => {
+                write!(
+                    line,
+                    "\"key_type\": \"{}\", \"key\": \"",
+                    sector_key.key_type.as_str()
+                )
+                .expect("line too long");
+                write_hex(&mut line, &sector_key.key);
+                line.push_str("\", ").expect("line too long");
+            }
+            None => line
+                .push_str("\"key_type\": null, \"key\": null, ")
+                .expect("line too long"),
+        }
+
+        match info.error {
+            Some(error) => write!(line, "\"data\": null, \"error\": \"{}\"}}", error),
+            None => {
+                line.push_str("\"data\": \"").expect("line too long");
+                write_hex(&mut line, dump.block(abs_block));
+                line.push_str("\", \"error\": null}")
+                    .map_err(|_| core::fmt::Error)
+            }
+        }
+        .expect("line too long");
+
+        if abs_block + 1 < BLOCKS {
+            line.push(',').expect("line too long");
+        }
+        line.push('\n').expect("line too long");
+        out.write_all(line.as_bytes())?;
+    }
+
+    out.write_all(b"  ]\n}\n")
+}
+
+fn write_hex<const N: usize>(buff: &mut String<N>, data: &[u8]) {
+    for &d in data.iter() {
+        write!(buff, "{:02x}", d).expect("failed to write byte into buffer");
+    }
+}
@@ -0,0 +1,189 @@
+#![no_std]
+#![no_main]
+
+pub mod export;
+
+use embassy_executor::Spawner;
+use embassy_time::Timer;
+
+// defmt Logging
+use defmt::{error, info, warn};
+use defmt_rtt as _;
+
+use panic_probe as _;
+
+// For SPI
+use embassy_rp::spi;
+use embassy_rp::spi::Spi;
+use embassy_time::Delay;
+use embedded_hal_bus::spi::ExclusiveDevice;
+
+// For CS Pin
+use embassy_rp::gpio::{Level, Output};
+
+// Driver for the MFRC522
+use mfrc522::{Mfrc522, comm::blocking::spi::SpiInterface};
+
+// For SdCard
+use embedded_sdmmc::{
+    BlockDevice, Directory, Error, File, Mode, SdCard, TimeSource, Timestamp, VolumeIdx,
+    VolumeManager,
+};
+
+use core::cell::RefCell;
+
+use rfid_common::dump;
+use rfid_common::formats;
+use rfid_common::keyring::KeyRing;
+use rfid_common::pcd::{PcdReader, SharedSpi};
+
+/// Code from https://github.com/rp-rs/rp-hal-boards/blob/main/boards/rp-pico/examples/pico_spi_sd_card.rs
+/// A dummy timesource, which is mostly important for creating files.
+#[derive(Default)]
+pub struct DummyTimesource();
+
+impl TimeSource for DummyTimesource {
+    // In theory you could use the RTC of the rp2040 here, if you had
+    // any external time synchronizing device.
+    fn get_timestamp(&self) -> Timestamp {
+        Timestamp {
+            year_since_1970: 0,
+            zero_indexed_month: 0,
+            zero_indexed_day: 0,
+            hours: 0,
+            minutes: 0,
+            seconds: 0,
+        }
+    }
+}
+
+/// Creates (or truncates) a file, fills it and flushes it to the card.
+fn save_file<D, T, E, const DIRS: usize, const FILES: usize, const VOLUMES: usize>(
+    dir: &Directory<'_, D, T, DIRS, FILES, VOLUMES>,
+    name: &str,
+    write: impl FnOnce(&mut File<'_, D, T, DIRS, FILES, VOLUMES>) -> Result<(), E>,
+) -> Result<(), E>
+where
+    D: BlockDevice,
+    T: TimeSource,
+    E: From<Error<D::Error>>,
+{
+    let mut file = dir.open_file_in_dir(name, Mode::ReadWriteCreateOrTruncate)?;
+    write(&mut file)?;
+    Ok(file.flush()?)
+}
+
+#[embassy_executor::main]
+async fn main(_spawner: Spawner) {
+    let p = embassy_rp::init(Default::default());
+
+    info!("Initializing the program");
+
+    // RFID reader on SPI0
+    let miso = p.PIN_0;
+    let cs_pin = Output::new(p.PIN_1, Level::High);
+    let clk = p.PIN_2;
+    let mosi = p.PIN_3;
+
+    let mut config = spi::Config::default();
+    config.frequency = 1_000_000;
+
+    let spi_bus = Spi::new_blocking(p.SPI0, clk, mosi, miso, config);
+    let spi = ExclusiveDevice::new(spi_bus, cs_pin, Delay).expect("Failed to get exclusive device");
+
+    // Shared between the driver and our own key B authentication
+    let spi = RefCell::new(spi);
+    let pcd = SharedSpi::new(&spi);
+
+    let itf = SpiInterface::new(pcd);
+    let mut rfid = Mfrc522::new(itf)
+        .init()
+        .expect("failed to initialize the RFID reader");
+
+    // SD card on SPI1
+    let sd_miso = p.PIN_12;
+    let sd_cs_pin = Output::new(p.PIN_13, Level::High);
+    let sd_clk = p.PIN_10;
+    let sd_mosi = p.PIN_11;
+
+    let mut sd_config = spi::Config::default();
+    sd_config.frequency = 400_000;
+
+    let sd_spi_bus = Spi::new_blocking(p.SPI1, sd_clk, sd_mosi, sd_miso, sd_config);
+    let sd_spi_device =
+        ExclusiveDevice::new(sd_spi_bus, sd_cs_pin, Delay).expect("Failed to get exclusive device");
+
+    let sdcard = SdCard::new(sd_spi_device, Delay);
+
+    info!("Init SD card controller and retrieve card size...");
+    let sd_size = sdcard.num_bytes().expect("failed to get sdcard size");
+    info!("card size is {} bytes", sd_size);
+
+    let volume_mgr = VolumeManager::new(sdcard, DummyTimesource::default());
+    let volume0 = volume_mgr
+        .open_volume(VolumeIdx(0))
+        .expect("failed to open volume");
+
+    let root_dir = volume0.open_root_dir().expect("failed to open root dir");
+
+    let keyring: KeyRing<16> = KeyRing::with_defaults();
+
+    loop {
+        if let Ok(atqa) = rfid.reqa()
+            && let Ok(uid) = rfid.select(&atqa)
+        {
+            match dump::dump_card(&uid, &keyring, &mut PcdReader::new(&mut rfid, pcd)) {
+                Ok(card_dump) => {
+                    let failed = card_dump.failed.iter().filter(|&&failed| failed).count();
+                    if failed > 0 {
+                        warn!("{} sector(s) could not be read", failed);
+                    }
+
+                    let mfd_name = export::file_name(&card_dump.uid, "MFD");
+                    match save_file(&root_dir, &mfd_name, |file| {
+                        export::write_mfd(&card_dump, file)
+                    }) {
+                        Ok(()) => info!("Written {}", mfd_name.as_str()),
+                        Err(_) => error!("Unable to write {}", mfd_name.as_str()),
+                    }
+
+                    let json_name = export::file_name(&card_dump.uid, "JSN");
+                    match save_file(&root_dir, &json_name, |file| {
+                        export::write_json(&card_dump, file)
+                    }) {
+                        Ok(()) => info!("Written {}", json_name.as_str()),
+                        Err(_) => error!("Unable to write {}", json_name.as_str()),
+                    }
+
+                    // For the Flipper Zero and the Proxmark
+                    let known = |block: usize| match card_dump.blocks[block].error {
+                        None => formats::ALL_KNOWN,
+                        Some(_) => formats::NONE_KNOWN,
+                    };
+                    let nfc_name = export::file_name(&card_dump.uid, "NFC");
+                    match save_file(&root_dir, &nfc_name, |file| {
+                        formats::write_flipper(&card_dump.uid, &card_dump.data, known, file)
+                    }) {
+                        Ok(()) => info!("Written {}", nfc_name.as_str()),
+                        Err(_) => error!("Unable to write {}", nfc_name.as_str()),
+                    }
+
+                    let eml_name = export::file_name(&card_dump.uid, "EML");
+                    match save_file(&root_dir, &eml_name, |file| {
+                        formats::write_eml(&card_dump.data, known, file)
+                    }) {
+                        Ok(()) => info!("Written {}", eml_name.as_str()),
+                        Err(_) => error!("Unable to write {}", eml_name.as_str()),
+                    }
+                }
+                Err(e) => error!("Error dumping card: {:?}", e),
+            }
+
+            let _ = rfid.hlta();
+            let _ = rfid.stop_crypto1();
+            Timer::after_millis(500).await;
+        }
+
+        Timer::after_millis(200).await;
+    }
+}
@@ -0,0 +1,311 @@
+//! Finding every card in the field, ISO/IEC 14443-3 style.
+//!
+//! When several cards answer at once, their UID bits pile up on the air.
+//! Where two cards disagree the reader sees a collision, and the position
+//! of the first one ends up in CollReg. The reader then repeats the SELECT
+//! with the bits known so far plus a guess for the colliding bit; only the
+//! cards that match keep answering. Going down this binary tree one bit at
+//! a time ends at exactly one card, which is selected and halted.
+//!
+//! A halted card only answers WUPA, not REQA. So an inventory wakes every
+//! card once with WUPA, and then goes round with REQA: each round walks the
+//! tree down to one more card and halts it, until nobody answers anymore.
+//!
+//! UIDs longer than 4 bytes take more than one cascade level. The first
+//! levels hand back the cascade tag `88` and three UID bytes, and the SAK
+//! tells whether another level follows.
+//!
+//! The `mfrc522` driver's `select` does the tree walk too, but it needs the
+//! `AtqA` from `reqa`, and `reqa` fails outright when two different kinds
+//! of card answer at once. So this module drives `transceive` itself.
+
+use embedded_hal::spi::SpiDevice;
+use heapless::Vec;
+use mfrc522::{Error, GenericUid, Initialized, Mfrc522, Uid};
+use rfid_common::pcd::{COLL_REG, SharedSpi};
+
+// PICC commands
+const PICC_REQA: u8 = 0x26;
+const PICC_WUPA: u8 = 0x52;
+const PICC_SEL_CL: [u8; 3] = [0x93, 0x95, 0x97];
+const CASCADE_TAG: u8 = 0x88;
+
+// SAK bit: the UID is not complete, go on with the next cascade level
+const SAK_CASCADE: u8 = 1 << 2;
+
+// CollReg bits
+const VALUES_AFTER_COLL: u8 = 1 << 7;
+const COLL_POS_NOT_VALID: u8 = 1 << 5;
+const COLL_POS: u8 = 0x1F;
+
+/// Give up on a level after this many collisions (one per UID bit)
+const MAX_COLLISIONS: usize = 32;
+
+/// CRC_A from ISO/IEC 14443-3, appended to commands sent with `transceive`.
+fn crc_a(data: &[u8]) -> [u8; 2] {
+    let mut crc: u16 = 0x6363;
+    for &byte in data {
+        let mut b = byte ^ (crc as u8);
+        b ^= b << 4;
+        crc = (crc >> 8) ^ ((b as u16) << 8) ^ ((b as u16) << 3) ^ ((b as u16) >> 4);
+    }
+    crc.to_le_bytes()
+}
+
+/// Builds the driver's `Uid` from the bytes and the final SAK.
+pub fn to_uid(bytes: &[u8], sak: u8) -> Result<Uid, &'static str> {
+    let uid = match bytes.len() {
+        4 => Uid::Single(GenericUid::new(bytes.try_into().expect("4 bytes"), sak)),
+        7 => Uid::Double(GenericUid::new(bytes.try_into().expect("7 bytes"), sak)),
+        10 => Uid::Triple(GenericUid::new(bytes.try_into().expect("10 bytes"), sak)),
+        _ => return Err("UID must be 4, 7 or 10 bytes"),
+    };
+    Ok(uid)
+}
+
+/// Sends REQA or WUPA. Returns true if any card answered, even garbled.
+fn wake<E, COMM>(command: u8, rfid: &mut Mfrc522<COMM, Initialized>) -> Result<bool, &'static str>
+where
+    COMM: mfrc522::comm::Interface<Error = E>,
+{
+    match rfid.transceive::<2>(&[command], 7, 0) {
+        Ok(_) => Ok(true),
+        Err(Error::Timeout) => Ok(false),
+        Err(Error::Comm(_)) => Err("SPI error"),
+        // Different ATQAs on top of each other
+        Err(_) => Ok(true),
+    }
+}
+
+/// Copies the received bits in after the `known_bits` we sent.
+fn merge_bits(uid: &mut [u8; 5], known_bits: usize, received: &[u8]) {
+    let mask = 0xFFu8 << (known_bits % 8);
+    let start = known_bits / 8;
+    for (i, &b) in received.iter().enumerate() {
+        let Some(byte) = uid.get_mut(start + i) else {
+            break;
+        };
+        *byte = if i == 0 {
+            (b & mask) | (*byte & !mask)
+        } else {
+            b
+        };
+    }
+}
+
+/// Runs the anticollision loop of one cascade level and selects the card.
+///
+/// Returns the four UID bytes of this level (cascade tag included) and the SAK.
+fn select_level<E, COMM, D>(
+    level: usize,
+    rfid: &mut Mfrc522<COMM, Initialized>,
+    pcd: &mut SharedSpi<'_, D>,
+) -> Result<([u8; 4], u8), &'static str>
+where
+    COMM: mfrc522::comm::Interface<Error = E>,
+    D: SpiDevice,
+{
+    let spi_err = |_| "SPI error";
+
+    // Keep the bits after a collision, so we can read how far the card got
+    let coll = pcd.read_register(COLL_REG).map_err(spi_err)?;
+    pcd.write_register(COLL_REG, coll & !VALUES_AFTER_COLL)
+        .map_err(spi_err)?;
+
+    // Four UID bytes and the BCC
+    let mut uid = [0u8; 5];
+    let mut known_bits: usize = 0;
+
+    let mut collisions = 0;
+    loop {
+        let last_bits = (known_bits % 8) as u8;
+        let tx_len = 2 + known_bits.div_ceil(8);
+
+        let mut tx = [0u8; 7];
+        tx[0] = PICC_SEL_CL[level];
+        // NVB: number of valid bytes (command and NVB included) and bits
+        tx[1] = (((2 + known_bits / 8) as u8) << 4) | last_bits;
+        tx[2..tx_len].copy_from_slice(&uid[..tx_len - 2]);
+
+        match rfid.transceive::<5>(&tx[..tx_len], last_bits, last_bits) {
+            Ok(rx) => {
+                merge_bits(&mut uid, known_bits, &rx.buffer[..rx.valid_bytes]);
+                break;
+            }
+            Err(Error::Collision) => {
+                collisions += 1;
+                if collisions > MAX_COLLISIONS {
+                    return Err("Too many collisions");
+                }
+
+                let coll = pcd.read_register(COLL_REG).map_err(spi_err)?;
+                if coll & COLL_POS_NOT_VALID != 0 {
+                    return Err("Collision position unknown");
+                }
+                let coll_pos = match (coll & COLL_POS) as usize {
+                    0 => 32,
+                    pos => pos,
+                };
+                if coll_pos <= known_bits {
+                    return Err("Anticollision made no progress");
+                }
+
+                let mut received = [0u8; 5];
+                let len = pcd.read_fifo(&mut received).map_err(spi_err)?;
+                merge_bits(&mut uid, known_bits, &received[..len]);
+
+                // Everything before the collision is known now. Take the
+                // branch where the colliding bit is 1, the cards on the
+                // other branch get their turn in a later round.
+                known_bits = coll_pos;
+                uid[(known_bits - 1) / 8] |= 1 << ((known_bits - 1) % 8);
+            }
+            Err(Error::Comm(_)) => return Err("SPI error"),
+            Err(_) => return Err("Card lost"),
+        }
+    }
+
+    if uid[4] != uid[..4].iter().fold(0, |bcc, b| bcc ^ b) {
+        return Err("BCC mismatch");
+    }
+
+    let level_uid: [u8; 4] = uid[..4].try_into().expect("4 bytes");
+    let sak = send_select(level, &level_uid, rfid)?;
+    Ok((level_uid, sak))
+}
+
+/// Sends SELECT with the complete UID bytes of a level, returning the SAK.
+fn send_select<E, COMM>(
+    level: usize,
+    level_uid: &[u8; 4],
+    rfid: &mut Mfrc522<COMM, Initialized>,
+) -> Result<u8, &'static str>
+where
+    COMM: mfrc522::comm::Interface<Error = E>,
+{
+    let mut tx = [0u8; 9];
+    tx[0] = PICC_SEL_CL[level];
+    tx[1] = 0x70;
+    tx[2..6].copy_from_slice(level_uid);
+    tx[6] = level_uid.iter().fold(0, |bcc, b| bcc ^ b);
+    let crc = crc_a(&tx[..7]);
+    tx[7..].copy_from_slice(&crc);
+
+    let rx = rfid
+        .transceive::<3>(&tx, 0, 0)
+        .map_err(|_| "No answer to SELECT")?;
+    if rx.valid_bytes != 3 || rx.buffer[1..3] != crc_a(&rx.buffer[..1]) {
+        return Err("Bad SAK");
+    }
+    Ok(rx.buffer[0])
+}
+
+/// Walks every cascade level of one card. The card is selected afterwards.
+fn select_any<E, COMM, D>(
+    rfid: &mut Mfrc522<COMM, Initialized>,
+    pcd: &mut SharedSpi<'_, D>,
+) -> Result<Uid, &'static str>
+where
+    COMM: mfrc522::comm::Interface<Error = E>,
+    D: SpiDevice,
+{
+    let mut bytes: Vec<u8, 10> = Vec::new();
+    for level in 0..PICC_SEL_CL.len() {
+        let (level_uid, sak) = select_level(level, rfid, pcd)?;
+
+        if sak & SAK_CASCADE == 0 {
+            bytes
+                .extend_from_slice(&level_uid)
+                .map_err(|_| "UID too long")?;
+            return to_uid(&bytes, sak);
+        }
+        if level_uid[0] != CASCADE_TAG {
+            return Err("Missing cascade tag");
+        }
+        bytes
+            .extend_from_slice(&level_uid[1..])
+            .map_err(|_| "UID too long")?;
+    }
+    Err("UID too long")
+}
+
+/// Lists every card in the field, up to `N` of them.
+///
+/// All the cards are halted afterwards; use `select_uid` to talk to one.
+pub fn inventory<const N: usize, E, COMM, D>(
+    rfid: &mut Mfrc522<COMM, Initialized>,
+    pcd: &mut SharedSpi<'_, D>,
+) -> Result<Vec<Uid, N>, &'static str>
+where
+    COMM: mfrc522::comm::Interface<Error = E>,
+    D: SpiDevice,
+{
+    let mut cards: Vec<Uid, N> = Vec::new();
+
+    // Rounds where a card moved out mid-way don't find anything,
+    // leave some room for those
+    let mut command = PICC_WUPA;
+    for _ in 0..N + 4 {
+        if !wake(command, rfid)? {
+            break;
+        }
+        command = PICC_REQA;
+
+        let Ok(uid) = select_any(rfid, pcd) else {
+            continue;
+        };
+        let _ = rfid.hlta();
+
+        if cards.iter().any(|c| c.as_bytes() == uid.as_bytes()) {
+            continue;
+        }
+        if cards.push(uid).is_err() {
+            break;
+        }
+    }
+    Ok(cards)
+}
+
+/// Wakes the field up and selects the card with this UID.
+///
+/// Cards with a different UID drop back to idle when they see the SELECT,
+/// so afterwards the chosen card is the only one listening.
+pub fn select_uid<E, COMM>(
+    uid: &[u8],
+    rfid: &mut Mfrc522<COMM, Initialized>,
+) -> Result<Uid, &'static str>
+where
+    COMM: mfrc522::comm::Interface<Error = E>,
+{
+    // One entry per cascade level
+    let mut levels: Vec<[u8; 4], 3> = Vec::new();
+    match uid.len() {
+        4 => {
+            let _ = levels.push(uid.try_into().expect("4 bytes"));
+        }
+        7 => {
+            let _ = levels.push([CASCADE_TAG, uid[0], uid[1], uid[2]]);
+            let _ = levels.push(uid[3..].try_into().expect("4 bytes"));
+        }
+        10 => {
+            let _ = levels.push([CASCADE_TAG, uid[0], uid[1], uid[2]]);
+            let _ = levels.push([CASCADE_TAG, uid[3], uid[4], uid[5]]);
+            let _ = levels.push(uid[6..].try_into().expect("4 bytes"));
+        }
+        _ => return Err("UID must be 4, 7 or 10 bytes"),
+    }
+
+    if !wake(PICC_WUPA, rfid)? {
+        return Err("No card in the field");
+    }
+
+    let mut sak = 0;
+    for (level, level_uid) in levels.iter().enumerate() {
+        sak = send_select(level, level_uid, rfid)?;
+        let last = level + 1 == levels.len();
+        if (sak & SAK_CASCADE == 0) != last {
+            return Err("SAK does not match the UID length");
+        }
+    }
+    to_uid(uid, sak)
+}
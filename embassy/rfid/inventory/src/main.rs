@@ -0,0 +1,103 @@
+#![no_std]
+#![no_main]
+
+pub mod anticollision;
+
+use embassy_executor::Spawner;
+use embassy_time::Timer;
+
+// defmt Logging
+use defmt::{info, warn};
+use defmt_rtt as _;
+
+use panic_probe as _;
+
+// For SPI
+use embassy_rp::spi;
+use embassy_rp::spi::Spi;
+use embassy_time::Delay;
+use embedded_hal_bus::spi::ExclusiveDevice;
+
+// For CS Pin
+use embassy_rp::gpio::{Level, Output};
+
+// Driver for the MFRC522
+use mfrc522::{Mfrc522, comm::blocking::spi::SpiInterface};
+
+use core::cell::RefCell;
+
+use rfid_common::pcd::SharedSpi;
+
+/// Most cards stacked on one reader we list
+const MAX_CARDS: usize = 8;
+
+const DEFAULT_KEY: [u8; 6] = [0xFF; 6];
+
+#[embassy_executor::main]
+async fn main(_spawner: Spawner) {
+    let p = embassy_rp::init(Default::default());
+
+    info!("Initializing the program");
+
+    let miso = p.PIN_0;
+    let cs_pin = Output::new(p.PIN_1, Level::High);
+    let clk = p.PIN_2;
+    let mosi = p.PIN_3;
+
+    let mut config = spi::Config::default();
+    config.frequency = 1_000_000;
+
+    let spi_bus = Spi::new_blocking(p.SPI0, clk, mosi, miso, config);
+    let spi = ExclusiveDevice::new(spi_bus, cs_pin, Delay).expect("Failed to get exclusive device");
+
+    // Shared between the driver and our collision register reads
+    let spi = RefCell::new(spi);
+    let mut pcd = SharedSpi::new(&spi);
+
+    let itf = SpiInterface::new(pcd);
+    let mut rfid = Mfrc522::new(itf)
+        .init()
+        .expect("failed to initialize the RFID reader");
+
+    loop {
+        let cards = match anticollision::inventory::<MAX_CARDS, _, _, _>(&mut rfid, &mut pcd) {
+            Ok(cards) => cards,
+            Err(e) => {
+                warn!("Inventory failed: {}", e);
+                Timer::after_secs(1).await;
+                continue;
+            }
+        };
+
+        if !cards.is_empty() {
+            info!("{} card(s) in the field", cards.len());
+        }
+        for (i, card) in cards.iter().enumerate() {
+            info!("Card {}: UID {:02x}", i, card.as_bytes());
+        }
+
+        // Talk to each card on its own: select it by UID and read block 1
+        for card in cards.iter() {
+            let uid = match anticollision::select_uid(card.as_bytes(), &mut rfid) {
+                Ok(uid) => uid,
+                Err(e) => {
+                    warn!("Can't select {:02x}: {}", card.as_bytes(), e);
+                    continue;
+                }
+            };
+
+            match rfid.mf_authenticate(&uid, 0, &DEFAULT_KEY) {
+                Ok(()) => match rfid.mf_read(1) {
+                    Ok(data) => info!("{:02x} block 1: {:02x}", uid.as_bytes(), data),
+                    Err(_) => warn!("{:02x}: read failed", uid.as_bytes()),
+                },
+                Err(_) => warn!("{:02x}: default key refused", uid.as_bytes()),
+            }
+
+            let _ = rfid.hlta();
+            let _ = rfid.stop_crypto1();
+        }
+
+        Timer::after_secs(1).await;
+    }
+}
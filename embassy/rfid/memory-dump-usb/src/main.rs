@@ -28,6 +28,21 @@ use heapless::String;
 // Logger
 use log::error;
 
+use core::cell::RefCell;
+use embedded_hal::spi::SpiDevice;
+
+use rfid_common::keyring::{self, KeyRing, SectorKey};
+use rfid_common::layout::{self, Layout};
+use rfid_common::pcd::{PcdReader, SharedSpi};
+use rfid_common::reader::CardReader;
+use rfid_common::retry;
+
+// Keys of our own, tried after the default ones
+const USER_KEYS: [mfrc522::MifareKey; 2] = [
+    [0x52, 0x75, 0x73, 0x74, 0x65, 0x64], // "Rusted"
+    [0x46, 0x65, 0x72, 0x72, 0x69, 0x73], // "Ferris"
+];
+
 embassy_rp::bind_interrupts!(struct Irqs {
     USBCTRL_IRQ => usb::InterruptHandler<USB>;
 });
@@ -39,25 +54,33 @@ async fn logger_task(usb: embassy_rp::Peri<'static, embassy_rp::peripherals::USB
     embassy_usb_logger::run!(8192, log::LevelFilter::Info, driver);
 }
 
-fn read_sector<E, COMM>(
+fn read_sector<R: CardReader, const N: usize>(
     uid: &mfrc522::Uid,
     sector: u8,
-    rfid: &mut Mfrc522<COMM, mfrc522::Initialized>,
-) -> Result<(), &'static str>
-where
-    COMM: mfrc522::comm::Interface<Error = E>,
-{
-    const AUTH_KEY: [u8; 6] = [0xFF; 6];
-
+    keyring: &KeyRing<N>,
+    rfid: &mut R,
+) -> Result<Option<SectorKey>, &'static str> {
     let mut buff: String<64> = String::new();
 
-    let block_offset = sector * 4;
-    rfid.mf_authenticate(uid, block_offset, &AUTH_KEY)
-        .map_err(|_| "Auth failed")?;
-
-    for abs_block in block_offset..block_offset + 4 {
-        let rel_block = abs_block - block_offset;
-        let data = rfid.mf_read(abs_block).map_err(|_| "Read failed")?;
+    let block_offset = layout::first_block(sector);
+    let block_count = layout::blocks_in_sector(sector);
+    let trailer_block = layout::trailer_block(sector);
+    let Some(sector_key) = keyring::find_sector_key(uid, trailer_block, keyring, rfid)? else {
+        error!("No key opened sector {}", sector);
+        return Ok(None);
+    };
+
+    for rel_block in 0..block_count {
+        let abs_block = block_offset + rel_block;
+        let data = match rfid.mf_read(abs_block) {
+            Ok(data) => data,
+            Err(_) => {
+                error!("Error reading block {}", abs_block);
+                // A failed read drops the authentication
+                retry::reselect(uid, rfid).map_err(|_| "Card lost")?;
+                return Ok(None);
+            }
+        };
 
         // Printing the block data
         for &d in data.iter() {
@@ -65,7 +88,7 @@ where
         }
 
         // Printing block type
-        let block_type = get_block_type(sector, rel_block);
+        let block_type = layout::get_block_type(sector, rel_block);
 
         log::info!(
             "BLOCK {} (REL: {}) | {} | {}",
@@ -78,34 +101,105 @@ where
         buff.clear();
     }
     log::info!("");
-    Ok(())
+    Ok(Some(sector_key))
 }
 
-fn dump_memory<E, COMM>(
+fn dump_classic<R: CardReader, const N: usize>(
     uid: &mfrc522::Uid,
-    rfid: &mut Mfrc522<COMM, mfrc522::Initialized>,
-) -> Result<(), &'static str>
-where
-    COMM: mfrc522::comm::Interface<Error = E>,
-{
+    sectors: u8,
+    keyring: &KeyRing<N>,
+    rfid: &mut R,
+) -> Result<(), &'static str> {
     let mut buff: String<64> = String::new();
-    for sector in 0..16 {
+    // Enough for the 40 sectors of a Classic 4K
+    let mut sector_keys: [Option<SectorKey>; 40] = [None; 40];
+
+    for sector in 0..sectors {
         // Printing the Sector number
         write!(buff, "-----------SECTOR {}-----------", sector)
             .expect("failed to write into heapless buff");
         log::info!("{}", buff);
         buff.clear();
 
-        read_sector(uid, sector, rfid)?;
+        // Keep going, so a partially protected card can still be dumped
+        sector_keys[sector as usize] = read_sector(uid, sector, keyring, rfid)?;
+    }
+
+    log::info!("-----------KEYS-----------");
+    for (sector, sector_key) in sector_keys.iter().take(sectors as usize).enumerate() {
+        match sector_key {
+            Some(sector_key) => {
+                for &d in sector_key.key.iter() {
+                    write!(buff, "{:02x} ", d).expect("failed to write byte into buffer");
+                }
+                log::info!(
+                    "SECTOR {} | KEY {} | {}",
+                    sector,
+                    sector_key.key_type.as_str(),
+                    buff
+                );
+                buff.clear();
+            }
+            None => log::info!("SECTOR {} | NO KEY", sector),
+        }
+    }
+    Ok(())
+}
+
+/// Ultralight and NTAG pages need no authentication. A read returns four
+/// pages at once, so we only print the ones that exist.
+fn dump_pages<E, COMM>(
+    layout: &Layout,
+    pages: u8,
+    rfid: &mut Mfrc522<COMM, mfrc522::Initialized>,
+) -> Result<(), &'static str>
+where
+    COMM: mfrc522::comm::Interface<Error = E>,
+{
+    let mut buff: String<64> = String::new();
+
+    for first_page in (0..pages).step_by(4) {
+        let data = rfid.mf_read(first_page).map_err(|_| "Read failed")?;
+
+        for (i, page_data) in data.chunks(4).enumerate() {
+            let page = first_page + i as u8;
+            if page >= pages {
+                break;
+            }
+
+            for &d in page_data.iter() {
+                write!(buff, "{:02x} ", d).expect("failed to write byte into buffer");
+            }
+            log::info!(
+                "PAGE {} | {} | {}",
+                page,
+                buff,
+                layout::get_page_type(layout, page)
+            );
+            buff.clear();
+        }
     }
     Ok(())
 }
 
-fn get_block_type(sector: u8, rel_block: u8) -> &'static str {
-    match rel_block {
-        0 if sector == 0 => "MFD",
-        3 => "TRAILER",
-        _ => "DATA",
+fn dump_memory<COMM, D, const N: usize>(
+    uid: &mfrc522::Uid,
+    keyring: &KeyRing<N>,
+    rfid: &mut Mfrc522<COMM, mfrc522::Initialized>,
+    pcd: &mut SharedSpi<'_, D>,
+) -> Result<(), &'static str>
+where
+    COMM: mfrc522::comm::Interface<Error = D::Error>,
+    D: SpiDevice,
+{
+    let layout = layout::detect(uid, rfid)?;
+    log::info!("-----------{}-----------", layout.name());
+
+    match layout {
+        Layout::Classic { sectors, .. } => {
+            dump_classic(uid, sectors, keyring, &mut PcdReader::new(rfid, *pcd))
+        }
+        Layout::Ultralight { pages, .. } => dump_pages(&layout, pages, rfid),
     }
 }
 
@@ -126,16 +220,26 @@ async fn main(spawner: Spawner) {
 
     let spi_bus = Spi::new_blocking(p.SPI0, clk, mosi, miso, config);
     let spi = ExclusiveDevice::new(spi_bus, cs_pin, Delay).expect("Failed to get exclusive device");
-    let itf = SpiInterface::new(spi);
+
+    // Shared between the driver and our own key B authentication
+    let spi = RefCell::new(spi);
+    let mut pcd = SharedSpi::new(&spi);
+
+    let itf = SpiInterface::new(pcd);
     let mut rfid = Mfrc522::new(itf)
         .init()
         .expect("failed to initialize the RFID reader");
 
+    let mut keyring: KeyRing<16> = KeyRing::with_defaults();
+    for key in USER_KEYS {
+        keyring.add(key).expect("key ring too small");
+    }
+
     loop {
         if let Ok(atqa) = rfid.reqa() {
             if let Ok(uid) = rfid.select(&atqa) {
-                if let Err(e) = dump_memory(&uid, &mut rfid) {
-                    error!("Error reading sector: {:?}", e);
+                if let Err(e) = dump_memory(&uid, &keyring, &mut rfid, &mut pcd) {
+                    error!("Error dumping memory: {:?}", e);
                 }
                 let _ = rfid.hlta();
                 let _ = rfid.stop_crypto1();
@@ -0,0 +1,222 @@
+//! Comparing two dumps of the same card.
+//!
+//! To see what a vending machine or a ticket gate wrote to a card, dump it
+//! before and after and look at the difference. Only the blocks that
+//! changed are printed, old line above new line, with the changed bytes in
+//! brackets:
+//!
+//! ```text
+//! BLOCK 8 | DATA
+//!   -  64  00  00  00  9b  ff  ff  ff  64  00  00  00  08  f7  08  f7
+//!   + [5f] 00  00  00 [a0] ff  ff  ff [5f] 00  00  00  08  f7  08  f7
+//!   VALUE 100 -> 95 (address 8)
+//! ```
+//!
+//! Value blocks and sector trailers are decoded as well, since a changed
+//! balance or changed access bits are what we usually look for.
+
+use core::fmt::Write;
+
+use defmt::println;
+use heapless::{String, Vec};
+use rfid_common::access::AccessConditions;
+use rfid_common::keyring::SectorKey;
+use rfid_common::layout::{self, Layout};
+use rfid_common::value::ValueBlock;
+
+/// A Classic 4K has 256 blocks; an NTAG216 231 pages, 4 to a row
+pub const MAX_ROWS: usize = 256;
+
+/// A snapshot of everything we could read from a card.
+///
+/// Classic cards have one row per block. Ultralight and NTAG pages are
+/// grouped four to a row, the way a READ returns them.
+pub struct Dump {
+    pub uid: Vec<u8, 10>,
+    pub layout: Layout,
+    /// `None` for blocks in sectors none of our keys opened
+    pub rows: [Option<[u8; 16]>; MAX_ROWS],
+    /// The key that opened each Classic sector
+    pub keys: [Option<SectorKey>; 40],
+}
+
+impl Dump {
+    pub fn new(uid: &[u8], layout: Layout) -> Self {
+        Self {
+            uid: Vec::from_slice(uid).unwrap_or_default(),
+            layout,
+            rows: [None; MAX_ROWS],
+            keys: [None; 40],
+        }
+    }
+
+    pub fn row_count(&self) -> usize {
+        match self.layout {
+            Layout::Classic { sectors, .. } => (0..sectors)
+                .map(|sector| layout::blocks_in_sector(sector) as usize)
+                .sum(),
+            Layout::Ultralight { pages, .. } => (pages as usize).div_ceil(4),
+        }
+    }
+}
+
+/// The last dump of each of the most recently seen cards.
+pub struct History<const N: usize> {
+    dumps: Vec<Dump, N>,
+    /// Slot the next new card replaces once the history is full
+    next: usize,
+}
+
+impl<const N: usize> History<N> {
+    pub const fn new() -> Self {
+        Self {
+            dumps: Vec::new(),
+            next: 0,
+        }
+    }
+
+    pub fn get(&self, uid: &[u8]) -> Option<&Dump> {
+        self.dumps.iter().find(|dump| dump.uid == uid)
+    }
+
+    /// Keeps `dump` as the latest one of its card, forgetting the oldest card if needed.
+    pub fn store(&mut self, dump: Dump) {
+        if let Some(slot) = self.dumps.iter_mut().find(|d| d.uid == dump.uid) {
+            *slot = dump;
+            return;
+        }
+        if let Err(dump) = self.dumps.push(dump) {
+            self.dumps[self.next] = dump;
+            self.next = (self.next + 1) % N;
+        }
+    }
+}
+
+impl<const N: usize> Default for History<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// One row as hex, with the bytes that differ from `other` in brackets.
+fn highlight(row: &[u8; 16], other: &[u8; 16]) -> String<64> {
+    let mut line = String::new();
+    for (byte, other) in row.iter().zip(other.iter()) {
+        let _ = if byte == other {
+            write!(line, " {:02x} ", byte)
+        } else {
+            write!(line, "[{:02x}]", byte)
+        };
+    }
+    line
+}
+
+/// The access bits as `C1C2C3` for the three data blocks and the trailer.
+fn access_summary(trailer: &[u8; 16]) -> String<32> {
+    let mut text = String::new();
+    match AccessConditions::from_trailer(trailer) {
+        Ok(conditions) => {
+            for bits in conditions.data.iter().chain([&conditions.trailer]) {
+                let _ = write!(text, "{:03b} ", bits.value());
+            }
+        }
+        Err(_) => {
+            let _ = text.push_str("invalid");
+        }
+    }
+    text
+}
+
+fn print_interpretation(trailer: bool, old: &[u8; 16], new: &[u8; 16]) {
+    if trailer {
+        if old[6..10] != new[6..10] {
+            println!(
+                "  ACCESS {} -> {}",
+                access_summary(old).as_str(),
+                access_summary(new).as_str()
+            );
+        }
+        if old[10..] != new[10..] {
+            println!("  KEY B {:02x} -> {:02x}", &old[10..], &new[10..]);
+        }
+        return;
+    }
+
+    match (ValueBlock::decode(old).ok(), ValueBlock::decode(new).ok()) {
+        (Some(old), Some(new)) => {
+            if old.address == new.address {
+                println!(
+                    "  VALUE {} -> {} (address {})",
+                    old.value, new.value, old.address
+                );
+            } else {
+                println!(
+                    "  VALUE {} -> {} (address {} -> {})",
+                    old.value, new.value, old.address, new.address
+                );
+            }
+        }
+        (None, Some(new)) => {
+            println!(
+                "  now a VALUE block: {} (address {})",
+                new.value, new.address
+            )
+        }
+        (Some(_), None) => println!("  no longer a valid VALUE block"),
+        (None, None) => {}
+    }
+}
+
+/// Prints the rows that differ between two dumps of the same card.
+///
+/// Returns the number of changed rows. Dumps with different layouts can't
+/// be compared row by row, so nothing is printed for those.
+pub fn print_diff(old: &Dump, new: &Dump) -> Result<usize, &'static str> {
+    if old.layout != new.layout {
+        return Err("Card layout changed");
+    }
+
+    // The label of a row, and whether it is a sector trailer
+    let label = |row: usize| -> (String<24>, bool) {
+        let mut label = String::new();
+        match new.layout {
+            Layout::Classic { .. } => {
+                let sector = layout::sector_of(row as u8);
+                let rel_block = row as u8 - layout::first_block(sector);
+                let block_type = layout::get_block_type(sector, rel_block);
+                let _ = write!(label, "BLOCK {} | {}", row, block_type);
+                (label, block_type == "TRAILER")
+            }
+            Layout::Ultralight { .. } => {
+                let _ = write!(label, "PAGES {}-{}", row * 4, row * 4 + 3);
+                (label, false)
+            }
+        }
+    };
+
+    let mut changed = 0;
+    for row in 0..new.row_count() {
+        match (&old.rows[row], &new.rows[row]) {
+            (Some(old_row), Some(new_row)) if old_row != new_row => {
+                changed += 1;
+                let (label, trailer) = label(row);
+                println!("{}", label.as_str());
+                println!("  - {}", highlight(old_row, new_row).as_str());
+                println!("  + {}", highlight(new_row, old_row).as_str());
+                if matches!(new.layout, Layout::Classic { .. }) {
+                    print_interpretation(trailer, old_row, new_row);
+                }
+            }
+            (Some(_), None) => {
+                changed += 1;
+                println!("{} | no longer readable", label(row).0.as_str());
+            }
+            (None, Some(_)) => {
+                changed += 1;
+                println!("{} | readable now", label(row).0.as_str());
+            }
+            _ => {}
+        }
+    }
+    Ok(changed)
+}
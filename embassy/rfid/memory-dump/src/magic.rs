@@ -0,0 +1,212 @@
+//! "Magic" Classic cards, whose block 0 (and so the UID) can be rewritten.
+//!
+//! On a genuine card block 0 is written at the factory and locked. Two
+//! kinds of clones let us change it:
+//!
+//! - Gen1a answers a backdoor after HLTA: `0x40` sent as 7 bits, then
+//!   `0x43`. Each is acknowledged with a 4 bit `0xA`, after which every
+//!   block reads and writes without authentication.
+//! - Gen2 (CUID) has no backdoor, block 0 simply accepts a normal write
+//!   once sector 0 is authenticated.
+//!
+//! The only way to spot a Gen2 is to write block 0, so the probe writes
+//! back what the card already holds. It still counts as a write and only
+//! runs when writes are allowed.
+//!
+//! Block 0 of a 4 byte UID card starts with the UID and its BCC (the XOR
+//! of the UID bytes). A card whose BCC doesn't match no longer selects, so
+//! the BCC is always recomputed and never taken from the caller.
+
+use embedded_hal::spi::SpiDevice;
+use mfrc522::{Initialized, Mfrc522, Uid};
+use rfid_common::keyring::{self, KeyRing};
+use rfid_common::pcd::{PcdReader, SharedSpi};
+use rfid_common::reader::CardReader;
+use rfid_common::retry;
+
+const BACKDOOR_UNLOCK_1: u8 = 0x40;
+const BACKDOOR_UNLOCK_2: u8 = 0x43;
+const ACK: u8 = 0x0A;
+
+/// Trailer of sector 0, for authenticating block 0
+const SECTOR_0_TRAILER: u8 = 3;
+
+/// First byte of a UID that continues in the next cascade level
+const CASCADE_TAG: u8 = 0x88;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Magic {
+    Gen1a,
+    Gen2,
+}
+
+impl Magic {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Magic::Gen1a => "Gen1a (backdoor)",
+            Magic::Gen2 => "Gen2 (writable block 0)",
+        }
+    }
+}
+
+/// Writing block 0 can leave a card that no reader selects any more, so
+/// nothing is written unless asked for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WriteOptions {
+    /// Opt-in for any write to block 0, the Gen2 probe included
+    pub allow_write: bool,
+    /// Work out the new block 0 but don't write it
+    pub dry_run: bool,
+}
+
+impl WriteOptions {
+    pub fn writes_enabled(&self) -> bool {
+        self.allow_write && !self.dry_run
+    }
+}
+
+pub fn bcc(uid: &[u8; 4]) -> u8 {
+    uid.iter().fold(0, |bcc, byte| bcc ^ byte)
+}
+
+/// Block 0 with a new UID and BCC, keeping SAK, ATQA and the manufacturer data.
+pub fn block0_with_uid(block0: &[u8; 16], uid: &[u8; 4]) -> Result<[u8; 16], &'static str> {
+    if uid[0] == CASCADE_TAG {
+        return Err("A 4 byte UID can't start with the cascade tag 0x88");
+    }
+    let mut block = *block0;
+    block[..4].copy_from_slice(uid);
+    block[4] = bcc(uid);
+    Ok(block)
+}
+
+fn is_ack(answer: &mfrc522::FifoData<1>) -> bool {
+    answer.valid_bytes == 1 && answer.valid_bits == 4 && answer.buffer[0] & 0x0F == ACK
+}
+
+/// Halts the card and opens the Gen1a backdoor.
+///
+/// A genuine card stays silent and is left halted.
+fn unlock_gen1a<E, COMM>(rfid: &mut Mfrc522<COMM, Initialized>) -> Result<(), &'static str>
+where
+    COMM: mfrc522::comm::Interface<Error = E>,
+{
+    let _ = rfid.stop_crypto1();
+    let _ = rfid.hlta();
+
+    let answer = rfid
+        .transceive::<1>(&[BACKDOOR_UNLOCK_1], 7, 0)
+        .map_err(|_| "No answer to the backdoor")?;
+    if !is_ack(&answer) {
+        return Err("Backdoor refused");
+    }
+    let answer = rfid
+        .transceive::<1>(&[BACKDOOR_UNLOCK_2], 0, 0)
+        .map_err(|_| "No answer to the backdoor")?;
+    if !is_ack(&answer) {
+        return Err("Backdoor refused");
+    }
+    Ok(())
+}
+
+/// Halts the card and selects it again, whatever UID it now answers with.
+fn wake<E, COMM>(rfid: &mut Mfrc522<COMM, Initialized>) -> Result<Uid, &'static str>
+where
+    COMM: mfrc522::comm::Interface<Error = E>,
+{
+    let _ = rfid.stop_crypto1();
+    let _ = rfid.hlta();
+    let atqa = rfid.wupa().map_err(|_| "Card lost")?;
+    rfid.select(&atqa).map_err(|_| "Card lost")
+}
+
+/// Writes block 0 back to the card unchanged. Only a Gen2 accepts it.
+fn probe_gen2<R: CardReader, const N: usize>(
+    uid: &Uid,
+    keyring: &KeyRing<N>,
+    rfid: &mut R,
+) -> Result<bool, &'static str> {
+    keyring::find_sector_key(uid, SECTOR_0_TRAILER, keyring, rfid)?
+        .ok_or("No key opened sector 0")?;
+    let block0 = rfid.mf_read(0).map_err(|_| "Read failed")?;
+    let writable = rfid.mf_write(0, block0).is_ok();
+    retry::reselect(uid, rfid).map_err(|_| "Card lost")?;
+    Ok(writable)
+}
+
+/// Finds out whether the selected card is a magic card.
+///
+/// Gen2 is only probed when `options` allow writes. The card is selected
+/// again afterwards.
+pub fn detect<COMM, D, const N: usize>(
+    uid: &Uid,
+    keyring: &KeyRing<N>,
+    options: &WriteOptions,
+    rfid: &mut Mfrc522<COMM, Initialized>,
+    pcd: &mut SharedSpi<'_, D>,
+) -> Result<Option<Magic>, &'static str>
+where
+    COMM: mfrc522::comm::Interface<Error = D::Error>,
+    D: SpiDevice,
+{
+    let gen1a = unlock_gen1a(rfid).is_ok();
+    retry::reselect(uid, rfid).map_err(|_| "Card lost")?;
+    if gen1a {
+        return Ok(Some(Magic::Gen1a));
+    }
+
+    if options.writes_enabled() && probe_gen2(uid, keyring, &mut PcdReader::new(rfid, *pcd))? {
+        return Ok(Some(Magic::Gen2));
+    }
+    Ok(None)
+}
+
+/// Gives a magic card a new 4 byte UID, recomputing the BCC.
+///
+/// Everything in block 0 but the UID and the BCC is kept. Returns the new
+/// block 0, which in a dry run is only worked out and not written.
+pub fn write_uid<COMM, D, const N: usize>(
+    uid: &Uid,
+    magic: Option<Magic>,
+    new_uid: &[u8; 4],
+    keyring: &KeyRing<N>,
+    options: &WriteOptions,
+    rfid: &mut Mfrc522<COMM, Initialized>,
+    pcd: &mut SharedSpi<'_, D>,
+) -> Result<[u8; 16], &'static str>
+where
+    COMM: mfrc522::comm::Interface<Error = D::Error>,
+    D: SpiDevice,
+{
+    if uid.as_bytes().len() != 4 {
+        return Err("Only 4 byte UIDs can be rewritten");
+    }
+    if !options.dry_run && !options.allow_write {
+        return Err("Block 0 writes are not enabled");
+    }
+    if !options.dry_run && magic.is_none() {
+        return Err("Not a magic card, block 0 is read-only");
+    }
+
+    match magic {
+        Some(Magic::Gen1a) => unlock_gen1a(rfid)?,
+        _ => {
+            let mut reader = PcdReader::new(rfid, *pcd);
+            keyring::find_sector_key(uid, SECTOR_0_TRAILER, keyring, &mut reader)?
+                .ok_or("No key opened sector 0")?;
+        }
+    }
+    let block0 = rfid.mf_read(0).map_err(|_| "Read failed")?;
+    let new_block0 = block0_with_uid(&block0, new_uid)?;
+
+    if options.dry_run {
+        retry::reselect(uid, rfid).map_err(|_| "Card lost")?;
+        return Ok(new_block0);
+    }
+    rfid.mf_write(0, new_block0).map_err(|_| "Write failed")?;
+
+    if wake(rfid)?.as_bytes() != new_uid {
+        return Err("The UID did not change");
+    }
+    Ok(new_block0)
+}
@@ -1,6 +1,9 @@
 #![no_std]
 #![no_main]
 
+pub mod diff;
+pub mod magic;
+
 use embassy_executor::Spawner;
 use embassy_time::Timer;
 
@@ -29,73 +32,253 @@ use heapless::String;
 // Logger
 use defmt::error;
 
-fn read_sector<E, COMM>(
+use core::cell::RefCell;
+use embedded_hal::spi::SpiDevice;
+
+use rfid_common::keyring::{self, KeyRing, SectorKey};
+use rfid_common::layout::{self, Layout};
+use rfid_common::pcd::{PcdReader, SharedSpi};
+use rfid_common::reader::CardReader;
+use rfid_common::retry;
+
+use crate::diff::{Dump, History};
+use crate::magic::WriteOptions;
+
+/// Cards whose last dump we keep to diff against. Each one takes about 4.5 KiB.
+const HISTORY_SIZE: usize = 4;
+
+/// UID to give every magic card we see, for test benches that need
+/// repeatable UIDs. `None` leaves block 0 alone.
+const NEW_UID: Option<[u8; 4]> = Some([0x12, 0x34, 0x56, 0x78]);
+
+/// Any write to block 0, the Gen2 probe included, needs `allow_write`.
+/// Check the dry run output before turning it on.
+const MAGIC_OPTIONS: WriteOptions = WriteOptions {
+    allow_write: false,
+    dry_run: true,
+};
+
+// Keys of our own, tried after the default ones
+const USER_KEYS: [mfrc522::MifareKey; 2] = [
+    [0x52, 0x75, 0x73, 0x74, 0x65, 0x64], // "Rusted"
+    [0x46, 0x65, 0x72, 0x72, 0x69, 0x73], // "Ferris"
+];
+
+fn print_row(buff: &mut String<64>, data: &[u8]) {
+    for &d in data.iter() {
+        write!(buff, "{:02x} ", d).expect("failed to write byte into buffer");
+    }
+}
+
+fn read_sector<R: CardReader, const N: usize>(
     uid: &mfrc522::Uid,
     sector: u8,
+    keyring: &KeyRing<N>,
+    dump: &mut Dump,
+    rfid: &mut R,
+) -> Result<Option<SectorKey>, &'static str> {
+    let block_offset = layout::first_block(sector);
+    let block_count = layout::blocks_in_sector(sector);
+    let trailer_block = layout::trailer_block(sector);
+    let Some(sector_key) = keyring::find_sector_key(uid, trailer_block, keyring, rfid)? else {
+        error!("No key opened sector {}", sector);
+        return Ok(None);
+    };
+
+    for rel_block in 0..block_count {
+        let abs_block = block_offset + rel_block;
+        match rfid.mf_read(abs_block) {
+            Ok(data) => dump.rows[abs_block as usize] = Some(data),
+            Err(_) => {
+                error!("Error reading block {}", abs_block);
+                // A failed read drops the authentication
+                retry::reselect(uid, rfid).map_err(|_| "Card lost")?;
+                return Ok(None);
+            }
+        }
+    }
+    Ok(Some(sector_key))
+}
+
+fn read_classic<R: CardReader, const N: usize>(
+    uid: &mfrc522::Uid,
+    sectors: u8,
+    keyring: &KeyRing<N>,
+    dump: &mut Dump,
+    rfid: &mut R,
+) -> Result<(), &'static str> {
+    // Keep going, so a partially protected card can still be dumped
+    for sector in 0..sectors {
+        dump.keys[sector as usize] = read_sector(uid, sector, keyring, dump, rfid)?;
+    }
+    Ok(())
+}
+
+/// Ultralight and NTAG pages need no authentication. A read returns four
+/// pages at once, so we only keep the ones that exist.
+fn read_pages<E, COMM>(
+    pages: u8,
+    dump: &mut Dump,
     rfid: &mut Mfrc522<COMM, mfrc522::Initialized>,
 ) -> Result<(), &'static str>
 where
     COMM: mfrc522::comm::Interface<Error = E>,
 {
-    const AUTH_KEY: [u8; 6] = [0xFF; 6];
+    for first_page in (0..pages).step_by(4) {
+        let mut data = rfid.mf_read(first_page).map_err(|_| "Read failed")?;
+
+        // Reads past the end wrap around to page 0
+        let valid = (pages - first_page).min(4) as usize * 4;
+        data[valid..].fill(0);
+        dump.rows[first_page as usize / 4] = Some(data);
+    }
+    Ok(())
+}
 
+fn dump_memory<COMM, D, const N: usize>(
+    uid: &mfrc522::Uid,
+    keyring: &KeyRing<N>,
+    rfid: &mut Mfrc522<COMM, mfrc522::Initialized>,
+    pcd: &mut SharedSpi<'_, D>,
+) -> Result<Dump, &'static str>
+where
+    COMM: mfrc522::comm::Interface<Error = D::Error>,
+    D: SpiDevice,
+{
+    let layout = layout::detect(uid, rfid)?;
+    let mut dump = Dump::new(uid.as_bytes(), layout);
+
+    match layout {
+        Layout::Classic { sectors, .. } => read_classic(
+            uid,
+            sectors,
+            keyring,
+            &mut dump,
+            &mut PcdReader::new(rfid, *pcd),
+        )?,
+        Layout::Ultralight { pages, .. } => read_pages(pages, &mut dump, rfid)?,
+    }
+    Ok(dump)
+}
+
+fn print_classic(dump: &Dump, sectors: u8) {
     let mut buff: String<64> = String::new();
 
-    let block_offset = sector * 4;
-    rfid.mf_authenticate(uid, block_offset, &AUTH_KEY)
-        .map_err(|_| "Auth failed")?;
+    for sector in 0..sectors {
+        // Printing the Sector number
+        write!(buff, "-----------SECTOR {}-----------", sector)
+            .expect("failed to write into heapless buff");
+        defmt::println!("{}", buff);
+        buff.clear();
+
+        let block_offset = layout::first_block(sector);
+        for rel_block in 0..layout::blocks_in_sector(sector) {
+            let abs_block = block_offset + rel_block;
+            let Some(data) = dump.rows[abs_block as usize] else {
+                continue;
+            };
+
+            // Printing the block data
+            print_row(&mut buff, &data);
 
-    for abs_block in block_offset..block_offset + 4 {
-        let rel_block = abs_block - block_offset;
-        let data = rfid.mf_read(abs_block).map_err(|_| "Read failed")?;
+            // Printing block type
+            let block_type = layout::get_block_type(sector, rel_block);
 
-        // Printing the block data
-        for &d in data.iter() {
-            write!(buff, "{:02x} ", d).expect("failed to write byte into buffer");
+            defmt::println!(
+                "BLOCK {} (REL: {}) | {} | {}",
+                abs_block,
+                rel_block,
+                buff,
+                block_type
+            );
+
+            buff.clear();
         }
+        defmt::println!("");
+    }
 
-        // Printing block type
-        let block_type = get_block_type(sector, rel_block);
+    defmt::println!("-----------KEYS-----------");
+    for (sector, sector_key) in dump.keys.iter().take(sectors as usize).enumerate() {
+        match sector_key {
+            Some(sector_key) => {
+                print_row(&mut buff, &sector_key.key);
+                defmt::println!(
+                    "SECTOR {} | KEY {} | {}",
+                    sector,
+                    sector_key.key_type.as_str(),
+                    buff
+                );
+                buff.clear();
+            }
+            None => defmt::println!("SECTOR {} | NO KEY", sector),
+        }
+    }
+}
 
+fn print_pages(dump: &Dump, pages: u8) {
+    let mut buff: String<64> = String::new();
+
+    for page in 0..pages {
+        let Some(data) = dump.rows[page as usize / 4] else {
+            continue;
+        };
+        let offset = (page as usize % 4) * 4;
+
+        print_row(&mut buff, &data[offset..offset + 4]);
         defmt::println!(
-            "BLOCK {} (REL: {}) | {} | {}",
-            abs_block,
-            rel_block,
+            "PAGE {} | {} | {}",
+            page,
             buff,
-            block_type
+            layout::get_page_type(&dump.layout, page)
         );
-
         buff.clear();
     }
-    defmt::println!("");
-    Ok(())
 }
 
-fn dump_memory<E, COMM>(
+fn print_dump(dump: &Dump) {
+    defmt::println!("-----------{}-----------", dump.layout.name());
+
+    match dump.layout {
+        Layout::Classic { sectors, .. } => print_classic(dump, sectors),
+        Layout::Ultralight { pages, .. } => print_pages(dump, pages),
+    }
+}
+
+/// Reports whether a Classic card is a magic card and gives it `NEW_UID`.
+fn handle_magic<COMM, D, const N: usize>(
     uid: &mfrc522::Uid,
+    keyring: &KeyRing<N>,
     rfid: &mut Mfrc522<COMM, mfrc522::Initialized>,
-) -> Result<(), &'static str>
-where
-    COMM: mfrc522::comm::Interface<Error = E>,
+    pcd: &mut SharedSpi<'_, D>,
+) where
+    COMM: mfrc522::comm::Interface<Error = D::Error>,
+    D: SpiDevice,
 {
-    let mut buff: String<64> = String::new();
-    for sector in 0..16 {
-        // Printing the Sector number
-        write!(buff, "-----------SECTOR {}-----------", sector)
-            .expect("failed to write into heapless buff");
-        defmt::println!("{}", buff);
-        buff.clear();
-
-        read_sector(uid, sector, rfid)?;
+    let magic = match magic::detect(uid, keyring, &MAGIC_OPTIONS, rfid, pcd) {
+        Ok(magic) => magic,
+        Err(e) => {
+            error!("Magic card detection failed: {:?}", e);
+            return;
+        }
+    };
+    match magic {
+        Some(magic) => defmt::println!("MAGIC: {}", magic.as_str()),
+        None if MAGIC_OPTIONS.writes_enabled() => defmt::println!("MAGIC: no"),
+        None => defmt::println!("MAGIC: not a Gen1a, Gen2 not probed"),
     }
-    Ok(())
-}
 
-fn get_block_type(sector: u8, rel_block: u8) -> &'static str {
-    match rel_block {
-        0 if sector == 0 => "MFD",
-        3 => "TRAILER",
-        _ => "DATA",
+    let Some(new_uid) = NEW_UID else {
+        return;
+    };
+    if uid.as_bytes() == new_uid {
+        return;
+    }
+    match magic::write_uid(uid, magic, &new_uid, keyring, &MAGIC_OPTIONS, rfid, pcd) {
+        Ok(block0) if MAGIC_OPTIONS.dry_run => {
+            defmt::println!("DRY RUN: block 0 would become {:02x}", block0)
+        }
+        Ok(block0) => info!("UID changed to {:02x}, block 0 is {:02x}", new_uid, block0),
+        Err(e) => error!("UID not changed: {:?}", e),
     }
 }
 
@@ -116,16 +299,54 @@ async fn main(_spawner: Spawner) {
     let spi_bus = Spi::new_blocking(p.SPI0, clk, mosi, miso, config);
     let spi = ExclusiveDevice::new(spi_bus, cs_pin, Delay).expect("Failed to get exclusive device");
 
-    let itf = SpiInterface::new(spi);
+    // Shared between the driver and our own key B authentication
+    let spi = RefCell::new(spi);
+    let mut pcd = SharedSpi::new(&spi);
+
+    let itf = SpiInterface::new(pcd);
     let mut rfid = Mfrc522::new(itf)
         .init()
         .expect("failed to initialize the RFID reader");
 
+    let mut keyring: KeyRing<16> = KeyRing::with_defaults();
+    for key in USER_KEYS {
+        keyring.add(key).expect("key ring too small");
+    }
+
+    // Show the whole card the first time we see it, and only what changed
+    // on every scan after that
+    let mut history: History<HISTORY_SIZE> = History::new();
+
     loop {
         if let Ok(atqa) = rfid.reqa() {
             if let Ok(uid) = rfid.select(&atqa) {
-                if let Err(e) = dump_memory(&uid, &mut rfid) {
-                    error!("Error reading sector: {:?}", e);
+                match dump_memory(&uid, &keyring, &mut rfid, &mut pcd) {
+                    Ok(dump) => {
+                        let classic = matches!(dump.layout, Layout::Classic { .. });
+                        match history.get(&dump.uid) {
+                            Some(previous) => {
+                                defmt::println!(
+                                    "-----------DIFF {:02x}-----------",
+                                    uid.as_bytes()
+                                );
+                                match diff::print_diff(previous, &dump) {
+                                    Ok(0) => defmt::println!("No changes"),
+                                    Ok(_) => {}
+                                    Err(e) => {
+                                        defmt::println!("{}, showing the whole card", e);
+                                        print_dump(&dump);
+                                    }
+                                }
+                            }
+                            None => print_dump(&dump),
+                        }
+                        history.store(dump);
+
+                        if classic {
+                            handle_magic(&uid, &keyring, &mut rfid, &mut pcd);
+                        }
+                    }
+                    Err(e) => error!("Error dumping memory: {:?}", e),
                 }
                 let _ = rfid.hlta();
                 let _ = rfid.stop_crypto1();
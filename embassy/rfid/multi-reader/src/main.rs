@@ -0,0 +1,112 @@
+#![no_std]
+#![no_main]
+
+use embassy_executor::Spawner;
+use embassy_time::Timer;
+
+// defmt Logging
+use defmt::info;
+use defmt_rtt as _;
+
+use panic_probe as _;
+
+// For SPI
+use embassy_embedded_hal::shared_bus::blocking::spi::SpiDevice;
+use embassy_rp::peripherals::SPI0;
+use embassy_rp::spi;
+use embassy_rp::spi::{Blocking, Spi};
+
+// For CS Pins
+use embassy_rp::gpio::{Level, Output};
+
+// Driver for the MFRC522
+use mfrc522::comm::blocking::spi::{DummyDelay, SpiInterface};
+use mfrc522::{Initialized, Mfrc522};
+
+// For sharing the bus and passing events between tasks
+use core::cell::RefCell;
+use embassy_sync::blocking_mutex::Mutex;
+use embassy_sync::blocking_mutex::raw::{CriticalSectionRawMutex, NoopRawMutex};
+use embassy_sync::channel::Channel;
+use heapless::Vec;
+use static_cell::StaticCell;
+
+type SpiBus = Spi<'static, SPI0, Blocking>;
+// All readers run on the same executor, so the bus needs no critical section
+type SharedBus = Mutex<NoopRawMutex, RefCell<SpiBus>>;
+type Reader = Mfrc522<
+    SpiInterface<SpiDevice<'static, NoopRawMutex, SpiBus, Output<'static>>, DummyDelay>,
+    Initialized,
+>;
+
+const READER_COUNT: usize = 2;
+
+#[derive(Debug, Clone, PartialEq, Eq, defmt::Format)]
+pub struct ReaderEvent {
+    pub reader: u8,
+    pub uid: Vec<u8, 10>,
+}
+
+static SPI_BUS: StaticCell<SharedBus> = StaticCell::new();
+static READER_EVENTS: Channel<CriticalSectionRawMutex, ReaderEvent, 8> = Channel::new();
+
+/// Creates a reader that shares the bus and only owns its chip select.
+fn new_reader(bus: &'static SharedBus, cs_pin: Output<'static>) -> Reader {
+    let spi = SpiDevice::new(bus, cs_pin);
+    let itf = SpiInterface::new(spi);
+    Mfrc522::new(itf)
+        .init()
+        .expect("failed to initialize the RFID reader")
+}
+
+#[embassy_executor::task(pool_size = READER_COUNT)]
+async fn reader_task(reader: u8, mut rfid: Reader) {
+    info!("Reader {} ready", reader);
+
+    loop {
+        if let Ok(atqa) = rfid.reqa()
+            && let Ok(uid) = rfid.select(&atqa)
+        {
+            let event = ReaderEvent {
+                reader,
+                uid: Vec::from_slice(uid.as_bytes()).expect("UID is at most 10 bytes"),
+            };
+            READER_EVENTS.send(event).await;
+
+            let _ = rfid.hlta();
+            Timer::after_millis(500).await;
+        }
+
+        // Also gives the other readers their turn on the bus
+        Timer::after_millis(100).await;
+    }
+}
+
+#[embassy_executor::main]
+async fn main(spawner: Spawner) {
+    let p = embassy_rp::init(Default::default());
+
+    info!("Initializing the program");
+
+    let miso = p.PIN_0;
+    let clk = p.PIN_2;
+    let mosi = p.PIN_3;
+
+    // One chip select per reader, everything else is shared
+    let door1_cs = Output::new(p.PIN_1, Level::High);
+    let door2_cs = Output::new(p.PIN_5, Level::High);
+
+    let mut config = spi::Config::default();
+    config.frequency = 1_000_000;
+
+    let spi_bus = Spi::new_blocking(p.SPI0, clk, mosi, miso, config);
+    let spi_bus = SPI_BUS.init(Mutex::new(RefCell::new(spi_bus)));
+
+    spawner.must_spawn(reader_task(0, new_reader(spi_bus, door1_cs)));
+    spawner.must_spawn(reader_task(1, new_reader(spi_bus, door2_cs)));
+
+    loop {
+        let event = READER_EVENTS.receive().await;
+        defmt::println!("READER {} | UID {=[u8]:02x}", event.reader, event.uid);
+    }
+}
@@ -0,0 +1,261 @@
+#![no_std]
+#![no_main]
+
+use embassy_executor::Spawner;
+use embassy_time::Timer;
+
+// defmt Logging
+use defmt::{error, info, warn};
+use defmt_rtt as _;
+
+use panic_probe as _;
+
+// For SPI
+use embassy_rp::spi;
+use embassy_rp::spi::Spi;
+use embassy_time::Delay;
+use embedded_hal_bus::spi::ExclusiveDevice;
+
+// For CS Pin
+use embassy_rp::gpio::{Level, Output};
+
+// Driver for the MFRC522
+use mfrc522::{Mfrc522, comm::blocking::spi::SpiInterface};
+
+use rfid_common::access::AccessConditions;
+use rfid_common::mad::{self, Mad};
+use rfid_common::ndef::{self, Message, Record};
+use rfid_common::retry;
+
+const DEFAULT_KEY: [u8; 6] = [0xFF; 6];
+
+// Set to true to format blank cards with MESSAGE_TO_WRITE. Cards that
+// still have data or keys of their own are never touched.
+const FORMAT_BLANK_CARDS: bool = false;
+const MESSAGE_TO_WRITE: Message = Message::Uri("https://rp2040.implrust.com/");
+
+// Sectors 1 to 15, three data blocks each
+const NDEF_AREA_SIZE: usize = 15 * 48;
+
+/// Authenticates with the first key A that works.
+fn authenticate_any<E, COMM>(
+    uid: &mfrc522::Uid,
+    block: u8,
+    keys: &[[u8; 6]],
+    rfid: &mut Mfrc522<COMM, mfrc522::Initialized>,
+) -> Result<(), &'static str>
+where
+    COMM: mfrc522::comm::Interface<Error = E>,
+{
+    for key in keys {
+        if rfid.mf_authenticate(uid, block, key).is_ok() {
+            return Ok(());
+        }
+        retry::reselect(uid, rfid).map_err(|_| "Card lost")?;
+    }
+    Err("Auth failed")
+}
+
+fn read_mad<E, COMM>(
+    uid: &mfrc522::Uid,
+    rfid: &mut Mfrc522<COMM, mfrc522::Initialized>,
+) -> Result<Mad, &'static str>
+where
+    COMM: mfrc522::comm::Interface<Error = E>,
+{
+    authenticate_any(uid, 0, &[mad::MAD_KEY_A, DEFAULT_KEY], rfid)?;
+    let block1 = rfid.mf_read(1).map_err(|_| "Read failed")?;
+    let block2 = rfid.mf_read(2).map_err(|_| "Read failed")?;
+    Mad::parse(&block1, &block2)
+}
+
+/// Reads the data blocks of every NDEF sector into one continuous area.
+fn read_ndef_area<E, COMM>(
+    uid: &mfrc522::Uid,
+    mad: &Mad,
+    area: &mut [u8; NDEF_AREA_SIZE],
+    rfid: &mut Mfrc522<COMM, mfrc522::Initialized>,
+) -> Result<usize, &'static str>
+where
+    COMM: mfrc522::comm::Interface<Error = E>,
+{
+    let mut len = 0;
+    for sector in mad.ndef_sectors() {
+        let block_offset = sector * 4;
+        authenticate_any(uid, block_offset, &[mad::NDEF_KEY_A, DEFAULT_KEY], rfid)?;
+
+        for abs_block in block_offset..block_offset + 3 {
+            let data = rfid.mf_read(abs_block).map_err(|_| "Read failed")?;
+            area[len..len + 16].copy_from_slice(&data);
+            len += 16;
+        }
+    }
+    Ok(len)
+}
+
+/// Checks that the sectors we are about to format hold nothing yet: the
+/// transport key opens them, the access bits are the factory ones and every
+/// data block is zero. Block 0 holds the manufacturer data and is skipped.
+fn is_blank<E, COMM>(
+    uid: &mfrc522::Uid,
+    sectors: u8,
+    rfid: &mut Mfrc522<COMM, mfrc522::Initialized>,
+) -> Result<bool, &'static str>
+where
+    COMM: mfrc522::comm::Interface<Error = E>,
+{
+    for sector in 0..=sectors {
+        let block_offset = sector * 4;
+        if rfid
+            .mf_authenticate(uid, block_offset, &DEFAULT_KEY)
+            .is_err()
+        {
+            retry::reselect(uid, rfid).map_err(|_| "Card lost")?;
+            return Ok(false);
+        }
+
+        for abs_block in block_offset.max(1)..block_offset + 3 {
+            let data = rfid.mf_read(abs_block).map_err(|_| "Read failed")?;
+            if data != [0; 16] {
+                return Ok(false);
+            }
+        }
+        let trailer = rfid.mf_read(block_offset + 3).map_err(|_| "Read failed")?;
+        if AccessConditions::from_trailer(&trailer) != Ok(AccessConditions::TRANSPORT) {
+            return Ok(false);
+        }
+    }
+    Ok(true)
+}
+
+fn print_message(message: &[u8]) {
+    defmt::println!("-----------NDEF-----------");
+    for record in ndef::records(message) {
+        match record {
+            Ok(Record::Uri { prefix, rest }) => defmt::println!("URI: {}{}", prefix, rest),
+            Ok(Record::Text { language, text }) => defmt::println!("TEXT ({}): {}", language, text),
+            Ok(Record::Mime { mime_type, data }) => {
+                defmt::println!("MIME {}: {=[u8]:02x}", mime_type, data)
+            }
+            Ok(other) => defmt::println!("{:?}", other),
+            Err(e) => {
+                error!("Bad record: {:?}", e);
+                break;
+            }
+        }
+    }
+}
+
+/// Writes an NDEF TLV area to a blank card and sets up the MAD.
+///
+/// Every sector is authenticated with the transport key. The NDEF sectors
+/// are written first, so sector 0 only announces data that is already there.
+fn format_card<E, COMM>(
+    uid: &mfrc522::Uid,
+    tlv_area: &[u8],
+    rfid: &mut Mfrc522<COMM, mfrc522::Initialized>,
+) -> Result<(), &'static str>
+where
+    COMM: mfrc522::comm::Interface<Error = E>,
+{
+    let sectors = tlv_area.len().div_ceil(48);
+    if sectors > 15 {
+        return Err("Message does not fit on the card");
+    }
+
+    let mut chunks = tlv_area.chunks(16);
+    for sector in 1..=sectors as u8 {
+        let block_offset = sector * 4;
+        rfid.mf_authenticate(uid, block_offset, &DEFAULT_KEY)
+            .map_err(|_| "Auth failed, card is not blank")?;
+
+        for abs_block in block_offset..block_offset + 3 {
+            let mut data = [0u8; 16];
+            if let Some(chunk) = chunks.next() {
+                data[..chunk.len()].copy_from_slice(chunk);
+            }
+            rfid.mf_write(abs_block, data).map_err(|_| "Write failed")?;
+        }
+        rfid.mf_write(block_offset + 3, mad::ndef_trailer(DEFAULT_KEY))
+            .map_err(|_| "Trailer write failed")?;
+    }
+
+    let (block1, block2) = Mad::ndef(sectors).encode();
+    rfid.mf_authenticate(uid, 0, &DEFAULT_KEY)
+        .map_err(|_| "Auth failed, card is not blank")?;
+    rfid.mf_write(1, block1).map_err(|_| "Write failed")?;
+    rfid.mf_write(2, block2).map_err(|_| "Write failed")?;
+    rfid.mf_write(3, mad::mad_trailer(DEFAULT_KEY))
+        .map_err(|_| "Trailer write failed")?;
+
+    Ok(())
+}
+
+fn handle_card<E, COMM>(
+    uid: &mfrc522::Uid,
+    rfid: &mut Mfrc522<COMM, mfrc522::Initialized>,
+) -> Result<(), &'static str>
+where
+    COMM: mfrc522::comm::Interface<Error = E>,
+{
+    let mad = match read_mad(uid, rfid) {
+        Ok(mad) => mad,
+        Err(e) if FORMAT_BLANK_CARDS => {
+            warn!("No MAD ({:?}), checking whether the card is blank", e);
+            retry::reselect(uid, rfid).map_err(|_| "Card lost")?;
+
+            let mut tlv_area = [0u8; NDEF_AREA_SIZE];
+            let len = MESSAGE_TO_WRITE.encode(&mut tlv_area)?;
+            if !is_blank(uid, len.div_ceil(48) as u8, rfid)? {
+                return Err("No MAD and the card is not blank, leaving it alone");
+            }
+            format_card(uid, &tlv_area[..len], rfid)?;
+            info!("Formatted the card as NDEF");
+            return Ok(());
+        }
+        Err(e) => return Err(e),
+    };
+
+    let mut area = [0u8; NDEF_AREA_SIZE];
+    let len = read_ndef_area(uid, &mad, &mut area, rfid)?;
+    let message = ndef::find_message(&area[..len])?;
+    print_message(message);
+    Ok(())
+}
+
+#[embassy_executor::main]
+async fn main(_spawner: Spawner) {
+    let p = embassy_rp::init(Default::default());
+
+    info!("Initializing the program");
+
+    let miso = p.PIN_0;
+    let cs_pin = Output::new(p.PIN_1, Level::High);
+    let clk = p.PIN_2;
+    let mosi = p.PIN_3;
+
+    let mut config = spi::Config::default();
+    config.frequency = 1_000_000;
+
+    let spi_bus = Spi::new_blocking(p.SPI0, clk, mosi, miso, config);
+    let spi = ExclusiveDevice::new(spi_bus, cs_pin, Delay).expect("Failed to get exclusive device");
+
+    let itf = SpiInterface::new(spi);
+    let mut rfid = Mfrc522::new(itf)
+        .init()
+        .expect("failed to initialize the RFID reader");
+
+    loop {
+        if let Ok(atqa) = rfid.reqa()
+            && let Ok(uid) = rfid.select(&atqa)
+        {
+            if let Err(e) = handle_card(&uid, &mut rfid) {
+                error!("Error handling NDEF tag: {:?}", e);
+            }
+            let _ = rfid.hlta();
+            let _ = rfid.stop_crypto1();
+            Timer::after_millis(1000).await;
+        }
+        Timer::after_millis(100).await;
+    }
+}
@@ -0,0 +1,233 @@
+#![no_std]
+#![no_main]
+
+use embassy_executor::Spawner;
+use embassy_time::Timer;
+
+// defmt Logging
+use defmt::{error, info};
+use defmt_rtt as _;
+
+use panic_probe as _;
+
+// For SPI
+use embassy_rp::spi;
+use embassy_rp::spi::Spi;
+use embassy_time::Delay;
+use embedded_hal_bus::spi::ExclusiveDevice;
+
+// For CS Pin
+use embassy_rp::gpio::{Level, Output};
+
+// Driver for the MFRC522
+use mfrc522::{Mfrc522, comm::blocking::spi::SpiInterface};
+
+// For SdCard
+use embedded_sdmmc::{
+    BlockDevice, Directory, Mode, SdCard, TimeSource, Timestamp, VolumeIdx, VolumeManager,
+};
+
+use core::cell::RefCell;
+
+use rfid_common::formats;
+use rfid_common::keyring::KeyRing;
+use rfid_common::pcd::{PcdReader, SharedSpi};
+use rfid_common::restore::{self, BlockStatus, IMAGE_SIZE, RestoreOptions};
+
+// A dump written by the dump-sdcard example (or by a Flipper Zero or a
+// Proxmark), renamed. The first of these found on the SD card is used.
+const SOURCE_FILES: [&str; 3] = ["SOURCE.MFD", "SOURCE.NFC", "SOURCE.EML"];
+
+// A 1K image as .nfc text is about 4 KiB
+const MAX_SOURCE_SIZE: usize = 8192;
+
+/// Code from https://github.com/rp-rs/rp-hal-boards/blob/main/boards/rp-pico/examples/pico_spi_sd_card.rs
+/// A dummy timesource, which is mostly important for creating files.
+#[derive(Default)]
+pub struct DummyTimesource();
+
+impl TimeSource for DummyTimesource {
+    // In theory you could use the RTC of the rp2040 here, if you had
+    // any external time synchronizing device.
+    fn get_timestamp(&self) -> Timestamp {
+        Timestamp {
+            year_since_1970: 0,
+            zero_indexed_month: 0,
+            zero_indexed_day: 0,
+            hours: 0,
+            minutes: 0,
+            seconds: 0,
+        }
+    }
+}
+
+/// Reads a whole file, failing if it doesn't fit in `buf`.
+fn read_file<D, T, const DIRS: usize, const FILES: usize, const VOLUMES: usize>(
+    dir: &Directory<'_, D, T, DIRS, FILES, VOLUMES>,
+    name: &str,
+    buf: &mut [u8],
+) -> Result<usize, &'static str>
+where
+    D: BlockDevice,
+    T: TimeSource,
+{
+    let file = dir
+        .open_file_in_dir(name, Mode::ReadOnly)
+        .map_err(|_| "Can't open file")?;
+
+    let mut len = 0;
+    while !file.is_eof() && len < buf.len() {
+        len += file.read(&mut buf[len..]).map_err(|_| "Can't read file")?;
+    }
+    if !file.is_eof() {
+        return Err("File too large");
+    }
+    Ok(len)
+}
+
+/// Turns the contents of a source file into a 1K image.
+fn load_image(name: &str, contents: &[u8]) -> Result<[u8; IMAGE_SIZE], &'static str> {
+    let mut image = [0u8; IMAGE_SIZE];
+
+    if name.ends_with(".MFD") {
+        if contents.len() != IMAGE_SIZE {
+            return Err("Not a 1K image");
+        }
+        image.copy_from_slice(contents);
+        restore::check_mfd_keys(&image)?;
+        return Ok(image);
+    }
+
+    let text = core::str::from_utf8(contents).map_err(|_| "Not a text file")?;
+    if name.ends_with(".NFC") {
+        let mut known = [formats::NONE_KNOWN; formats::BLOCKS_1K];
+        let card = formats::parse_flipper(text, &mut image, &mut known)?;
+        info!("Flipper image of UID {:02x}", card.uid.as_slice());
+        // Writing zeros over bytes we never saw would destroy them
+        if card.blocks != formats::BLOCKS_1K
+            || known.iter().any(|&bytes| bytes != formats::ALL_KNOWN)
+        {
+            return Err("Only complete 1K images can be restored");
+        }
+    } else if formats::parse_eml(text, &mut image)? != formats::BLOCKS_1K {
+        return Err("Not a 1K image");
+    }
+    Ok(image)
+}
+
+fn print_report(status: &[BlockStatus]) {
+    defmt::println!("-----------RESTORE REPORT-----------");
+    for (abs_block, block_status) in status.iter().enumerate() {
+        defmt::println!(
+            "BLOCK {} (SECTOR {}) | {}",
+            abs_block,
+            abs_block / 4,
+            block_status
+        );
+    }
+
+    let failed = status
+        .iter()
+        .filter(|s| matches!(s, BlockStatus::Failed(_)))
+        .count();
+    defmt::println!("{} of {} blocks failed", failed, status.len());
+}
+
+#[embassy_executor::main]
+async fn main(_spawner: Spawner) {
+    let p = embassy_rp::init(Default::default());
+
+    info!("Initializing the program");
+
+    // RFID reader on SPI0
+    let miso = p.PIN_0;
+    let cs_pin = Output::new(p.PIN_1, Level::High);
+    let clk = p.PIN_2;
+    let mosi = p.PIN_3;
+
+    let mut config = spi::Config::default();
+    config.frequency = 1_000_000;
+
+    let spi_bus = Spi::new_blocking(p.SPI0, clk, mosi, miso, config);
+    let spi = ExclusiveDevice::new(spi_bus, cs_pin, Delay).expect("Failed to get exclusive device");
+
+    // Shared between the driver and our own key B authentication
+    let spi = RefCell::new(spi);
+    let pcd = SharedSpi::new(&spi);
+
+    let itf = SpiInterface::new(pcd);
+    let mut rfid = Mfrc522::new(itf)
+        .init()
+        .expect("failed to initialize the RFID reader");
+
+    // SD card on SPI1
+    let sd_miso = p.PIN_12;
+    let sd_cs_pin = Output::new(p.PIN_13, Level::High);
+    let sd_clk = p.PIN_10;
+    let sd_mosi = p.PIN_11;
+
+    let mut sd_config = spi::Config::default();
+    sd_config.frequency = 400_000;
+
+    let sd_spi_bus = Spi::new_blocking(p.SPI1, sd_clk, sd_mosi, sd_miso, sd_config);
+    let sd_spi_device =
+        ExclusiveDevice::new(sd_spi_bus, sd_cs_pin, Delay).expect("Failed to get exclusive device");
+
+    let sdcard = SdCard::new(sd_spi_device, Delay);
+
+    let volume_mgr = VolumeManager::new(sdcard, DummyTimesource::default());
+    let volume0 = volume_mgr
+        .open_volume(VolumeIdx(0))
+        .expect("failed to open volume");
+
+    let root_dir = volume0.open_root_dir().expect("failed to open root dir");
+
+    let mut contents = [0u8; MAX_SOURCE_SIZE];
+    let (name, len) = SOURCE_FILES
+        .iter()
+        .find_map(|name| {
+            read_file(&root_dir, name, &mut contents)
+                .ok()
+                .map(|len| (*name, len))
+        })
+        .expect("no SOURCE.MFD, SOURCE.NFC or SOURCE.EML file");
+
+    let image = match load_image(name, &contents[..len]) {
+        Ok(image) => image,
+        Err(e) => panic!("{} can't be used: {}", name, e),
+    };
+    info!("Loaded {} from {} bytes", name, len);
+
+    let mut keyring: KeyRing<48> = KeyRing::with_defaults();
+    restore::add_image_keys(&image, &mut keyring).expect("key ring too small");
+
+    let options = RestoreOptions {
+        allow_block0: false,
+    };
+
+    info!("Place the target card on the reader");
+    loop {
+        if let Ok(atqa) = rfid.reqa()
+            && let Ok(uid) = rfid.select(&atqa)
+        {
+            match restore::restore_card(
+                &uid,
+                &image,
+                &keyring,
+                &options,
+                &mut PcdReader::new(&mut rfid, pcd),
+            ) {
+                Ok(status) => print_report(&status),
+                Err(e) => error!("Error restoring card: {:?}", e),
+            }
+
+            let _ = rfid.hlta();
+            let _ = rfid.stop_crypto1();
+
+            info!("Done, remove the card");
+            Timer::after_secs(3).await;
+        }
+
+        Timer::after_millis(200).await;
+    }
+}
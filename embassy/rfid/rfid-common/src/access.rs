@@ -0,0 +1,389 @@
+//! MIFARE Classic access conditions.
+//!
+//! Bytes 6 to 9 of a sector trailer hold the access bits. Every block of the
+//! sector (three data blocks and the trailer itself) gets three bits: C1, C2
+//! and C3. Each bit is stored twice, once as-is and once inverted:
+//!
+//! ```text
+//! byte 6: !C2_3 !C2_2 !C2_1 !C2_0 | !C1_3 !C1_2 !C1_1 !C1_0
+//! byte 7:  C1_3  C1_2  C1_1  C1_0 | !C3_3 !C3_2 !C3_1 !C3_0
+//! byte 8:  C3_3  C3_2  C3_1  C3_0 |  C2_3  C2_2  C2_1  C2_0
+//! byte 9:  general purpose byte (user data)
+//! ```
+//!
+//! If the inverted copy does not match, the card treats the sector as
+//! permanently locked. So we always check it before writing a trailer.
+
+/// Which key (if any) is allowed to perform an operation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Permission {
+    Never,
+    KeyA,
+    KeyB,
+    KeyAOrB,
+}
+
+/// What the keys may do with a data block.
+///
+/// `decrement` also covers the transfer and restore commands.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct DataPermissions {
+    pub read: Permission,
+    pub write: Permission,
+    pub increment: Permission,
+    pub decrement: Permission,
+}
+
+/// What the keys may do with the sector trailer.
+///
+/// Key A can never be read back, so there is no field for it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct TrailerPermissions {
+    pub key_a_write: Permission,
+    pub access_bits_read: Permission,
+    pub access_bits_write: Permission,
+    pub key_b_read: Permission,
+    pub key_b_write: Permission,
+}
+
+impl TrailerPermissions {
+    /// True if the access bits can never be changed again.
+    pub fn is_frozen(&self) -> bool {
+        self.access_bits_write == Permission::Never
+    }
+
+    /// True if key B can be read, which means it cannot be used to authenticate.
+    pub fn key_b_readable(&self) -> bool {
+        self.key_b_read != Permission::Never
+    }
+}
+
+/// The C1, C2 and C3 bits for a single block.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct AccessBits {
+    pub c1: bool,
+    pub c2: bool,
+    pub c3: bool,
+}
+
+impl AccessBits {
+    pub const fn new(c1: bool, c2: bool, c3: bool) -> Self {
+        Self { c1, c2, c3 }
+    }
+
+    /// Packs the bits as `C1 C2 C3`, the order used in the datasheet tables.
+    pub const fn value(self) -> u8 {
+        ((self.c1 as u8) << 2) | ((self.c2 as u8) << 1) | self.c3 as u8
+    }
+
+    /// Interprets the bits for one of the three data blocks.
+    pub fn data_permissions(self) -> DataPermissions {
+        use Permission::*;
+        let (read, write, increment, decrement) = match self.value() {
+            0b000 => (KeyAOrB, KeyAOrB, KeyAOrB, KeyAOrB),
+            0b010 => (KeyAOrB, Never, Never, Never),
+            0b100 => (KeyAOrB, KeyB, Never, Never),
+            0b110 => (KeyAOrB, KeyB, KeyB, KeyAOrB),
+            0b001 => (KeyAOrB, Never, Never, KeyAOrB),
+            0b011 => (KeyB, KeyB, Never, Never),
+            0b101 => (KeyB, Never, Never, Never),
+            _ => (Never, Never, Never, Never),
+        };
+        DataPermissions {
+            read,
+            write,
+            increment,
+            decrement,
+        }
+    }
+
+    /// Interprets the bits for the sector trailer.
+    pub fn trailer_permissions(self) -> TrailerPermissions {
+        use Permission::*;
+        let (key_a_write, access_bits_read, access_bits_write, key_b_read, key_b_write) =
+            match self.value() {
+                0b000 => (KeyA, KeyA, Never, KeyA, KeyA),
+                0b010 => (Never, KeyA, Never, KeyA, Never),
+                0b100 => (KeyB, KeyAOrB, Never, Never, KeyB),
+                0b110 => (Never, KeyAOrB, Never, Never, Never),
+                0b001 => (KeyA, KeyA, KeyA, KeyA, KeyA),
+                0b011 => (KeyB, KeyAOrB, KeyB, Never, KeyB),
+                0b101 => (Never, KeyAOrB, KeyB, Never, Never),
+                _ => (Never, KeyAOrB, Never, Never, Never),
+            };
+        TrailerPermissions {
+            key_a_write,
+            access_bits_read,
+            access_bits_write,
+            key_b_read,
+            key_b_write,
+        }
+    }
+}
+
+/// Access conditions for a whole sector.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct AccessConditions {
+    /// Bits for the three data blocks, in block order.
+    pub data: [AccessBits; 3],
+    pub trailer: AccessBits,
+}
+
+impl AccessConditions {
+    /// The factory ("transport") configuration: `FF 07 80`.
+    ///
+    /// Key A can do everything, key B is readable and the access bits can
+    /// still be changed with key A.
+    pub const TRANSPORT: Self = Self {
+        data: [AccessBits::new(false, false, false); 3],
+        trailer: AccessBits::new(false, false, true),
+    };
+
+    /// Encodes the conditions into trailer bytes 6, 7 and 8.
+    pub const fn encode(&self) -> [u8; 3] {
+        let mut c1 = 0u8;
+        let mut c2 = 0u8;
+        let mut c3 = 0u8;
+
+        let mut i = 0;
+        while i < 4 {
+            let bits = if i < 3 { self.data[i] } else { self.trailer };
+            c1 |= (bits.c1 as u8) << i;
+            c2 |= (bits.c2 as u8) << i;
+            c3 |= (bits.c3 as u8) << i;
+            i += 1;
+        }
+
+        [
+            ((!c2 & 0x0F) << 4) | (!c1 & 0x0F),
+            (c1 << 4) | (!c3 & 0x0F),
+            (c3 << 4) | c2,
+        ]
+    }
+
+    /// Decodes trailer bytes 6, 7 and 8, rejecting them if the inverted
+    /// copies do not match.
+    pub fn decode(bytes: [u8; 3]) -> Result<Self, &'static str> {
+        let c1 = bytes[1] >> 4;
+        let c2 = bytes[2] & 0x0F;
+        let c3 = bytes[2] >> 4;
+
+        let inv_c1 = bytes[0] & 0x0F;
+        let inv_c2 = bytes[0] >> 4;
+        let inv_c3 = bytes[1] & 0x0F;
+
+        if c1 != (!inv_c1 & 0x0F) || c2 != (!inv_c2 & 0x0F) || c3 != (!inv_c3 & 0x0F) {
+            return Err("Access bits inverted copy mismatch");
+        }
+
+        let bits =
+            |i: u8| AccessBits::new((c1 >> i) & 1 == 1, (c2 >> i) & 1 == 1, (c3 >> i) & 1 == 1);
+
+        Ok(Self {
+            data: [bits(0), bits(1), bits(2)],
+            trailer: bits(3),
+        })
+    }
+
+    /// Reads the access conditions out of a full 16-byte sector trailer.
+    pub fn from_trailer(trailer: &[u8; 16]) -> Result<Self, &'static str> {
+        Self::decode([trailer[6], trailer[7], trailer[8]])
+    }
+
+    /// Builds a full sector trailer from two keys, these conditions and the
+    /// general purpose byte.
+    pub const fn to_trailer(&self, key_a: [u8; 6], gpb: u8, key_b: [u8; 6]) -> [u8; 16] {
+        let access = self.encode();
+        [
+            key_a[0], key_a[1], key_a[2], key_a[3], key_a[4], key_a[5], //
+            access[0], access[1], access[2], gpb, //
+            key_b[0], key_b[1], key_b[2], key_b[3], key_b[4], key_b[5],
+        ]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bits(value: u8) -> AccessBits {
+        AccessBits::new(value & 0b100 != 0, value & 0b010 != 0, value & 0b001 != 0)
+    }
+
+    // Access conditions for data blocks, MF1S50 datasheet table 8:
+    // C1C2C3, read, write, increment, decrement/transfer/restore
+    #[rustfmt::skip]
+    const DATA_TABLE: [(u8, [Permission; 4]); 8] = {
+        use Permission::*;
+        [
+            (0b000, [KeyAOrB, KeyAOrB, KeyAOrB, KeyAOrB]),
+            (0b010, [KeyAOrB, Never,   Never,   Never  ]),
+            (0b100, [KeyAOrB, KeyB,    Never,   Never  ]),
+            (0b110, [KeyAOrB, KeyB,    KeyB,    KeyAOrB]),
+            (0b001, [KeyAOrB, Never,   Never,   KeyAOrB]),
+            (0b011, [KeyB,    KeyB,    Never,   Never  ]),
+            (0b101, [KeyB,    Never,   Never,   Never  ]),
+            (0b111, [Never,   Never,   Never,   Never  ]),
+        ]
+    };
+
+    // Access conditions for the sector trailer, MF1S50 datasheet table 7:
+    // C1C2C3, key A write, access bits read, access bits write, key B read, key B write
+    #[rustfmt::skip]
+    const TRAILER_TABLE: [(u8, [Permission; 5]); 8] = {
+        use Permission::*;
+        [
+            (0b000, [KeyA,  KeyA,    Never, KeyA,  KeyA ]),
+            (0b010, [Never, KeyA,    Never, KeyA,  Never]),
+            (0b100, [KeyB,  KeyAOrB, Never, Never, KeyB ]),
+            (0b110, [Never, KeyAOrB, Never, Never, Never]),
+            (0b001, [KeyA,  KeyA,    KeyA,  KeyA,  KeyA ]),
+            (0b011, [KeyB,  KeyAOrB, KeyB,  Never, KeyB ]),
+            (0b101, [Never, KeyAOrB, KeyB,  Never, Never]),
+            (0b111, [Never, KeyAOrB, Never, Never, Never]),
+        ]
+    };
+
+    #[test]
+    fn data_permissions_match_the_datasheet() {
+        for (value, [read, write, increment, decrement]) in DATA_TABLE {
+            let expected = DataPermissions {
+                read,
+                write,
+                increment,
+                decrement,
+            };
+            assert_eq!(
+                bits(value).data_permissions(),
+                expected,
+                "C1C2C3 {value:03b}"
+            );
+        }
+    }
+
+    #[test]
+    fn trailer_permissions_match_the_datasheet() {
+        for (
+            value,
+            [
+                key_a_write,
+                access_bits_read,
+                access_bits_write,
+                key_b_read,
+                key_b_write,
+            ],
+        ) in TRAILER_TABLE
+        {
+            let expected = TrailerPermissions {
+                key_a_write,
+                access_bits_read,
+                access_bits_write,
+                key_b_read,
+                key_b_write,
+            };
+            let permissions = bits(value).trailer_permissions();
+            assert_eq!(permissions, expected, "C1C2C3 {value:03b}");
+            assert_eq!(
+                permissions.is_frozen(),
+                access_bits_write == Permission::Never
+            );
+            assert_eq!(
+                permissions.key_b_readable(),
+                key_b_read != Permission::Never
+            );
+        }
+    }
+
+    #[test]
+    fn transport_configuration() {
+        assert_eq!(AccessConditions::TRANSPORT.encode(), [0xFF, 0x07, 0x80]);
+        assert_eq!(
+            AccessConditions::decode([0xFF, 0x07, 0x80]),
+            Ok(AccessConditions::TRANSPORT)
+        );
+    }
+
+    #[test]
+    fn data_bits_round_trip() {
+        for block in 0..3 {
+            for value in 0..8 {
+                let mut conditions = AccessConditions::TRANSPORT;
+                conditions.data[block] = bits(value);
+
+                let decoded = AccessConditions::decode(conditions.encode()).unwrap();
+                assert_eq!(decoded, conditions, "block {block}, C1C2C3 {value:03b}");
+                assert_eq!(decoded.data[block].value(), value);
+            }
+        }
+    }
+
+    #[test]
+    fn trailer_bits_round_trip() {
+        for value in 0..8 {
+            let conditions = AccessConditions {
+                trailer: bits(value),
+                ..AccessConditions::TRANSPORT
+            };
+
+            let decoded = AccessConditions::decode(conditions.encode()).unwrap();
+            assert_eq!(decoded, conditions, "C1C2C3 {value:03b}");
+            assert_eq!(decoded.trailer.value(), value);
+        }
+    }
+
+    #[test]
+    fn full_trailer_round_trip() {
+        let conditions = AccessConditions {
+            data: [bits(0b100), bits(0b110), bits(0b001)],
+            trailer: bits(0b011),
+        };
+        let trailer = conditions.to_trailer([0xA0; 6], 0x69, [0xB0; 6]);
+
+        assert_eq!(&trailer[..6], &[0xA0; 6]);
+        assert_eq!(trailer[9], 0x69);
+        assert_eq!(&trailer[10..], &[0xB0; 6]);
+        assert_eq!(AccessConditions::from_trailer(&trailer), Ok(conditions));
+    }
+
+    #[test]
+    fn corrupted_inverted_bits_are_rejected() {
+        let encoded = AccessConditions::TRANSPORT.encode();
+
+        // The inverted copies are the whole of byte 6 and the low nibble of byte 7
+        let inverted = (0..8)
+            .map(|bit| (0, 1 << bit))
+            .chain((0..4).map(|bit| (1, 1 << bit)));
+        for (byte, mask) in inverted {
+            let mut corrupted = encoded;
+            corrupted[byte] ^= mask;
+            assert!(
+                AccessConditions::decode(corrupted).is_err(),
+                "byte {}, mask {mask:08b}",
+                byte + 6
+            );
+        }
+    }
+
+    #[test]
+    fn corrupted_plain_bits_are_rejected() {
+        let encoded = AccessConditions::TRANSPORT.encode();
+
+        let plain = (4..8)
+            .map(|bit| (1, 1 << bit))
+            .chain((0..8).map(|bit| (2, 1 << bit)));
+        for (byte, mask) in plain {
+            let mut corrupted = encoded;
+            corrupted[byte] ^= mask;
+            assert!(
+                AccessConditions::decode(corrupted).is_err(),
+                "byte {}, mask {mask:08b}",
+                byte + 6
+            );
+        }
+    }
+}
@@ -0,0 +1,534 @@
+//! UIDs allowed through the door, kept in flash.
+//!
+//! The list lives in two 4K flash sectors at the end of the flash (see
+//! `memory.x` in `rfid-led`). Flash wears out with every erase, so instead
+//! of rewriting the list on every change we append 16-byte records to a log:
+//!
+//! ```text
+//! slot 0:  "ALST" | sequence (u32 LE) | state | FF...
+//! slot n:  status | UID length | UID (10 bytes, FF padded) | FF...
+//! ```
+//!
+//! Programming flash can only clear bits, so a record goes from empty
+//! (`FF`) to valid (`7F`) to revoked (`3F`) without an erase. The status
+//! byte is written last, so a record torn by a power cut is never valid.
+//!
+//! Once a sector is full, the live records are copied to the other sector
+//! with a higher sequence number. The new sector only counts after its
+//! state byte is written, so the old one stays usable until then. That way
+//! each sector is erased once every couple of hundred changes.
+
+use embedded_storage::nor_flash::{MultiwriteNorFlash, NorFlash};
+use heapless::Vec;
+
+pub const SECTOR_SIZE: u32 = 4096;
+pub const STORAGE_SIZE: u32 = 2 * SECTOR_SIZE;
+pub const MAX_UIDS: usize = 64;
+
+const RECORD_SIZE: usize = 16;
+const SLOTS: usize = SECTOR_SIZE as usize / RECORD_SIZE;
+
+const MAGIC: [u8; 4] = *b"ALST";
+const HEADER_ACTIVE: u8 = 0x7F;
+
+// Value of every byte after an erase
+const ERASED: u8 = 0xFF;
+
+const STATUS_VALID: u8 = 0x7F;
+const STATUS_REVOKED: u8 = 0x3F;
+
+pub type CardUid = Vec<u8, 10>;
+
+struct Entry {
+    uid: CardUid,
+    slot: usize,
+}
+
+pub struct AllowList<F> {
+    flash: F,
+    base: u32,
+    /// Sector holding the current log, 0 or 1
+    active: u32,
+    sequence: u32,
+    next_slot: usize,
+    entries: Vec<Entry, MAX_UIDS>,
+}
+
+fn flash_err<E>(_: E) -> &'static str {
+    "Flash error"
+}
+
+impl<F: NorFlash + MultiwriteNorFlash> AllowList<F> {
+    /// Loads the list from the `STORAGE_SIZE` bytes of flash at `base`.
+    ///
+    /// Blank flash is formatted as an empty list.
+    pub fn load(flash: F, base: u32) -> Result<Self, &'static str> {
+        let mut list = Self {
+            flash,
+            base,
+            active: 0,
+            sequence: 0,
+            next_slot: 1,
+            entries: Vec::new(),
+        };
+
+        let headers = [list.read_header(0)?, list.read_header(1)?];
+        let active = match headers {
+            [Some(a), Some(b)] => Some(if b > a { 1 } else { 0 }),
+            [Some(_), None] => Some(0),
+            [None, Some(_)] => Some(1),
+            [None, None] => None,
+        };
+
+        match active {
+            Some(sector) => {
+                list.active = sector;
+                list.sequence = headers[sector as usize].expect("active sector has a header");
+                list.scan()?;
+            }
+            None => {
+                list.erase_sector(0)?;
+                list.write_header(0, 0)?;
+                list.activate(0)?;
+            }
+        }
+        Ok(list)
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn contains(&self, uid: &[u8]) -> bool {
+        self.entries.iter().any(|e| e.uid == uid)
+    }
+
+    pub fn add(&mut self, uid: &[u8]) -> Result<(), &'static str> {
+        if !matches!(uid.len(), 4 | 7 | 10) {
+            return Err("UID must be 4, 7 or 10 bytes");
+        }
+        if self.contains(uid) {
+            return Ok(());
+        }
+        if self.entries.is_full() {
+            return Err("Allow-list is full");
+        }
+
+        if self.next_slot == SLOTS {
+            self.compact()?;
+        }
+        let slot = self.next_slot;
+        self.write_record(self.active, slot, uid)?;
+        self.next_slot += 1;
+
+        let uid = Vec::from_slice(uid).map_err(|_| "UID too long")?;
+        let _ = self.entries.push(Entry { uid, slot });
+        Ok(())
+    }
+
+    pub fn remove(&mut self, uid: &[u8]) -> Result<(), &'static str> {
+        let Some(index) = self.entries.iter().position(|e| e.uid == uid) else {
+            return Ok(());
+        };
+        let offset = self.slot_offset(self.active, self.entries[index].slot);
+        self.flash
+            .write(offset, &[STATUS_REVOKED])
+            .map_err(flash_err)?;
+        self.entries.swap_remove(index);
+        Ok(())
+    }
+
+    /// Adds the UID if it is missing, removes it otherwise.
+    ///
+    /// Returns true if the UID is on the list afterwards.
+    pub fn toggle(&mut self, uid: &[u8]) -> Result<bool, &'static str> {
+        if self.contains(uid) {
+            self.remove(uid)?;
+            Ok(false)
+        } else {
+            self.add(uid)?;
+            Ok(true)
+        }
+    }
+
+    fn sector_offset(&self, sector: u32) -> u32 {
+        self.base + sector * SECTOR_SIZE
+    }
+
+    fn slot_offset(&self, sector: u32, slot: usize) -> u32 {
+        self.sector_offset(sector) + (slot * RECORD_SIZE) as u32
+    }
+
+    fn read_slot(&mut self, sector: u32, slot: usize) -> Result<[u8; RECORD_SIZE], &'static str> {
+        let mut record = [0u8; RECORD_SIZE];
+        let offset = self.slot_offset(sector, slot);
+        self.flash.read(offset, &mut record).map_err(flash_err)?;
+        Ok(record)
+    }
+
+    /// Returns the sequence number if the sector holds a complete log.
+    fn read_header(&mut self, sector: u32) -> Result<Option<u32>, &'static str> {
+        let header = self.read_slot(sector, 0)?;
+        if header[..4] != MAGIC || header[8] != HEADER_ACTIVE {
+            return Ok(None);
+        }
+        Ok(Some(u32::from_le_bytes(
+            header[4..8].try_into().expect("4 bytes"),
+        )))
+    }
+
+    fn write_header(&mut self, sector: u32, sequence: u32) -> Result<(), &'static str> {
+        let mut header = [0u8; 8];
+        header[..4].copy_from_slice(&MAGIC);
+        header[4..].copy_from_slice(&sequence.to_le_bytes());
+        let offset = self.slot_offset(sector, 0);
+        self.flash.write(offset, &header).map_err(flash_err)
+    }
+
+    fn activate(&mut self, sector: u32) -> Result<(), &'static str> {
+        let offset = self.slot_offset(sector, 0) + 8;
+        self.flash
+            .write(offset, &[HEADER_ACTIVE])
+            .map_err(flash_err)
+    }
+
+    fn erase_sector(&mut self, sector: u32) -> Result<(), &'static str> {
+        let from = self.sector_offset(sector);
+        self.flash
+            .erase(from, from + SECTOR_SIZE)
+            .map_err(flash_err)
+    }
+
+    fn write_record(&mut self, sector: u32, slot: usize, uid: &[u8]) -> Result<(), &'static str> {
+        let mut body = [ERASED; 11];
+        body[0] = uid.len() as u8;
+        body[1..1 + uid.len()].copy_from_slice(uid);
+
+        let offset = self.slot_offset(sector, slot);
+        self.flash.write(offset + 1, &body).map_err(flash_err)?;
+        self.flash.write(offset, &[STATUS_VALID]).map_err(flash_err)
+    }
+
+    /// Rebuilds the list in RAM from the active sector.
+    fn scan(&mut self) -> Result<(), &'static str> {
+        self.entries.clear();
+        self.next_slot = SLOTS;
+
+        for slot in 1..SLOTS {
+            let record = self.read_slot(self.active, slot)?;
+            if record.iter().all(|&b| b == ERASED) {
+                self.next_slot = slot;
+                break;
+            }
+
+            // Revoked, or torn by a power cut
+            let len = record[1] as usize;
+            if record[0] != STATUS_VALID || !matches!(len, 4 | 7 | 10) {
+                continue;
+            }
+            let uid = Vec::from_slice(&record[2..2 + len]).map_err(|_| "UID too long")?;
+            self.entries
+                .push(Entry { uid, slot })
+                .map_err(|_| "Allow-list is full")?;
+        }
+        Ok(())
+    }
+
+    /// Moves the live records to the other sector and makes it the active one.
+    fn compact(&mut self) -> Result<(), &'static str> {
+        let target = 1 - self.active;
+        let sequence = self.sequence.wrapping_add(1);
+
+        self.erase_sector(target)?;
+        self.write_header(target, sequence)?;
+
+        let mut slot = 1;
+        for i in 0..self.entries.len() {
+            let uid = self.entries[i].uid.clone();
+            self.write_record(target, slot, &uid)?;
+            self.entries[i].slot = slot;
+            slot += 1;
+        }
+
+        self.activate(target)?;
+        self.active = target;
+        self.sequence = sequence;
+        self.next_slot = slot;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use embedded_storage::nor_flash::{ErrorType, NorFlashError, NorFlashErrorKind, ReadNorFlash};
+
+    use super::*;
+
+    /// NOR flash in RAM. Programming only clears bits, like on the real chip.
+    #[derive(Clone)]
+    struct Memory {
+        bytes: [u8; STORAGE_SIZE as usize],
+        /// Writes and erases that still complete, the power goes during the next one
+        power: usize,
+        /// Writes and erases started so far
+        steps: usize,
+    }
+
+    impl Memory {
+        fn blank() -> Self {
+            Self {
+                bytes: [ERASED; STORAGE_SIZE as usize],
+                power: usize::MAX,
+                steps: 0,
+            }
+        }
+
+        /// Counts a write or erase, false if the power goes during it.
+        fn step(&mut self) -> bool {
+            self.steps += 1;
+            if self.power == 0 {
+                return false;
+            }
+            self.power -= 1;
+            true
+        }
+    }
+
+    #[derive(Debug)]
+    struct PowerCut;
+
+    impl NorFlashError for PowerCut {
+        fn kind(&self) -> NorFlashErrorKind {
+            NorFlashErrorKind::Other
+        }
+    }
+
+    struct MockFlash<'a>(&'a mut Memory);
+
+    impl ErrorType for MockFlash<'_> {
+        type Error = PowerCut;
+    }
+
+    impl ReadNorFlash for MockFlash<'_> {
+        const READ_SIZE: usize = 1;
+
+        fn read(&mut self, offset: u32, bytes: &mut [u8]) -> Result<(), PowerCut> {
+            let offset = offset as usize;
+            bytes.copy_from_slice(&self.0.bytes[offset..offset + bytes.len()]);
+            Ok(())
+        }
+
+        fn capacity(&self) -> usize {
+            STORAGE_SIZE as usize
+        }
+    }
+
+    impl NorFlash for MockFlash<'_> {
+        const WRITE_SIZE: usize = 1;
+        const ERASE_SIZE: usize = SECTOR_SIZE as usize;
+
+        /// A cut erase only gets through the first half of the range.
+        fn erase(&mut self, from: u32, to: u32) -> Result<(), PowerCut> {
+            let (from, to) = (from as usize, to as usize);
+            let done = self.0.step();
+            let end = if done { to } else { from + (to - from) / 2 };
+            self.0.bytes[from..end].fill(ERASED);
+            if done { Ok(()) } else { Err(PowerCut) }
+        }
+
+        /// A cut write only gets the first half of the bytes out.
+        fn write(&mut self, offset: u32, bytes: &[u8]) -> Result<(), PowerCut> {
+            let offset = offset as usize;
+            let done = self.0.step();
+            let len = if done { bytes.len() } else { bytes.len() / 2 };
+            for (i, &b) in bytes[..len].iter().enumerate() {
+                let cell = &mut self.0.bytes[offset + i];
+                assert_eq!(b & !*cell, 0, "write sets bits at {:#x}", offset + i);
+                *cell &= b;
+            }
+            if done { Ok(()) } else { Err(PowerCut) }
+        }
+    }
+
+    impl MultiwriteNorFlash for MockFlash<'_> {}
+
+    fn uid(n: u8) -> [u8; 4] {
+        [0x04, n, 0xA5, 0x5A]
+    }
+
+    fn load(memory: &mut Memory) -> AllowList<MockFlash<'_>> {
+        AllowList::load(MockFlash(memory), 0).expect("list loads")
+    }
+
+    /// The UIDs on the list, sorted.
+    fn uids(list: &AllowList<MockFlash<'_>>) -> std::vec::Vec<CardUid> {
+        let mut uids: std::vec::Vec<CardUid> = list.entries.iter().map(|e| e.uid.clone()).collect();
+        uids.sort_by(|a, b| a.as_slice().cmp(b));
+        uids
+    }
+
+    /// UIDs 1, 2 and 3 on the list, and a log with one free slot left:
+    /// the rest was used up by enrolling and revoking UID 100.
+    fn churned() -> Memory {
+        let mut memory = Memory::blank();
+        let mut list = load(&mut memory);
+        for n in 1..=3 {
+            list.add(&uid(n)).unwrap();
+        }
+        while list.next_slot < SLOTS - 1 {
+            assert!(list.toggle(&uid(100)).unwrap());
+            assert!(!list.toggle(&uid(100)).unwrap());
+        }
+        drop(list);
+        memory
+    }
+
+    #[derive(Debug, Clone, Copy)]
+    enum Change {
+        Add(u8),
+        Remove(u8),
+    }
+
+    impl Change {
+        fn apply(self, list: &mut AllowList<MockFlash<'_>>) -> Result<(), &'static str> {
+            match self {
+                Change::Add(n) => list.add(&uid(n)),
+                Change::Remove(n) => list.remove(&uid(n)),
+            }
+        }
+    }
+
+    #[test]
+    fn blank_flash_is_formatted() {
+        let mut memory = Memory::blank();
+        let mut list = load(&mut memory);
+        assert!(list.is_empty());
+        list.add(&uid(1)).unwrap();
+        drop(list);
+
+        assert_eq!(&memory.bytes[..4], b"ALST");
+        let list = load(&mut memory);
+        assert!(list.contains(&uid(1)));
+        assert_eq!(list.len(), 1);
+    }
+
+    #[test]
+    fn revoked_uids_stay_revoked() {
+        let mut memory = Memory::blank();
+        let mut list = load(&mut memory);
+        list.add(&uid(1)).unwrap();
+        list.add(&uid(2)).unwrap();
+        list.remove(&uid(1)).unwrap();
+        drop(list);
+
+        let list = load(&mut memory);
+        assert!(!list.contains(&uid(1)));
+        assert!(list.contains(&uid(2)));
+        assert_eq!(list.next_slot, 3);
+    }
+
+    #[test]
+    fn compaction_drops_revoked_records() {
+        let mut memory = churned();
+        let mut list = load(&mut memory);
+        let (active, sequence) = (list.active, list.sequence);
+
+        list.remove(&uid(2)).unwrap();
+        list.add(&uid(10)).unwrap();
+        assert_eq!(list.next_slot, SLOTS);
+        list.add(&uid(11)).unwrap();
+
+        assert_eq!(list.active, 1 - active);
+        assert_eq!(list.sequence, sequence + 1);
+        // UIDs 1, 3 and 10 were copied over, then 11 was appended
+        assert_eq!(list.next_slot, 5);
+        assert_eq!(
+            uids(&list),
+            [uid(1), uid(3), uid(10), uid(11)].map(|u| CardUid::from_slice(&u).unwrap())
+        );
+    }
+
+    #[test]
+    fn reload_after_compaction() {
+        let mut memory = churned();
+        let mut list = load(&mut memory);
+        let active = list.active;
+        list.remove(&uid(2)).unwrap();
+        list.add(&uid(10)).unwrap();
+        list.add(&uid(11)).unwrap();
+        drop(list);
+
+        // Both sectors have a header now, the one with the higher sequence wins
+        let mut list = load(&mut memory);
+        assert_eq!(list.active, 1 - active);
+        assert_eq!(list.next_slot, 5);
+        assert!(!list.contains(&uid(2)) && !list.contains(&uid(100)));
+
+        // Revoking goes to the record's slot in the new sector
+        list.remove(&uid(10)).unwrap();
+        list.add(&uid(12)).unwrap();
+        drop(list);
+
+        let list = load(&mut memory);
+        assert_eq!(
+            uids(&list),
+            [uid(1), uid(3), uid(11), uid(12)].map(|u| CardUid::from_slice(&u).unwrap())
+        );
+    }
+
+    #[test]
+    fn power_cut_at_any_step_leaves_a_usable_list() {
+        let start = churned();
+        // Fills the last slot, compacts, then keeps changing the new sector
+        let changes = [
+            Change::Add(10),
+            Change::Add(11),
+            Change::Remove(2),
+            Change::Add(12),
+        ];
+
+        // The list after each change, and the steps they take all together
+        let mut memory = start.clone();
+        let mut expected = std::vec::Vec::new();
+        let mut list = load(&mut memory);
+        expected.push(uids(&list));
+        for change in changes {
+            change.apply(&mut list).unwrap();
+            expected.push(uids(&list));
+        }
+        drop(list);
+        let steps = memory.steps - start.steps;
+
+        for power in 0..steps {
+            let mut memory = start.clone();
+            memory.power = power;
+            let mut list = load(&mut memory);
+            let done = changes
+                .iter()
+                .take_while(|change| change.apply(&mut list).is_ok())
+                .count();
+            drop(list);
+            assert!(done < changes.len(), "power cut after {power} steps");
+
+            // The change in flight either made it or it didn't
+            memory.power = usize::MAX;
+            let mut list = load(&mut memory);
+            let found = uids(&list);
+            assert!(
+                found == expected[done] || found == expected[done + 1],
+                "power cut after {power} steps: {found:?}"
+            );
+
+            // And the list keeps working
+            list.add(&uid(20)).unwrap();
+            drop(list);
+            let list = load(&mut memory);
+            assert!(list.contains(&uid(20)), "power cut after {power} steps");
+            assert_eq!(list.len(), found.len() + 1);
+        }
+    }
+}
@@ -0,0 +1,302 @@
+//! Append-only CSV log with fixed-width rows, kept on the SD card.
+//!
+//! Every row has the same width, the fields are padded with spaces:
+//!
+//! ```text
+//! timestamp          ,uid                 ,result ,reader
+//! 2026-10-18 08:30:12,A1B2C3D4            ,GRANTED,1
+//! ```
+//!
+//! The file is flushed after each row, so a power cut loses at most the row
+//! being written. Since every row is `ROW_LEN` bytes, a torn row shows up
+//! as a file length that is not a multiple of `ROW_LEN`. Before appending
+//! we seek back to the last complete row, so the next record overwrites
+//! the torn bytes (embedded-sdmmc can't truncate a file to a given length).
+//!
+//! Once the log reaches `MAX_LOG_SIZE` it is copied to the first free
+//! `NAME01.CSV` .. `NAME99.CSV` and `NAME.CSV` starts over. There is no
+//! rename in embedded-sdmmc, hence the copy.
+
+use core::fmt::Write as _;
+
+use embedded_sdmmc::{BlockDevice, Directory, Error, File, Mode, TimeSource};
+use heapless::String;
+
+pub const MAX_LOG_SIZE: u32 = 64 * 1024;
+
+/// Width of a `YYYY-MM-DD hh:mm:ss` timestamp
+pub const TIMESTAMP_WIDTH: usize = 19;
+/// Width of a 10 byte UID in hex
+pub const UID_WIDTH: usize = 20;
+
+/// Base names are at most 6 characters, so the archives fit 8.3 names
+const MAX_BASE_LEN: usize = 6;
+
+/// Pads `fields` to their widths and joins them into a row.
+///
+/// Panics if `ROW_LEN` doesn't match the widths, the commas and the newline:
+/// that is a mistake in the caller's constants, not in the data.
+pub fn pad_row<const ROW_LEN: usize>(fields: &[(&str, usize)]) -> String<ROW_LEN> {
+    let mut row = String::new();
+    for (i, (field, width)) in fields.iter().enumerate() {
+        let separator = if i + 1 == fields.len() { "\n" } else { "," };
+        write!(row, "{:<width$}{}", field, separator, width = width).expect("row too long");
+    }
+    assert_eq!(row.len(), ROW_LEN, "row is not ROW_LEN bytes");
+    row
+}
+
+/// Formats a timestamp for the first column.
+pub fn timestamp(
+    year: u16,
+    month: u8,
+    day: u8,
+    hour: u8,
+    minute: u8,
+    second: u8,
+) -> Result<String<TIMESTAMP_WIDTH>, &'static str> {
+    let mut timestamp = String::new();
+    write!(
+        timestamp,
+        "{:04}-{:02}-{:02} {:02}:{:02}:{:02}",
+        year, month, day, hour, minute, second
+    )
+    .map_err(|_| "Invalid timestamp")?;
+    Ok(timestamp)
+}
+
+/// Formats a UID as upper case hex.
+pub fn uid_hex(uid: &[u8]) -> Result<String<UID_WIDTH>, &'static str> {
+    let mut hex = String::new();
+    for b in uid {
+        write!(hex, "{:02X}", b).map_err(|_| "UID too long")?;
+    }
+    Ok(hex)
+}
+
+pub struct CsvLog<
+    'a,
+    D,
+    T,
+    const DIRS: usize,
+    const FILES: usize,
+    const VOLUMES: usize,
+    const ROW_LEN: usize,
+> where
+    D: BlockDevice,
+    T: TimeSource,
+{
+    dir: Directory<'a, D, T, DIRS, FILES, VOLUMES>,
+    base: &'static str,
+    header: String<ROW_LEN>,
+}
+
+impl<'a, D, T, const DIRS: usize, const FILES: usize, const VOLUMES: usize, const ROW_LEN: usize>
+    CsvLog<'a, D, T, DIRS, FILES, VOLUMES, ROW_LEN>
+where
+    D: BlockDevice,
+    T: TimeSource,
+{
+    /// Opens `<base>.CSV` in `dir`, creating it with `header` as its first row if needed.
+    ///
+    /// Also returns the number of bytes of a torn last row, 0 if the log
+    /// ended cleanly. They get overwritten by the next row.
+    pub fn open(
+        dir: Directory<'a, D, T, DIRS, FILES, VOLUMES>,
+        base: &'static str,
+        header: String<ROW_LEN>,
+    ) -> Result<(Self, u32), Error<D::Error>> {
+        assert!(base.len() <= MAX_BASE_LEN, "log name too long for 8.3");
+        let log = Self { dir, base, header };
+        let file = log.open_log()?;
+        let torn = file.length() % ROW_LEN as u32;
+        file.close()?;
+        Ok((log, torn))
+    }
+
+    /// Name of the current log, `<base>.CSV`.
+    pub fn name(&self) -> String<12> {
+        let mut name = String::new();
+        write!(name, "{}.CSV", self.base).expect("log name too long");
+        name
+    }
+
+    /// Calls `apply` with every complete row, oldest first, header included.
+    pub fn replay(&self, mut apply: impl FnMut(&[u8; ROW_LEN])) -> Result<(), Error<D::Error>> {
+        let file = self.open_log()?;
+        let end = file.offset();
+        file.seek_from_start(0)?;
+
+        let mut row = [0u8; ROW_LEN];
+        let mut offset = 0;
+        while offset + ROW_LEN as u32 <= end {
+            let mut len = 0;
+            while len < ROW_LEN {
+                let read = file.read(&mut row[len..])?;
+                if read == 0 {
+                    break;
+                }
+                len += read;
+            }
+            if len < ROW_LEN {
+                break;
+            }
+            apply(&row);
+            offset += ROW_LEN as u32;
+        }
+        file.close()
+    }
+
+    /// Writes a row and flushes it to the card, rotating the log first if it is full.
+    pub fn append(&self, row: &String<ROW_LEN>) -> Result<(), Error<D::Error>> {
+        self.append_with(row, core::iter::empty)
+    }
+
+    /// Like `append`, but when the log rotates the rows from `carry` go
+    /// into the new log first, after the header. They carry over whatever
+    /// state would otherwise only be found in the archive.
+    ///
+    /// A power cut between emptying the log and writing those rows loses
+    /// them; the archive still has everything.
+    pub fn append_with<I>(
+        &self,
+        row: &String<ROW_LEN>,
+        carry: impl FnOnce() -> I,
+    ) -> Result<(), Error<D::Error>>
+    where
+        I: IntoIterator<Item = String<ROW_LEN>>,
+    {
+        let mut file = self.open_log()?;
+        if file.offset() + ROW_LEN as u32 > MAX_LOG_SIZE {
+            let end = file.offset();
+            if let Some(archive_name) = self.free_archive_name()? {
+                self.archive(&file, end, &archive_name)?;
+                file.close()?;
+                file = self.start_log()?;
+                for carried in carry() {
+                    file.write(carried.as_bytes())?;
+                }
+                file.flush()?;
+                info!(
+                    "Archived {} as {}",
+                    self.name().as_str(),
+                    archive_name.as_str()
+                );
+            } else {
+                warn!(
+                    "No free archive name, {} keeps growing",
+                    self.name().as_str()
+                );
+            }
+        }
+        file.write(row.as_bytes())?;
+        file.close()
+    }
+
+    /// Opens the log positioned after its last complete row.
+    fn open_log(&self) -> Result<File<'_, D, T, DIRS, FILES, VOLUMES>, Error<D::Error>> {
+        let file = self
+            .dir
+            .open_file_in_dir(self.name().as_str(), Mode::ReadWriteCreateOrAppend)?;
+        let length = file.length();
+        if length == 0 {
+            file.write(self.header.as_bytes())?;
+            file.flush()?;
+        }
+
+        let torn = length % ROW_LEN as u32;
+        if torn != 0 {
+            file.seek_from_start(length - torn)?;
+        }
+        Ok(file)
+    }
+
+    /// Empties the log, leaving only the header.
+    fn start_log(&self) -> Result<File<'_, D, T, DIRS, FILES, VOLUMES>, Error<D::Error>> {
+        let file = self
+            .dir
+            .open_file_in_dir(self.name().as_str(), Mode::ReadWriteCreateOrTruncate)?;
+        file.write(self.header.as_bytes())?;
+        file.flush()?;
+        Ok(file)
+    }
+
+    /// Copies the first `end` bytes of the log into a new archive file.
+    ///
+    /// A power cut during the copy leaves the log as it was, it is
+    /// archived again under the next name on the following append.
+    fn archive(
+        &self,
+        log: &File<'_, D, T, DIRS, FILES, VOLUMES>,
+        end: u32,
+        archive_name: &str,
+    ) -> Result<(), Error<D::Error>> {
+        let archive = self
+            .dir
+            .open_file_in_dir(archive_name, Mode::ReadWriteCreate)?;
+        log.seek_from_start(0)?;
+
+        let mut buffer = [0u8; 512];
+        let mut copied = 0;
+        while copied < end {
+            let len = buffer.len().min((end - copied) as usize);
+            let read = log.read(&mut buffer[..len])?;
+            if read == 0 {
+                break;
+            }
+            archive.write(&buffer[..read])?;
+            copied += read as u32;
+        }
+        archive.close()
+    }
+
+    /// First of `<base>01.CSV` .. `<base>99.CSV` that doesn't exist yet.
+    ///
+    /// With all of them taken the log keeps growing, old archives have to
+    /// be removed from the card by hand.
+    fn free_archive_name(&self) -> Result<Option<String<12>>, Error<D::Error>> {
+        for n in 1..=99 {
+            let mut name: String<12> = String::new();
+            write!(name, "{}{:02}.CSV", self.base, n).expect("archive name too long");
+            match self.dir.find_directory_entry(name.as_str()) {
+                Ok(_) => continue,
+                Err(Error::NotFound) => return Ok(Some(name)),
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rows_are_padded() {
+        let row: String<16> = pad_row(&[("ab", 4), ("c", 3), ("", 6)]);
+        assert_eq!(row, "ab  ,c  ,      \n");
+    }
+
+    #[test]
+    #[should_panic(expected = "row is not ROW_LEN bytes")]
+    fn short_row_panics() {
+        let _: String<20> = pad_row(&[("ab", 4)]);
+    }
+
+    #[test]
+    fn timestamps() {
+        assert_eq!(
+            timestamp(2026, 10, 18, 8, 30, 2).unwrap(),
+            "2026-10-18 08:30:02"
+        );
+        assert_eq!(timestamp(20260, 1, 1, 0, 0, 0), Err("Invalid timestamp"));
+    }
+
+    #[test]
+    fn uids() {
+        assert_eq!(uid_hex(&[0xA1, 0x02, 0xC3, 0x0D]).unwrap(), "A102C30D");
+        assert_eq!(uid_hex(&[0; 10]).unwrap().len(), UID_WIDTH);
+        assert_eq!(uid_hex(&[0; 11]), Err("UID too long"));
+    }
+}
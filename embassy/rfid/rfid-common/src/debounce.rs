@@ -0,0 +1,150 @@
+//! Card arrival and removal from repeated scans of the field.
+//!
+//! A card at the edge of the field tends to flicker in and out, so it has
+//! to be seen `ARRIVAL_SCANS` times to arrive and missed `REMOVAL_SCANS`
+//! times to leave.
+
+use heapless::Vec;
+
+pub use crate::allowlist::CardUid;
+
+/// Scans that must see the same UID before it counts as arrived
+pub const ARRIVAL_SCANS: u8 = 2;
+/// Scans in a row without an answer before the card counts as removed
+pub const REMOVAL_SCANS: u8 = 3;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum CardEvent {
+    CardArrived(CardUid),
+    CardRemoved(CardUid),
+}
+
+/// Turns raw scan results into arrival and removal events.
+#[derive(Default)]
+pub struct Debouncer {
+    present: Option<CardUid>,
+    candidate: Option<CardUid>,
+    hits: u8,
+    misses: u8,
+}
+
+impl Debouncer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Feeds the UID seen by one scan, if any. Returns the events it caused.
+    pub fn update(&mut self, seen: Option<CardUid>) -> Vec<CardEvent, 2> {
+        let mut events = Vec::new();
+
+        match seen {
+            Some(uid) if self.present.as_ref() == Some(&uid) => {
+                self.misses = 0;
+                self.candidate = None;
+                self.hits = 0;
+            }
+            Some(uid) => {
+                if self.candidate.as_ref() == Some(&uid) {
+                    self.hits += 1;
+                } else {
+                    self.candidate = Some(uid.clone());
+                    self.hits = 1;
+                }
+
+                if self.hits >= ARRIVAL_SCANS {
+                    // A different card took the place of the old one
+                    if let Some(old) = self.present.take() {
+                        let _ = events.push(CardEvent::CardRemoved(old));
+                    }
+                    let _ = events.push(CardEvent::CardArrived(uid.clone()));
+                    self.present = Some(uid);
+                    self.candidate = None;
+                    self.hits = 0;
+                    self.misses = 0;
+                }
+            }
+            None => {
+                self.candidate = None;
+                self.hits = 0;
+
+                if self.present.is_some() {
+                    self.misses += 1;
+                    if self.misses >= REMOVAL_SCANS
+                        && let Some(old) = self.present.take()
+                    {
+                        let _ = events.push(CardEvent::CardRemoved(old));
+                        self.misses = 0;
+                    }
+                }
+            }
+        }
+        events
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn card(n: u8) -> Option<CardUid> {
+        Some(CardUid::from_slice(&[n, 0x11, 0x22, 0x33]).unwrap())
+    }
+
+    fn arrived(n: u8) -> CardEvent {
+        CardEvent::CardArrived(card(n).unwrap())
+    }
+
+    fn removed(n: u8) -> CardEvent {
+        CardEvent::CardRemoved(card(n).unwrap())
+    }
+
+    /// Feeds `scans` and collects every event they caused.
+    fn run(debouncer: &mut Debouncer, scans: &[Option<CardUid>]) -> std::vec::Vec<CardEvent> {
+        scans
+            .iter()
+            .flat_map(|seen| debouncer.update(seen.clone()))
+            .collect()
+    }
+
+    #[test]
+    fn card_arrives_on_the_second_scan() {
+        let mut debouncer = Debouncer::new();
+        assert!(debouncer.update(card(1)).is_empty());
+        assert_eq!(debouncer.update(card(1)), [arrived(1)]);
+        assert!(run(&mut debouncer, &[card(1), card(1)]).is_empty());
+    }
+
+    #[test]
+    fn flicker_at_the_edge_of_the_field() {
+        let mut debouncer = Debouncer::new();
+        assert!(run(&mut debouncer, &[card(1), None, card(1), None]).is_empty());
+
+        // Once present, a few missed scans don't remove it
+        assert_eq!(run(&mut debouncer, &[card(1), card(1)]), [arrived(1)]);
+        assert!(run(&mut debouncer, &[None, None, card(1), None, None]).is_empty());
+    }
+
+    #[test]
+    fn card_leaves_after_three_missed_scans() {
+        let mut debouncer = Debouncer::new();
+        run(&mut debouncer, &[card(1), card(1)]);
+        assert!(run(&mut debouncer, &[None, None]).is_empty());
+        assert_eq!(debouncer.update(None), [removed(1)]);
+        assert!(run(&mut debouncer, &[None, None, None]).is_empty());
+    }
+
+    #[test]
+    fn card_swapped_for_another() {
+        let mut debouncer = Debouncer::new();
+        run(&mut debouncer, &[card(1), card(1)]);
+        assert!(debouncer.update(card(2)).is_empty());
+        assert_eq!(debouncer.update(card(2)), [removed(1), arrived(2)]);
+    }
+
+    #[test]
+    fn two_cards_taking_turns_never_arrive() {
+        let mut debouncer = Debouncer::new();
+        assert!(run(&mut debouncer, &[card(1), card(2), card(1), card(2)]).is_empty());
+    }
+}
@@ -0,0 +1,111 @@
+//! Reads a whole MIFARE Classic 1K card into RAM.
+
+use heapless::Vec;
+use mfrc522::Uid;
+
+use crate::keyring::{self, KeyRing, SectorKey};
+use crate::pcd::KeyType;
+use crate::reader::CardReader;
+use crate::retry;
+
+pub const SECTORS: usize = 16;
+pub const BLOCKS: usize = SECTORS * 4;
+
+/// What happened while reading a single block.
+#[derive(Debug, Clone, Copy)]
+pub struct BlockInfo {
+    /// The key that authenticated the block's sector
+    pub key: Option<SectorKey>,
+    pub error: Option<&'static str>,
+}
+
+/// A 1K card image plus per-block metadata.
+///
+/// Blocks that could not be read are left as zeros.
+pub struct CardDump {
+    pub uid: Vec<u8, 10>,
+    pub data: [u8; BLOCKS * 16],
+    pub blocks: [BlockInfo; BLOCKS],
+    /// Sectors with at least one block we could not read
+    pub failed: [bool; SECTORS],
+}
+
+impl CardDump {
+    pub fn new(uid: &[u8]) -> Self {
+        Self {
+            uid: Vec::from_slice(uid).expect("UID is at most 10 bytes"),
+            data: [0; BLOCKS * 16],
+            blocks: [BlockInfo {
+                key: None,
+                error: None,
+            }; BLOCKS],
+            failed: [false; SECTORS],
+        }
+    }
+
+    pub fn block(&self, abs_block: usize) -> &[u8] {
+        &self.data[abs_block * 16..abs_block * 16 + 16]
+    }
+}
+
+/// Reads every sector the key ring can open.
+///
+/// Key A always reads back as zeros, so the key that opened the sector is
+/// patched into the trailer. That's what other MIFARE tools expect in a
+/// `.mfd` file. A trailer that could not be read stays zeros, with no key
+/// in it.
+pub fn dump_card<R: CardReader, const N: usize>(
+    uid: &Uid,
+    keyring: &KeyRing<N>,
+    rfid: &mut R,
+) -> Result<CardDump, &'static str> {
+    let mut dump = CardDump::new(uid.as_bytes());
+
+    for sector in 0..SECTORS as u8 {
+        let block_offset = sector * 4;
+
+        let Some(sector_key) = keyring::find_sector_key(uid, block_offset + 3, keyring, rfid)?
+        else {
+            for abs_block in block_offset..block_offset + 4 {
+                dump.blocks[abs_block as usize].error = Some("No key opened the sector");
+            }
+            dump.failed[sector as usize] = true;
+            continue;
+        };
+
+        for abs_block in block_offset..block_offset + 4 {
+            dump.blocks[abs_block as usize].key = Some(sector_key);
+        }
+
+        for abs_block in block_offset..block_offset + 4 {
+            match rfid.mf_read(abs_block) {
+                Ok(data) => {
+                    let start = abs_block as usize * 16;
+                    dump.data[start..start + 16].copy_from_slice(&data);
+                }
+                Err(_) => {
+                    // The card drops out of the authenticated state,
+                    // so the rest of the sector can't be read either
+                    for abs_block in abs_block..block_offset + 4 {
+                        dump.blocks[abs_block as usize].error = Some("Read failed");
+                    }
+                    dump.failed[sector as usize] = true;
+                    retry::reselect(uid, rfid).map_err(|_| "Card lost")?;
+                    break;
+                }
+            }
+        }
+
+        let trailer_block = block_offset as usize + 3;
+        if dump.blocks[trailer_block].error.is_some() {
+            continue;
+        }
+        let trailer = trailer_block * 16;
+        match sector_key.key_type {
+            KeyType::A => dump.data[trailer..trailer + 6].copy_from_slice(&sector_key.key),
+            KeyType::B => dump.data[trailer + 10..trailer + 16].copy_from_slice(&sector_key.key),
+        }
+    }
+
+    Ok(dump)
+}
@@ -0,0 +1,86 @@
+//! Errors from talking to a MIFARE Classic card.
+//!
+//! Every error keeps the block (or sector, for authentication) it happened
+//! on, so a log line is enough to tell which part of the card misbehaved.
+
+use core::fmt;
+
+/// What we were doing when the `mfrc522` driver failed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Operation {
+    Auth,
+    Read,
+    Write,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum RfidError {
+    /// The card did not answer in time
+    Timeout { block: u8 },
+    /// The answer arrived with a bad CRC
+    Crc { block: u8 },
+    /// More than one card answered
+    Collision { block: u8 },
+    /// The card refused the command
+    Nak { block: u8 },
+    /// None of the keys we tried opened the sector
+    AuthFailed { sector: u8 },
+    /// The card did not accept the data, usually because the access bits forbid it
+    WriteFailed { block: u8 },
+    /// SPI communication with the MFRC522 failed
+    Transport { block: u8 },
+    /// Anything else the MFRC522 flagged: parity, framing, buffer overflow
+    Protocol { block: u8 },
+    /// We refused to write a trailer that would lock the sector
+    InvalidTrailer { sector: u8, reason: &'static str },
+}
+
+impl RfidError {
+    pub fn from_mfrc522<E>(err: mfrc522::Error<E>, operation: Operation, block: u8) -> Self {
+        use mfrc522::Error;
+
+        match (err, operation) {
+            (Error::Comm(_), _) => Self::Transport { block },
+            // The driver reports a wrong key as a timeout, so this has to
+            // come before the timeout arm or a bad key gets retried
+            (_, Operation::Auth) => Self::AuthFailed { sector: block / 4 },
+            (Error::Timeout, _) => Self::Timeout { block },
+            (Error::Crc, _) => Self::Crc { block },
+            (Error::Collision, _) => Self::Collision { block },
+            (Error::Nak, Operation::Write) => Self::WriteFailed { block },
+            (Error::Nak, _) => Self::Nak { block },
+            _ => Self::Protocol { block },
+        }
+    }
+
+    /// Errors a weak field can cause, which are worth another try.
+    pub fn is_transient(&self) -> bool {
+        matches!(
+            self,
+            Self::Timeout { .. }
+                | Self::Crc { .. }
+                | Self::Collision { .. }
+                | Self::Protocol { .. }
+        )
+    }
+}
+
+impl fmt::Display for RfidError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Timeout { block } => write!(f, "timeout at block {}", block),
+            Self::Crc { block } => write!(f, "CRC error at block {}", block),
+            Self::Collision { block } => write!(f, "collision at block {}", block),
+            Self::Nak { block } => write!(f, "NAK at block {}", block),
+            Self::AuthFailed { sector } => write!(f, "authentication failed for sector {}", sector),
+            Self::WriteFailed { block } => write!(f, "write rejected at block {}", block),
+            Self::Transport { block } => write!(f, "SPI error at block {}", block),
+            Self::Protocol { block } => write!(f, "protocol error at block {}", block),
+            Self::InvalidTrailer { sector, reason } => {
+                write!(f, "invalid trailer for sector {}: {}", sector, reason)
+            }
+        }
+    }
+}
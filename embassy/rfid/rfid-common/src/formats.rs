@@ -0,0 +1,444 @@
+//! Card images in the formats of other MIFARE tools.
+//!
+//! Flipper Zero `.nfc` is a text file with a few header lines and one line
+//! per block. Bytes it could not read are written as `??`:
+//!
+//! ```text
+//! Filetype: Flipper NFC device
+//! Version: 4
+//! Device type: Mifare Classic
+//! UID: DE AD BE EF
+//! ATQA: 00 04
+//! SAK: 08
+//! Mifare Classic type: 1K
+//! Data format version: 2
+//! Block 0: DE AD BE EF 22 08 04 00 62 63 64 65 66 67 68 69
+//! Block 1: 00 00 00 00 00 00 00 00 00 00 00 00 00 00 00 00
+//! ...
+//! ```
+//!
+//! A Proxmark `.eml` file is just one line of 32 hex digits per block, with
+//! no header and no way to mark unknown data.
+//!
+//! Both work on a plain image (16 bytes per block, in block order) of a
+//! Classic Mini, 1K or 4K. Bytes that could not be read are marked in a
+//! separate `known` list, one `KnownBytes` mask per block; they are written
+//! as `??` to `.nfc` files and as zeros to `.eml` files. Flipper often knows
+//! the access bits and key B of a trailer but not key A, so the mask is per
+//! byte rather than per block.
+
+use core::fmt::Write as _;
+
+use embedded_io::Write;
+use heapless::{String, Vec};
+
+pub const BLOCKS_MINI: usize = 20;
+pub const BLOCKS_1K: usize = 64;
+pub const BLOCKS_4K: usize = 256;
+
+/// Which bytes of a block are known, bit `i` for byte `i`.
+pub type KnownBytes = u16;
+pub const ALL_KNOWN: KnownBytes = 0xFFFF;
+pub const NONE_KNOWN: KnownBytes = 0;
+
+/// Why a file could not be written.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum WriteError<E> {
+    /// The image is not the size of a Mini, 1K or 4K
+    UnknownSize,
+    Io(E),
+}
+
+impl<E> From<E> for WriteError<E> {
+    fn from(e: E) -> Self {
+        Self::Io(e)
+    }
+}
+
+/// What a `.nfc` file says about the card besides the blocks.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FlipperCard {
+    pub uid: Vec<u8, 10>,
+    pub blocks: usize,
+}
+
+fn classic_type(blocks: usize) -> Option<&'static str> {
+    match blocks {
+        BLOCKS_MINI => Some("MINI"),
+        BLOCKS_1K => Some("1K"),
+        BLOCKS_4K => Some("4K"),
+        _ => None,
+    }
+}
+
+fn write_hex<const N: usize>(line: &mut String<N>, data: &[u8], separator: &str) {
+    write_known_hex(line, data, ALL_KNOWN, separator, "");
+}
+
+/// Like `write_hex`, with `unknown` in place of the bytes `known` leaves out.
+fn write_known_hex<const N: usize>(
+    line: &mut String<N>,
+    data: &[u8],
+    known: KnownBytes,
+    separator: &str,
+    unknown: &str,
+) {
+    for (i, &d) in data.iter().enumerate() {
+        if i > 0 {
+            line.push_str(separator).expect("line too long");
+        }
+        if known & (1 << i) != 0 {
+            write!(line, "{:02X}", d).expect("line too long");
+        } else {
+            line.push_str(unknown).expect("line too long");
+        }
+    }
+}
+
+/// SAK and ATQA from block 0, where the manufacturer put them after the UID.
+///
+/// Block 0 stores the ATQA least significant byte first, Flipper the other
+/// way around.
+fn sak_and_atqa(uid: &[u8], block0: &[u8], blocks: usize) -> (u8, [u8; 2]) {
+    // 4 byte UIDs are followed by their BCC
+    let offset = if uid.len() == 4 { 5 } else { uid.len() };
+    match block0.get(offset..offset + 3) {
+        Some(&[sak, atqa0, atqa1]) if block0[..uid.len()] == *uid => (sak, [atqa1, atqa0]),
+        _ if blocks == BLOCKS_4K => (0x18, [0x00, 0x02]),
+        _ if blocks == BLOCKS_MINI => (0x09, [0x00, 0x04]),
+        _ => (0x08, [0x00, 0x04]),
+    }
+}
+
+/// Writes a Flipper Zero `.nfc` file, version 4.
+///
+/// `data` is the whole image; `known(block)` tells which bytes of a block were read.
+pub fn write_flipper<W: Write>(
+    uid: &[u8],
+    data: &[u8],
+    known: impl Fn(usize) -> KnownBytes,
+    out: &mut W,
+) -> Result<(), WriteError<W::Error>> {
+    let blocks = data.len() / 16;
+    let classic_type = classic_type(blocks)
+        .filter(|_| data.len().is_multiple_of(16))
+        .ok_or(WriteError::UnknownSize)?;
+    let (sak, atqa) = sak_and_atqa(uid, &data[..16], blocks);
+
+    let mut line: String<96> = String::new();
+    out.write_all(b"Filetype: Flipper NFC device\nVersion: 4\n")?;
+    out.write_all(b"# Device type can be ISO14443-3A, ISO14443-3B, ISO14443-4A, NTAG/Ultralight, Mifare Classic, Mifare DESFire\n")?;
+    out.write_all(b"Device type: Mifare Classic\n# UID is common for all formats\nUID: ")?;
+    write_hex(&mut line, uid, " ");
+    line.push('\n').expect("line too long");
+    out.write_all(line.as_bytes())?;
+
+    line.clear();
+    line.push_str("# ISO14443-3A specific data\nATQA: ")
+        .expect("line too long");
+    write_hex(&mut line, &atqa, " ");
+    write!(line, "\nSAK: {:02X}\n", sak).expect("line too long");
+    out.write_all(line.as_bytes())?;
+
+    line.clear();
+    write!(
+        line,
+        "# Mifare Classic specific data\nMifare Classic type: {}\nData format version: 2\n",
+        classic_type
+    )
+    .expect("line too long");
+    out.write_all(line.as_bytes())?;
+    out.write_all(b"# Mifare Classic blocks, '??' means unknown data\n")?;
+
+    for (block, bytes) in data.chunks(16).enumerate() {
+        line.clear();
+        write!(line, "Block {}: ", block).expect("line too long");
+        write_known_hex(&mut line, bytes, known(block), " ", "??");
+        line.push('\n').expect("line too long");
+        out.write_all(line.as_bytes())?;
+    }
+    Ok(())
+}
+
+/// Writes a Proxmark `.eml` file. Unknown bytes are written as zeros.
+pub fn write_eml<W: Write>(
+    data: &[u8],
+    known: impl Fn(usize) -> KnownBytes,
+    out: &mut W,
+) -> Result<(), W::Error> {
+    let mut line: String<40> = String::new();
+    for (block, bytes) in data.chunks(16).enumerate() {
+        line.clear();
+        write_known_hex(&mut line, bytes, known(block), "", "00");
+        line.push('\n').expect("line too long");
+        out.write_all(line.as_bytes())?;
+    }
+    Ok(())
+}
+
+fn parse_byte(text: &str) -> Option<u8> {
+    if text.len() != 2 {
+        return None;
+    }
+    u8::from_str_radix(text, 16).ok()
+}
+
+/// Reads a Flipper Zero `.nfc` file of a MIFARE Classic into `data`.
+///
+/// `??` bytes are zeroed and left out of their block's `known` mask.
+/// `data` and `known` have to be large enough for the card type in the file.
+pub fn parse_flipper(
+    text: &str,
+    data: &mut [u8],
+    known: &mut [KnownBytes],
+) -> Result<FlipperCard, &'static str> {
+    let mut filetype = false;
+    let mut classic = false;
+    let mut uid: Option<Vec<u8, 10>> = None;
+    let mut blocks = None;
+    let mut seen = 0;
+
+    known.fill(NONE_KNOWN);
+    data.fill(0);
+
+    for line in text.lines() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let Some((key, value)) = line.split_once(':') else {
+            return Err("Line without a key");
+        };
+        let value = value.trim();
+
+        match key {
+            "Filetype" => filetype = value == "Flipper NFC device",
+            "Device type" => classic = value == "Mifare Classic",
+            "UID" => {
+                let mut bytes = Vec::new();
+                for byte in value.split_whitespace() {
+                    let byte = parse_byte(byte).ok_or("Bad UID")?;
+                    bytes.push(byte).map_err(|_| "UID too long")?;
+                }
+                if ![4, 7, 10].contains(&bytes.len()) {
+                    return Err("UID must be 4, 7 or 10 bytes");
+                }
+                uid = Some(bytes);
+            }
+            "Mifare Classic type" => {
+                let count = match value {
+                    "MINI" => BLOCKS_MINI,
+                    "1K" => BLOCKS_1K,
+                    "4K" => BLOCKS_4K,
+                    _ => return Err("Unknown Mifare Classic type"),
+                };
+                if data.len() < count * 16 || known.len() < count {
+                    return Err("Image too small for this card type");
+                }
+                blocks = Some(count);
+            }
+            _ if key.starts_with("Block ") => {
+                let count = blocks.ok_or("Block before the Mifare Classic type")?;
+                let block: usize = key["Block ".len()..]
+                    .parse()
+                    .map_err(|_| "Bad block number")?;
+                if block >= count {
+                    return Err("Block number out of range");
+                }
+
+                let mut bytes = [0u8; 16];
+                let mut mask = NONE_KNOWN;
+                let mut len = 0;
+                for byte in value.split_whitespace() {
+                    if len == 16 {
+                        return Err("Block longer than 16 bytes");
+                    }
+                    if byte != "??" {
+                        bytes[len] = parse_byte(byte).ok_or("Bad block data")?;
+                        mask |= 1 << len;
+                    }
+                    len += 1;
+                }
+                if len != 16 {
+                    return Err("Block shorter than 16 bytes");
+                }
+
+                data[block * 16..block * 16 + 16].copy_from_slice(&bytes);
+                known[block] = mask;
+                seen += 1;
+            }
+            // Version, ATQA, SAK and anything newer versions add
+            _ => {}
+        }
+    }
+
+    if !filetype {
+        return Err("Not a Flipper NFC file");
+    }
+    if !classic {
+        return Err("Not a Mifare Classic");
+    }
+    let blocks = blocks.ok_or("Missing Mifare Classic type")?;
+    if seen != blocks {
+        return Err("Missing blocks");
+    }
+    Ok(FlipperCard {
+        uid: uid.ok_or("Missing UID")?,
+        blocks,
+    })
+}
+
+/// Reads a Proxmark `.eml` file into `data`, returning the number of blocks.
+pub fn parse_eml(text: &str, data: &mut [u8]) -> Result<usize, &'static str> {
+    let mut blocks = 0;
+    for line in text.lines() {
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        if line.len() != 32 || !line.is_ascii() {
+            return Err("Block lines must be 32 hex digits");
+        }
+        let block = data
+            .get_mut(blocks * 16..blocks * 16 + 16)
+            .ok_or("Image too small for this file")?;
+        for (i, byte) in block.iter_mut().enumerate() {
+            *byte = parse_byte(&line[i * 2..i * 2 + 2]).ok_or("Bad block data")?;
+        }
+        blocks += 1;
+    }
+
+    if classic_type(blocks).is_none() {
+        return Err("Not the size of a Mini, 1K or 4K");
+    }
+    Ok(blocks)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The same 1K card in both formats. Sector 15 could not be read, and
+    // block 8 holds a value block of 100.
+    const SAMPLE_NFC: &str = include_str!("../samples/card-1k.nfc");
+    const SAMPLE_EML: &str = include_str!("../samples/card-1k.eml");
+
+    const UID: [u8; 4] = [0xDE, 0xAD, 0xBE, 0xEF];
+
+    fn parse_sample() -> ([u8; BLOCKS_1K * 16], [KnownBytes; BLOCKS_1K]) {
+        let mut image = [0u8; BLOCKS_1K * 16];
+        let mut known = [NONE_KNOWN; BLOCKS_1K];
+        let card = parse_flipper(SAMPLE_NFC, &mut image, &mut known).unwrap();
+        assert_eq!(card.uid, UID);
+        assert_eq!(card.blocks, BLOCKS_1K);
+        (image, known)
+    }
+
+    #[test]
+    fn nfc_sample_parses() {
+        let (image, known) = parse_sample();
+
+        assert_eq!(image[..4], UID);
+        assert_eq!(image[8 * 16..8 * 16 + 4], 100i32.to_le_bytes());
+        // '??' marks the blocks of sector 15 as unknown
+        for (block, &known) in known.iter().enumerate() {
+            let expected = if block < 60 { ALL_KNOWN } else { NONE_KNOWN };
+            assert_eq!(known, expected, "block {block}");
+        }
+    }
+
+    #[test]
+    fn nfc_round_trip() {
+        let (image, known) = parse_sample();
+
+        let mut nfc = std::vec::Vec::new();
+        write_flipper(&UID, &image, |block| known[block], &mut nfc).unwrap();
+        assert_eq!(std::string::String::from_utf8(nfc).unwrap(), SAMPLE_NFC);
+    }
+
+    #[test]
+    fn nfc_converts_to_eml() {
+        let (image, known) = parse_sample();
+
+        let mut eml = std::vec::Vec::new();
+        write_eml(&image, |block| known[block], &mut eml).unwrap();
+        assert_eq!(std::string::String::from_utf8(eml).unwrap(), SAMPLE_EML);
+    }
+
+    #[test]
+    fn eml_round_trip() {
+        let mut image = [0u8; BLOCKS_1K * 16];
+        assert_eq!(parse_eml(SAMPLE_EML, &mut image), Ok(BLOCKS_1K));
+
+        let mut eml = std::vec::Vec::new();
+        write_eml(&image, |_| ALL_KNOWN, &mut eml).unwrap();
+        assert_eq!(std::string::String::from_utf8(eml).unwrap(), SAMPLE_EML);
+    }
+
+    #[test]
+    fn eml_sample_matches_nfc_sample() {
+        let (nfc_image, _) = parse_sample();
+
+        let mut eml_image = [0u8; BLOCKS_1K * 16];
+        parse_eml(SAMPLE_EML, &mut eml_image).unwrap();
+        assert_eq!(eml_image, nfc_image);
+    }
+
+    #[test]
+    fn nfc_with_a_missing_block_is_refused() {
+        let truncated = &SAMPLE_NFC[..SAMPLE_NFC.find("Block 63").unwrap()];
+        let mut image = [0u8; BLOCKS_1K * 16];
+        let mut known = [NONE_KNOWN; BLOCKS_1K];
+        assert!(parse_flipper(truncated, &mut image, &mut known).is_err());
+    }
+
+    #[test]
+    fn nfc_keeps_the_known_bytes_of_a_trailer() {
+        // Key A unknown, access bits and key B read
+        let sample = SAMPLE_NFC.replace(
+            "Block 59: FF FF FF FF FF FF FF 07 80 69 FF FF FF FF FF FF",
+            "Block 59: ?? ?? ?? ?? ?? ?? FF 07 80 69 A0 A1 A2 ?? A4 A5",
+        );
+        let mut image = [0u8; BLOCKS_1K * 16];
+        let mut known = [NONE_KNOWN; BLOCKS_1K];
+        parse_flipper(&sample, &mut image, &mut known).unwrap();
+
+        assert_eq!(known[59], 0b1101_1111_1100_0000);
+        assert_eq!(
+            image[59 * 16..60 * 16],
+            [
+                0, 0, 0, 0, 0, 0, 0xFF, 0x07, 0x80, 0x69, 0xA0, 0xA1, 0xA2, 0, 0xA4, 0xA5
+            ]
+        );
+
+        let mut nfc = std::vec::Vec::new();
+        write_flipper(&UID, &image, |block| known[block], &mut nfc).unwrap();
+        assert_eq!(std::string::String::from_utf8(nfc).unwrap(), sample);
+
+        let mut eml = std::vec::Vec::new();
+        write_eml(&image, |block| known[block], &mut eml).unwrap();
+        let eml = std::string::String::from_utf8(eml).unwrap();
+        assert_eq!(
+            eml.lines().nth(59),
+            Some("000000000000FF078069A0A1A200A4A5")
+        );
+    }
+
+    #[test]
+    fn image_of_an_unknown_size_is_refused() {
+        let mut nfc = std::vec::Vec::new();
+        assert_eq!(
+            write_flipper(&UID, &[0u8; 48 * 16], |_| ALL_KNOWN, &mut nfc),
+            Err(WriteError::UnknownSize)
+        );
+        assert!(nfc.is_empty());
+    }
+
+    #[test]
+    fn eml_of_63_blocks_is_refused() {
+        let short = &SAMPLE_EML[..SAMPLE_EML.len() - 33];
+        let mut image = [0u8; BLOCKS_1K * 16];
+        assert!(parse_eml(short, &mut image).is_err());
+    }
+}
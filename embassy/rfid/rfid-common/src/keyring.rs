@@ -0,0 +1,104 @@
+//! Dictionary authentication.
+//!
+//! Instead of assuming every sector uses `FF FF FF FF FF FF`, we try a list
+//! of keys (factory defaults, the well-known transport keys and any keys of
+//! our own) as both key A and key B until one of them opens the sector.
+
+use heapless::Vec;
+use mfrc522::{MifareKey, Uid};
+
+use crate::pcd::KeyType;
+use crate::reader::CardReader;
+use crate::retry;
+
+/// Keys that are commonly found on cards in the wild.
+pub const DEFAULT_KEYS: [MifareKey; 10] = [
+    [0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF], // Factory default
+    [0xA0, 0xA1, 0xA2, 0xA3, 0xA4, 0xA5], // MAD key A
+    [0xB0, 0xB1, 0xB2, 0xB3, 0xB4, 0xB5], // MAD key B
+    [0xD3, 0xF7, 0xD3, 0xF7, 0xD3, 0xF7], // NFC Forum (NDEF) key A
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0x00], // Blank
+    [0xAA, 0xBB, 0xCC, 0xDD, 0xEE, 0xFF],
+    [0x4D, 0x3A, 0x99, 0xC3, 0x51, 0xDD],
+    [0x1A, 0x98, 0x2C, 0x7E, 0x45, 0x9A],
+    [0x71, 0x4C, 0x5C, 0x88, 0x6E, 0x97],
+    [0x58, 0x7E, 0xE5, 0xF9, 0x35, 0x0F],
+];
+
+/// The key that opened a sector.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SectorKey {
+    pub key_type: KeyType,
+    pub key: MifareKey,
+}
+
+/// An ordered list of keys to try.
+pub struct KeyRing<const N: usize> {
+    keys: Vec<MifareKey, N>,
+}
+
+impl<const N: usize> KeyRing<N> {
+    pub fn new() -> Self {
+        Self { keys: Vec::new() }
+    }
+
+    /// A key ring that starts with [`DEFAULT_KEYS`].
+    pub fn with_defaults() -> Self {
+        let mut ring = Self::new();
+        for key in DEFAULT_KEYS.iter() {
+            // Silently stop if N is smaller than the default list
+            if ring.add(*key).is_err() {
+                break;
+            }
+        }
+        ring
+    }
+
+    /// Adds a key to the end of the ring, ignoring duplicates.
+    pub fn add(&mut self, key: MifareKey) -> Result<(), &'static str> {
+        if self.keys.contains(&key) {
+            return Ok(());
+        }
+        self.keys.push(key).map_err(|_| "Key ring is full")
+    }
+
+    pub fn keys(&self) -> &[MifareKey] {
+        &self.keys
+    }
+}
+
+impl<const N: usize> Default for KeyRing<N> {
+    fn default() -> Self {
+        Self::with_defaults()
+    }
+}
+
+/// Tries every key in the ring as key A and then as key B.
+///
+/// `trailer_block` is the last block of the sector. On success the sector
+/// is left authenticated, so it can be read right away. When no key opens
+/// it the card is still selected and `None` comes back; an error means the
+/// card is gone.
+pub fn find_sector_key<R: CardReader, const N: usize>(
+    uid: &Uid,
+    trailer_block: u8,
+    keyring: &KeyRing<N>,
+    rfid: &mut R,
+) -> Result<Option<SectorKey>, &'static str> {
+    for key_type in [KeyType::A, KeyType::B] {
+        for key in keyring.keys() {
+            if rfid
+                .mf_authenticate_with(uid, trailer_block, key_type, key)
+                .is_ok()
+            {
+                return Ok(Some(SectorKey {
+                    key_type,
+                    key: *key,
+                }));
+            }
+            // A failed authentication drops the card out of the active state
+            retry::reselect(uid, rfid).map_err(|_| "Card lost")?;
+        }
+    }
+    Ok(None)
+}
@@ -0,0 +1,197 @@
+//! Memory maps of the card types we can dump.
+//!
+//! The SAK byte returned by `select` tells the MIFARE Classic sizes apart.
+//! Ultralight and NTAG21x share a SAK of `0x00`, so for those we ask the
+//! card for its version to learn how many pages it has.
+//!
+//! ```text
+//! Classic Mini: sectors 0..5, 4 blocks each
+//! Classic 1K:   sectors 0..16, 4 blocks each
+//! Classic 4K:   sectors 0..32, 4 blocks each, then sectors 32..40, 16 blocks each
+//! Ultralight:   4-byte pages, no authentication
+//! ```
+
+use mfrc522::{Initialized, Mfrc522, Type};
+
+use crate::retry;
+use crate::value::crc_a;
+
+const GET_VERSION: u8 = 0x60;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Layout {
+    Classic {
+        name: &'static str,
+        sectors: u8,
+    },
+    Ultralight {
+        name: &'static str,
+        pages: u8,
+        /// Pages at the end holding configuration, password and PACK
+        config_pages: u8,
+    },
+}
+
+impl Layout {
+    pub const CLASSIC_MINI: Self = Self::Classic {
+        name: "MIFARE Classic Mini",
+        sectors: 5,
+    };
+    pub const CLASSIC_1K: Self = Self::Classic {
+        name: "MIFARE Classic 1K",
+        sectors: 16,
+    };
+    pub const CLASSIC_4K: Self = Self::Classic {
+        name: "MIFARE Classic 4K",
+        sectors: 40,
+    };
+    pub const ULTRALIGHT: Self = Self::Ultralight {
+        name: "MIFARE Ultralight",
+        pages: 16,
+        config_pages: 0,
+    };
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Classic { name, .. } | Self::Ultralight { name, .. } => name,
+        }
+    }
+
+    /// Ultralight and NTAG21x by the storage size byte of GET_VERSION.
+    fn from_storage_size(size: u8) -> Option<Self> {
+        let (name, pages, config_pages) = match size {
+            0x0B => ("MIFARE Ultralight EV1 (MF0UL11)", 20, 4),
+            0x0E => ("MIFARE Ultralight EV1 (MF0UL21)", 41, 5),
+            0x0F => ("NTAG213", 45, 5),
+            0x11 => ("NTAG215", 135, 5),
+            0x13 => ("NTAG216", 231, 5),
+            _ => return None,
+        };
+        Some(Self::Ultralight {
+            name,
+            pages,
+            config_pages,
+        })
+    }
+}
+
+/// Absolute number of the first block of a Classic sector.
+pub fn first_block(sector: u8) -> u8 {
+    if sector < 32 {
+        sector * 4
+    } else {
+        128 + (sector - 32) * 16
+    }
+}
+
+/// Classic sector a block belongs to.
+pub fn sector_of(block: u8) -> u8 {
+    if block < 128 {
+        block / 4
+    } else {
+        32 + (block - 128) / 16
+    }
+}
+
+pub fn blocks_in_sector(sector: u8) -> u8 {
+    if sector < 32 { 4 } else { 16 }
+}
+
+/// Absolute number of the sector trailer. For sector 39 of a 4K card the
+/// first block plus the block count is 256, so the last block is found
+/// without going past it.
+pub fn trailer_block(sector: u8) -> u8 {
+    first_block(sector) + (blocks_in_sector(sector) - 1)
+}
+
+/// Labels a Classic block. The trailer is always the last block of its sector.
+pub fn get_block_type(sector: u8, rel_block: u8) -> &'static str {
+    match rel_block {
+        0 if sector == 0 => "MFD",
+        _ if rel_block == blocks_in_sector(sector) - 1 => "TRAILER",
+        _ => "DATA",
+    }
+}
+
+/// Labels an Ultralight or NTAG page.
+pub fn get_page_type(layout: &Layout, page: u8) -> &'static str {
+    let Layout::Ultralight {
+        pages,
+        config_pages,
+        ..
+    } = *layout
+    else {
+        return "UNKNOWN";
+    };
+
+    match page {
+        0 | 1 => "UID",
+        2 => "UID/LOCK",
+        3 => "OTP/CC",
+        _ if page + config_pages < pages => "DATA",
+        _ if config_pages == 5 && page == pages - 5 => "DYN LOCK",
+        _ if page == pages - 4 => "CFG0",
+        _ if page == pages - 3 => "CFG1",
+        _ if page == pages - 2 => "PWD",
+        _ => "PACK",
+    }
+}
+
+/// Works out the memory map of the selected card.
+///
+/// A plain Ultralight doesn't know GET_VERSION and drops back to idle, so
+/// the card has to be selected again before it can be read.
+pub fn detect<E, COMM>(
+    uid: &mfrc522::Uid,
+    rfid: &mut Mfrc522<COMM, Initialized>,
+) -> Result<Layout, &'static str>
+where
+    COMM: mfrc522::comm::Interface<Error = E>,
+{
+    match uid.get_type() {
+        Type::MifareMini => Ok(Layout::CLASSIC_MINI),
+        Type::Mifare1k => Ok(Layout::CLASSIC_1K),
+        Type::Mifare4k => Ok(Layout::CLASSIC_4K),
+        Type::MifareUL => {
+            let mut tx = [GET_VERSION, 0, 0];
+            let crc = crc_a(&tx[..1]);
+            tx[1..].copy_from_slice(&crc);
+
+            match rfid.transceive::<10>(&tx, 0, 0) {
+                Ok(rx) if rx.valid_bytes >= 8 => {
+                    Ok(Layout::from_storage_size(rx.buffer[6]).unwrap_or(Layout::ULTRALIGHT))
+                }
+                _ => {
+                    retry::reselect(uid, rfid).map_err(|_| "Card lost")?;
+                    Ok(Layout::ULTRALIGHT)
+                }
+            }
+        }
+        _ => Err("Card type not supported"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn block_types() {
+        assert_eq!(get_block_type(0, 0), "MFD");
+        assert_eq!(get_block_type(1, 0), "DATA");
+        assert_eq!(get_block_type(5, 3), "TRAILER");
+        assert_eq!(get_block_type(32, 3), "DATA");
+        assert_eq!(get_block_type(39, 15), "TRAILER");
+    }
+
+    #[test]
+    fn sectors_of_a_4k() {
+        for sector in 0..40 {
+            assert_eq!(sector_of(first_block(sector)), sector);
+            assert_eq!(sector_of(trailer_block(sector)), sector);
+        }
+        assert_eq!(sector_of(127), 31);
+        assert_eq!(sector_of(128), 32);
+        assert_eq!(trailer_block(39), 255);
+    }
+}
@@ -0,0 +1,38 @@
+//! Code shared by the RFID examples: access conditions, value blocks, key
+//! rings, memory layouts, dump file formats, the MAD and NDEF messages, the
+//! CSV logs on the SD card, the allow-list in flash, what a UID and block 0
+//! say about a card, card presence from repeated scans and direct MFRC522
+//! register access.
+//!
+//! Nothing in here is specific to the Pico, so the tests run on the host
+//! with `cargo test`. The card operations (retries, key rotation, storage,
+//! dumping and restoring a card) work on any `CardReader`, which is how
+//! `card-sim` tests them without a reader. The firmware crates turn on the
+//! `defmt` feature to log these types.
+
+#![cfg_attr(not(test), no_std)]
+
+#[macro_use]
+mod fmt;
+
+pub mod access;
+pub mod allowlist;
+pub mod csv_log;
+pub mod debounce;
+pub mod dump;
+pub mod error;
+pub mod formats;
+pub mod keyring;
+pub mod keys;
+pub mod layout;
+pub mod mad;
+pub mod ndef;
+pub mod pcd;
+pub mod protocol;
+pub mod reader;
+pub mod restore;
+pub mod retry;
+pub mod rotation;
+pub mod storage;
+pub mod uid_info;
+pub mod value;
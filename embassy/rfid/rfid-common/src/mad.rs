@@ -0,0 +1,184 @@
+//! MIFARE Application Directory (MAD) version 1.
+//!
+//! Blocks 1 and 2 of sector 0 tell a reader which application lives in
+//! each of the other 15 sectors:
+//!
+//! ```text
+//! block 1: CRC | info | AID sector 1 | AID sector 2 | ... | AID sector 7
+//! block 2: AID sector 8 | ... | AID sector 15
+//! ```
+//!
+//! Every AID is two bytes, little endian. NDEF data uses AID `0xE103`.
+
+use crate::access::{AccessBits, AccessConditions};
+
+pub const NDEF_AID: u16 = 0xE103;
+pub const FREE_AID: u16 = 0x0000;
+
+/// Key A of sector 0 on a card with a MAD, as defined by NXP (AN10787).
+pub const MAD_KEY_A: [u8; 6] = [0xA0, 0xA1, 0xA2, 0xA3, 0xA4, 0xA5];
+/// Key A of the NDEF sectors, as defined by the NFC Forum.
+pub const NDEF_KEY_A: [u8; 6] = [0xD3, 0xF7, 0xD3, 0xF7, 0xD3, 0xF7];
+
+/// General purpose byte of sector 0: MAD v1, multi-application card.
+const MAD_GPB: u8 = 0xC1;
+/// General purpose byte of an NDEF sector: mapping version 1.0, read/write.
+const NDEF_GPB: u8 = 0x40;
+
+/// Sector 0: key A can only read, key B manages everything (`78 77 88`).
+const MAD_ACCESS: AccessConditions = AccessConditions {
+    data: [AccessBits::new(true, false, false); 3],
+    trailer: AccessBits::new(false, true, true),
+};
+
+/// NDEF sectors: key A reads and writes data, key B manages the trailer (`7F 07 88`).
+const NDEF_ACCESS: AccessConditions = AccessConditions {
+    data: [AccessBits::new(false, false, false); 3],
+    trailer: AccessBits::new(false, true, true),
+};
+
+/// Trailer for sector 0 of a card with a MAD. Key B stays with the owner.
+pub const fn mad_trailer(key_b: [u8; 6]) -> [u8; 16] {
+    MAD_ACCESS.to_trailer(MAD_KEY_A, MAD_GPB, key_b)
+}
+
+/// Trailer for a sector that holds NDEF data. Key B stays with the owner.
+pub const fn ndef_trailer(key_b: [u8; 6]) -> [u8; 16] {
+    NDEF_ACCESS.to_trailer(NDEF_KEY_A, NDEF_GPB, key_b)
+}
+
+/// CRC-8 used by the MAD (polynomial 0x1D, preset 0xC7).
+fn crc8(data: &[u8]) -> u8 {
+    let mut crc: u8 = 0xC7;
+    for &byte in data {
+        crc ^= byte;
+        for _ in 0..8 {
+            crc = if crc & 0x80 != 0 {
+                (crc << 1) ^ 0x1D
+            } else {
+                crc << 1
+            };
+        }
+    }
+    crc
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Mad {
+    /// Points to the card publisher sector (0 if there is none)
+    pub info: u8,
+    /// AIDs of sectors 1 to 15
+    pub aids: [u16; 15],
+}
+
+impl Mad {
+    /// A MAD that hands the first `sectors` sectors after sector 0 to NDEF.
+    pub fn ndef(sectors: usize) -> Self {
+        let mut aids = [FREE_AID; 15];
+        for aid in aids.iter_mut().take(sectors) {
+            *aid = NDEF_AID;
+        }
+        Self { info: 0x01, aids }
+    }
+
+    /// Parses blocks 1 and 2 of sector 0 and checks the CRC.
+    pub fn parse(block1: &[u8; 16], block2: &[u8; 16]) -> Result<Self, &'static str> {
+        let mut raw = [0u8; 32];
+        raw[..16].copy_from_slice(block1);
+        raw[16..].copy_from_slice(block2);
+
+        if crc8(&raw[1..]) != raw[0] {
+            return Err("MAD CRC mismatch");
+        }
+
+        let mut aids = [0u16; 15];
+        for (i, aid) in aids.iter_mut().enumerate() {
+            *aid = u16::from_le_bytes([raw[2 + i * 2], raw[3 + i * 2]]);
+        }
+
+        Ok(Self {
+            info: raw[1] & 0x3F,
+            aids,
+        })
+    }
+
+    /// Encodes the MAD back into blocks 1 and 2, with a fresh CRC.
+    pub fn encode(&self) -> ([u8; 16], [u8; 16]) {
+        let mut raw = [0u8; 32];
+        raw[1] = self.info;
+        for (i, aid) in self.aids.iter().enumerate() {
+            raw[2 + i * 2..4 + i * 2].copy_from_slice(&aid.to_le_bytes());
+        }
+        raw[0] = crc8(&raw[1..]);
+
+        (
+            raw[..16].try_into().expect("16 bytes"),
+            raw[16..].try_into().expect("16 bytes"),
+        )
+    }
+
+    /// Sector numbers assigned to NDEF, in order.
+    pub fn ndef_sectors(&self) -> impl Iterator<Item = u8> + '_ {
+        self.aids
+            .iter()
+            .enumerate()
+            .filter(|(_, aid)| **aid == NDEF_AID)
+            .map(|(i, _)| i as u8 + 1)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Sector 0 of a card with NDEF in every sector, from NXP AN1304
+    const NDEF_BLOCK_1: [u8; 16] = [
+        0x14, 0x01, 0x03, 0xE1, 0x03, 0xE1, 0x03, 0xE1, //
+        0x03, 0xE1, 0x03, 0xE1, 0x03, 0xE1, 0x03, 0xE1,
+    ];
+    const NDEF_BLOCK_2: [u8; 16] = [
+        0x03, 0xE1, 0x03, 0xE1, 0x03, 0xE1, 0x03, 0xE1, //
+        0x03, 0xE1, 0x03, 0xE1, 0x03, 0xE1, 0x03, 0xE1,
+    ];
+
+    #[test]
+    fn ndef_mad_crc() {
+        assert_eq!(Mad::ndef(15).encode(), (NDEF_BLOCK_1, NDEF_BLOCK_2));
+    }
+
+    #[test]
+    fn parse_ndef_mad() {
+        let mad = Mad::parse(&NDEF_BLOCK_1, &NDEF_BLOCK_2).unwrap();
+        assert_eq!(mad, Mad::ndef(15));
+        assert!(mad.ndef_sectors().eq(1..=15));
+    }
+
+    #[test]
+    fn round_trip() {
+        let mut mad = Mad::ndef(3);
+        mad.aids[7] = 0x4810;
+        let (block1, block2) = mad.encode();
+        assert_eq!(Mad::parse(&block1, &block2), Ok(mad));
+        assert!(mad.ndef_sectors().eq(1..=3));
+    }
+
+    #[test]
+    fn bad_crc_is_rejected() {
+        let mut block1 = NDEF_BLOCK_1;
+        block1[0] ^= 0x01;
+        assert_eq!(Mad::parse(&block1, &NDEF_BLOCK_2), Err("MAD CRC mismatch"));
+
+        let mut block2 = NDEF_BLOCK_2;
+        block2[15] = 0x00;
+        assert_eq!(Mad::parse(&NDEF_BLOCK_1, &block2), Err("MAD CRC mismatch"));
+    }
+
+    #[test]
+    fn trailers() {
+        assert_eq!(mad_trailer([0xFF; 6])[6..10], [0x78, 0x77, 0x88, 0xC1]);
+        assert_eq!(
+            ndef_trailer([0xFF; 6])[..10],
+            [0xD3, 0xF7, 0xD3, 0xF7, 0xD3, 0xF7, 0x7F, 0x07, 0x88, 0x40]
+        );
+    }
+}
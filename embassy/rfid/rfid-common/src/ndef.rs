@@ -0,0 +1,446 @@
+//! NDEF messages stored on MIFARE Classic cards.
+//!
+//! The data sectors listed in the MAD hold a sequence of TLV blocks. The
+//! one we care about is the NDEF message TLV (type `0x03`), which contains
+//! one or more NDEF records:
+//!
+//! ```text
+//! flags+TNF | type length | payload length (1 or 4) | [id length] | type | [id] | payload
+//! ```
+
+const TLV_NULL: u8 = 0x00;
+const TLV_NDEF_MESSAGE: u8 = 0x03;
+const TLV_TERMINATOR: u8 = 0xFE;
+
+const FLAG_MB: u8 = 0x80;
+const FLAG_ME: u8 = 0x40;
+const FLAG_CF: u8 = 0x20;
+const FLAG_SR: u8 = 0x10;
+const FLAG_IL: u8 = 0x08;
+
+const TNF_WELL_KNOWN: u8 = 0x01;
+const TNF_MIME: u8 = 0x02;
+
+/// URI identifier codes from the NFC Forum URI record type definition.
+const URI_PREFIXES: [&str; 36] = [
+    "",
+    "http://www.",
+    "https://www.",
+    "http://",
+    "https://",
+    "tel:",
+    "mailto:",
+    "ftp://anonymous:anonymous@",
+    "ftp://ftp.",
+    "ftps://",
+    "sftp://",
+    "smb://",
+    "nfs://",
+    "ftp://",
+    "dav://",
+    "news:",
+    "telnet://",
+    "imap:",
+    "rtsp://",
+    "urn:",
+    "pop:",
+    "sip:",
+    "sips:",
+    "tftp:",
+    "btspp://",
+    "btl2cap://",
+    "btgoep://",
+    "tcpobex://",
+    "irdaobex://",
+    "file://",
+    "urn:epc:id:",
+    "urn:epc:tag:",
+    "urn:epc:pat:",
+    "urn:epc:raw:",
+    "urn:epc:",
+    "urn:nfc:",
+];
+
+/// A decoded NDEF record.
+#[derive(Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Record<'a> {
+    Uri {
+        prefix: &'static str,
+        rest: &'a str,
+    },
+    Text {
+        language: &'a str,
+        text: &'a str,
+    },
+    Mime {
+        mime_type: &'a str,
+        data: &'a [u8],
+    },
+    /// Anything we don't decode, kept raw
+    Other {
+        tnf: u8,
+        record_type: &'a [u8],
+        payload: &'a [u8],
+    },
+}
+
+/// Finds the first NDEF message in a TLV area and returns its bytes.
+pub fn find_message(tlv_area: &[u8]) -> Result<&[u8], &'static str> {
+    let mut pos = 0;
+    while pos < tlv_area.len() {
+        let tag = tlv_area[pos];
+        pos += 1;
+
+        match tag {
+            TLV_NULL => continue,
+            TLV_TERMINATOR => break,
+            _ => {}
+        }
+
+        // One byte length, or 0xFF followed by a two byte length
+        let len = match tlv_area.get(pos) {
+            Some(0xFF) => {
+                let bytes = tlv_area.get(pos + 1..pos + 3).ok_or("TLV truncated")?;
+                pos += 3;
+                u16::from_be_bytes([bytes[0], bytes[1]]) as usize
+            }
+            Some(&len) => {
+                pos += 1;
+                len as usize
+            }
+            None => return Err("TLV truncated"),
+        };
+
+        let value = tlv_area.get(pos..pos + len).ok_or("TLV truncated")?;
+        if tag == TLV_NDEF_MESSAGE {
+            return Ok(value);
+        }
+        pos += len;
+    }
+    Err("No NDEF message")
+}
+
+/// Iterates over the records of an NDEF message.
+pub fn records(message: &[u8]) -> Records<'_> {
+    Records {
+        data: message,
+        done: message.is_empty(),
+    }
+}
+
+pub struct Records<'a> {
+    data: &'a [u8],
+    done: bool,
+}
+
+impl<'a> Iterator for Records<'a> {
+    type Item = Result<Record<'a>, &'static str>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        let result = self.parse_record();
+        if result.is_err() {
+            self.done = true;
+        }
+        Some(result)
+    }
+}
+
+impl<'a> Records<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], &'static str> {
+        if self.data.len() < len {
+            return Err("Record truncated");
+        }
+        let (head, tail) = self.data.split_at(len);
+        self.data = tail;
+        Ok(head)
+    }
+
+    fn parse_record(&mut self) -> Result<Record<'a>, &'static str> {
+        let header = self.take(1)?[0];
+        if header & FLAG_CF != 0 {
+            return Err("Chunked records are not supported");
+        }
+        if header & FLAG_ME != 0 {
+            self.done = true;
+        }
+
+        let type_len = self.take(1)?[0] as usize;
+        let payload_len = if header & FLAG_SR != 0 {
+            self.take(1)?[0] as usize
+        } else {
+            let bytes = self.take(4)?;
+            u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as usize
+        };
+        let id_len = if header & FLAG_IL != 0 {
+            self.take(1)?[0] as usize
+        } else {
+            0
+        };
+
+        let record_type = self.take(type_len)?;
+        self.take(id_len)?;
+        let payload = self.take(payload_len)?;
+
+        decode(header & 0x07, record_type, payload)
+    }
+}
+
+fn decode<'a>(
+    tnf: u8,
+    record_type: &'a [u8],
+    payload: &'a [u8],
+) -> Result<Record<'a>, &'static str> {
+    let utf8 = |bytes| core::str::from_utf8(bytes).map_err(|_| "Invalid UTF-8");
+
+    match (tnf, record_type) {
+        (TNF_WELL_KNOWN, b"U") => {
+            let (&code, rest) = payload.split_first().ok_or("Empty URI record")?;
+            Ok(Record::Uri {
+                prefix: URI_PREFIXES.get(code as usize).copied().unwrap_or(""),
+                rest: utf8(rest)?,
+            })
+        }
+        (TNF_WELL_KNOWN, b"T") => {
+            let (&status, rest) = payload.split_first().ok_or("Empty text record")?;
+            if status & 0x80 != 0 {
+                return Err("UTF-16 text is not supported");
+            }
+            let lang_len = (status & 0x3F) as usize;
+            if rest.len() < lang_len {
+                return Err("Record truncated");
+            }
+            let (language, text) = rest.split_at(lang_len);
+            Ok(Record::Text {
+                language: utf8(language)?,
+                text: utf8(text)?,
+            })
+        }
+        (TNF_MIME, _) => Ok(Record::Mime {
+            mime_type: utf8(record_type)?,
+            data: payload,
+        }),
+        _ => Ok(Record::Other {
+            tnf,
+            record_type,
+            payload,
+        }),
+    }
+}
+
+/// Wraps a single short record into an NDEF message TLV followed by a terminator.
+fn encode_single_record(
+    record_type: u8,
+    payload_parts: &[&[u8]],
+    out: &mut [u8],
+) -> Result<usize, &'static str> {
+    let payload_len: usize = payload_parts.iter().map(|p| p.len()).sum();
+    if payload_len > 255 {
+        return Err("Payload too long for a short record");
+    }
+
+    // header + type length + payload length + type
+    let record_len = 4 + payload_len;
+    let tlv_header_len = if record_len < 0xFF { 2 } else { 4 };
+    let total = tlv_header_len + record_len + 1;
+    if out.len() < total {
+        return Err("Output buffer too small");
+    }
+
+    let mut pos = 0;
+    out[pos] = TLV_NDEF_MESSAGE;
+    if record_len < 0xFF {
+        out[pos + 1] = record_len as u8;
+    } else {
+        out[pos + 1] = 0xFF;
+        out[pos + 2..pos + 4].copy_from_slice(&(record_len as u16).to_be_bytes());
+    }
+    pos += tlv_header_len;
+
+    out[pos] = FLAG_MB | FLAG_ME | FLAG_SR | TNF_WELL_KNOWN;
+    out[pos + 1] = 1;
+    out[pos + 2] = payload_len as u8;
+    out[pos + 3] = record_type;
+    pos += 4;
+
+    for part in payload_parts {
+        out[pos..pos + part.len()].copy_from_slice(part);
+        pos += part.len();
+    }
+
+    out[pos] = TLV_TERMINATOR;
+    Ok(pos + 1)
+}
+
+/// Encodes a URI record as a complete TLV area, ready to be written to the card.
+///
+/// The longest matching URI prefix is replaced by its identifier code.
+pub fn encode_uri(uri: &str, out: &mut [u8]) -> Result<usize, &'static str> {
+    let (code, prefix) = URI_PREFIXES
+        .iter()
+        .enumerate()
+        .skip(1)
+        .filter(|(_, prefix)| uri.starts_with(*prefix))
+        .max_by_key(|(_, prefix)| prefix.len())
+        .unwrap_or((0, &""));
+
+    encode_single_record(b'U', &[&[code as u8], &uri.as_bytes()[prefix.len()..]], out)
+}
+
+/// Encodes a UTF-8 text record as a complete TLV area.
+pub fn encode_text(language: &str, text: &str, out: &mut [u8]) -> Result<usize, &'static str> {
+    if language.len() > 0x3F {
+        return Err("Language code too long");
+    }
+    encode_single_record(
+        b'T',
+        &[
+            &[language.len() as u8],
+            language.as_bytes(),
+            text.as_bytes(),
+        ],
+        out,
+    )
+}
+
+/// A single record message we know how to write.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Message<'a> {
+    Uri(&'a str),
+    Text { language: &'a str, text: &'a str },
+}
+
+impl Message<'_> {
+    /// Encodes the message as a complete TLV area.
+    pub fn encode(&self, out: &mut [u8]) -> Result<usize, &'static str> {
+        match *self {
+            Message::Uri(uri) => encode_uri(uri, out),
+            Message::Text { language, text } => encode_text(language, text, out),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::vec::Vec;
+
+    fn decode_all(message: &[u8]) -> Vec<Result<Record<'_>, &'static str>> {
+        records(message).collect()
+    }
+
+    #[test]
+    fn find_message_skips_other_tlvs() {
+        // NULL, a lock control TLV, then the message
+        let area = [
+            0x00, 0x01, 0x03, 0xA0, 0x10, 0x44, 0x03, 0x02, 0xAB, 0xCD, 0xFE,
+        ];
+        assert_eq!(find_message(&area), Ok(&[0xAB, 0xCD][..]));
+    }
+
+    #[test]
+    fn find_message_with_three_byte_length() {
+        let mut area = [0u8; 0x104 + 5];
+        area[..4].copy_from_slice(&[0x03, 0xFF, 0x01, 0x04]);
+        area[4..4 + 0x104].fill(0x55);
+        area[4 + 0x104] = 0xFE;
+        assert_eq!(find_message(&area), Ok(&area[4..4 + 0x104]));
+    }
+
+    #[test]
+    fn find_message_errors() {
+        assert_eq!(find_message(&[0x03, 0x05, 0xD1]), Err("TLV truncated"));
+        assert_eq!(find_message(&[0x03, 0xFF, 0x01]), Err("TLV truncated"));
+        assert_eq!(find_message(&[0x00, 0x03]), Err("TLV truncated"));
+        assert_eq!(find_message(&[0xFE, 0x03, 0x00]), Err("No NDEF message"));
+        assert_eq!(find_message(&[0x00; 16]), Err("No NDEF message"));
+    }
+
+    #[test]
+    fn uri_record() {
+        // "https://" is identifier code 4
+        let message = [
+            0xD1, 0x01, 0x08, b'U', 0x04, b'r', b'u', b's', b't', b'.', b'r', b's',
+        ];
+        assert_eq!(
+            decode_all(&message),
+            [Ok(Record::Uri {
+                prefix: "https://",
+                rest: "rust.rs"
+            })]
+        );
+    }
+
+    #[test]
+    fn text_and_mime_records() {
+        let message = [
+            0x91, 0x01, 0x05, b'T', 0x02, b'e', b'n', b'h', b'i', // text, MB
+            0x52, 0x03, 0x01, b'a', b'/', b'b', 0x42, // MIME, ME
+        ];
+        assert_eq!(
+            decode_all(&message),
+            [
+                Ok(Record::Text {
+                    language: "en",
+                    text: "hi"
+                }),
+                Ok(Record::Mime {
+                    mime_type: "a/b",
+                    data: &[0x42]
+                }),
+            ]
+        );
+    }
+
+    #[test]
+    fn truncated_record_stops_the_iterator() {
+        let message = [0xD1, 0x01, 0x08, b'U', 0x04, b'r'];
+        assert_eq!(decode_all(&message), [Err("Record truncated")]);
+    }
+
+    #[test]
+    fn encode_uri_uses_the_longest_prefix() {
+        let mut out = [0u8; 64];
+        let len = encode_uri("https://www.rust-lang.org", &mut out).unwrap();
+        assert_eq!(out[..7], [0x03, 0x12, 0xD1, 0x01, 0x0E, b'U', 0x02]);
+        assert_eq!(out[len - 1], TLV_TERMINATOR);
+
+        let message = find_message(&out[..len]).unwrap();
+        assert_eq!(
+            decode_all(message),
+            [Ok(Record::Uri {
+                prefix: "https://www.",
+                rest: "rust-lang.org"
+            })]
+        );
+    }
+
+    #[test]
+    fn encode_text_round_trip() {
+        let mut out = [0u8; 64];
+        let message = Message::Text {
+            language: "de",
+            text: "Hallo",
+        };
+        let len = message.encode(&mut out).unwrap();
+        assert_eq!(
+            decode_all(find_message(&out[..len]).unwrap()),
+            [Ok(Record::Text {
+                language: "de",
+                text: "Hallo"
+            })]
+        );
+    }
+
+    #[test]
+    fn encode_rejects_a_small_buffer() {
+        let mut out = [0u8; 8];
+        assert_eq!(
+            encode_uri("https://example.com", &mut out),
+            Err("Output buffer too small")
+        );
+    }
+}
@@ -0,0 +1,239 @@
+//! Direct register access to the MFRC522 (the PCD).
+//!
+//! The `mfrc522` driver owns its SPI interface and keeps its register API
+//! private, so anything it does not wrap (authenticating with key B, the
+//! anticollision loop, the self-test) has to talk to the chip through a
+//! second handle to the same SPI device. `SharedSpi` is that handle: the
+//! driver gets one copy and we keep another. `PcdReader` puts the two back
+//! together as a `CardReader` that knows key B.
+
+use core::cell::RefCell;
+
+use embedded_hal::spi::{ErrorType, Operation, SpiDevice};
+use mfrc522::{Error, Initialized, Mfrc522, Uid};
+
+use crate::reader::CardReader;
+
+// Registers (see section 9 of the MFRC522 datasheet)
+pub const COMMAND_REG: u8 = 0x01;
+pub const COM_I_EN_REG: u8 = 0x02;
+pub const DIV_I_EN_REG: u8 = 0x03;
+pub const COM_IRQ_REG: u8 = 0x04;
+pub const ERROR_REG: u8 = 0x06;
+pub const STATUS2_REG: u8 = 0x08;
+pub const FIFO_DATA_REG: u8 = 0x09;
+pub const FIFO_LEVEL_REG: u8 = 0x0A;
+pub const BIT_FRAMING_REG: u8 = 0x0D;
+pub const COLL_REG: u8 = 0x0E;
+pub const TX_CONTROL_REG: u8 = 0x14;
+pub const RF_CFG_REG: u8 = 0x26;
+pub const AUTO_TEST_REG: u8 = 0x36;
+pub const VERSION_REG: u8 = 0x37;
+
+// PCD commands
+const CMD_IDLE: u8 = 0x00;
+const CMD_MF_AUTHENT: u8 = 0x0E;
+
+// ComIrqReg bits
+const TIMER_IRQ: u8 = 1 << 0;
+const ERR_IRQ: u8 = 1 << 1;
+const IDLE_IRQ: u8 = 1 << 4;
+
+// Status2Reg: set once MIFARE authentication succeeded
+const MF_CRYPTO1_ON: u8 = 1 << 3;
+
+/// Which of the two sector keys to authenticate with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum KeyType {
+    A,
+    B,
+}
+
+impl KeyType {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            KeyType::A => "A",
+            KeyType::B => "B",
+        }
+    }
+
+    fn auth_command(&self) -> u8 {
+        match self {
+            KeyType::A => 0x60,
+            KeyType::B => 0x61,
+        }
+    }
+}
+
+/// A copyable handle to an SPI device that is shared with the `mfrc522` driver.
+pub struct SharedSpi<'a, D>(&'a RefCell<D>);
+
+impl<'a, D> SharedSpi<'a, D> {
+    pub fn new(device: &'a RefCell<D>) -> Self {
+        Self(device)
+    }
+}
+
+impl<D> Clone for SharedSpi<'_, D> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<D> Copy for SharedSpi<'_, D> {}
+
+impl<D: SpiDevice> ErrorType for SharedSpi<'_, D> {
+    type Error = D::Error;
+}
+
+impl<D: SpiDevice> SpiDevice for SharedSpi<'_, D> {
+    fn transaction(&mut self, operations: &mut [Operation<'_, u8>]) -> Result<(), Self::Error> {
+        self.0.borrow_mut().transaction(operations)
+    }
+}
+
+impl<D: SpiDevice> SharedSpi<'_, D> {
+    pub fn read_register(&mut self, reg: u8) -> Result<u8, D::Error> {
+        let mut buffer = [(reg << 1) | 0x80, 0];
+        self.transfer_in_place(&mut buffer)?;
+        Ok(buffer[1])
+    }
+
+    pub fn write_register(&mut self, reg: u8, value: u8) -> Result<(), D::Error> {
+        self.write(&[reg << 1, value])
+    }
+
+    pub fn write_fifo(&mut self, bytes: &[u8]) -> Result<(), D::Error> {
+        let address = [FIFO_DATA_REG << 1];
+        self.transaction(&mut [Operation::Write(&address), Operation::Write(bytes)])
+    }
+
+    /// Reads what is left in the FIFO, returning the number of bytes.
+    pub fn read_fifo(&mut self, buffer: &mut [u8]) -> Result<usize, D::Error> {
+        let level = self.read_register(FIFO_LEVEL_REG)? as usize;
+        let len = level.min(buffer.len());
+        for byte in buffer[..len].iter_mut() {
+            *byte = self.read_register(FIFO_DATA_REG)?;
+        }
+        Ok(len)
+    }
+}
+
+/// Authenticates a block with either key A or key B.
+///
+/// Only the last four UID bytes take part in the authentication,
+/// which is what 7-byte UID cards expect as well. Like the driver, a
+/// wrong key shows up as `Error::Timeout`.
+pub fn authenticate<D: SpiDevice>(
+    pcd: &mut SharedSpi<'_, D>,
+    uid: &[u8],
+    block: u8,
+    key_type: KeyType,
+    key: &[u8; 6],
+) -> Result<(), Error<D::Error>> {
+    let mut tx_buffer = [0u8; 12];
+    tx_buffer[0] = key_type.auth_command();
+    tx_buffer[1] = block;
+    tx_buffer[2..8].copy_from_slice(key);
+    tx_buffer[8..12].copy_from_slice(&uid[uid.len() - 4..]);
+
+    pcd.write_register(COMMAND_REG, CMD_IDLE)
+        .map_err(Error::Comm)?;
+    pcd.write_register(COM_IRQ_REG, 0x7F).map_err(Error::Comm)?;
+    pcd.write_register(FIFO_LEVEL_REG, 0x80)
+        .map_err(Error::Comm)?;
+    pcd.write_register(BIT_FRAMING_REG, 0)
+        .map_err(Error::Comm)?;
+    pcd.write_fifo(&tx_buffer).map_err(Error::Comm)?;
+    pcd.write_register(COMMAND_REG, CMD_MF_AUTHENT)
+        .map_err(Error::Comm)?;
+
+    loop {
+        let irq = pcd.read_register(COM_IRQ_REG).map_err(Error::Comm)?;
+        if irq & (ERR_IRQ | IDLE_IRQ) != 0 {
+            break;
+        } else if irq & TIMER_IRQ != 0 {
+            return Err(Error::Timeout);
+        }
+    }
+
+    if pcd.read_register(ERROR_REG).map_err(Error::Comm)? & 0x1B != 0 {
+        return Err(Error::Protocol);
+    }
+    if pcd.read_register(STATUS2_REG).map_err(Error::Comm)? & MF_CRYPTO1_ON == 0 {
+        return Err(Error::Timeout);
+    }
+    Ok(())
+}
+
+/// The `mfrc522` driver plus our own handle to its SPI device.
+///
+/// Everything goes through the driver except authentication with key B,
+/// which the driver doesn't offer.
+pub struct PcdReader<'r, 'd, COMM: mfrc522::comm::Interface, D> {
+    rfid: &'r mut Mfrc522<COMM, Initialized>,
+    pcd: SharedSpi<'d, D>,
+}
+
+impl<'r, 'd, COMM: mfrc522::comm::Interface, D> PcdReader<'r, 'd, COMM, D> {
+    pub fn new(rfid: &'r mut Mfrc522<COMM, Initialized>, pcd: SharedSpi<'d, D>) -> Self {
+        Self { rfid, pcd }
+    }
+}
+
+impl<COMM, D> CardReader for PcdReader<'_, '_, COMM, D>
+where
+    COMM: mfrc522::comm::Interface<Error = D::Error>,
+    D: SpiDevice,
+{
+    type Atqa = mfrc522::AtqA;
+    type CommError = D::Error;
+
+    fn reqa(&mut self) -> Result<Self::Atqa, Error<D::Error>> {
+        self.rfid.reqa()
+    }
+
+    fn wupa(&mut self) -> Result<Self::Atqa, Error<D::Error>> {
+        self.rfid.wupa()
+    }
+
+    fn select(&mut self, atqa: &Self::Atqa) -> Result<Uid, Error<D::Error>> {
+        self.rfid.select(atqa)
+    }
+
+    fn hlta(&mut self) -> Result<(), Error<D::Error>> {
+        self.rfid.hlta()
+    }
+
+    fn stop_crypto1(&mut self) -> Result<(), Error<D::Error>> {
+        self.rfid.stop_crypto1()
+    }
+
+    fn mf_authenticate(
+        &mut self,
+        uid: &Uid,
+        block: u8,
+        key: &[u8; 6],
+    ) -> Result<(), Error<D::Error>> {
+        self.rfid.mf_authenticate(uid, block, key)
+    }
+
+    fn mf_authenticate_with(
+        &mut self,
+        uid: &Uid,
+        block: u8,
+        key_type: KeyType,
+        key: &[u8; 6],
+    ) -> Result<(), Error<D::Error>> {
+        authenticate(&mut self.pcd, uid.as_bytes(), block, key_type, key)
+    }
+
+    fn mf_read(&mut self, block: u8) -> Result<[u8; 16], Error<D::Error>> {
+        self.rfid.mf_read(block)
+    }
+
+    fn mf_write(&mut self, block: u8, data: [u8; 16]) -> Result<(), Error<D::Error>> {
+        self.rfid.mf_write(block, data)
+    }
+}
@@ -0,0 +1,458 @@
+//! Request/response protocol between the host and the reader over USB serial.
+//!
+//! Both the `serial-reader` firmware and the `serial-reader-cli` host tool
+//! use it, so keep it allocation free.
+//!
+//! Every message is a frame:
+//!
+//! ```text
+//! COBS( payload | CRC-16 (LE) ) | 00
+//! ```
+//!
+//! COBS removes every zero byte from the encoded data, so `00` only ever
+//! marks the end of a frame. A receiver that joins mid-stream or sees a
+//! corrupted frame just waits for the next `00` and starts over. The CRC is
+//! CRC-16/CCITT-FALSE over the payload.
+//!
+//! A request payload is `sequence | command | arguments`, the response to it
+//! is `sequence | status | data`. The reader echoes the sequence number, so
+//! the host can tell a late answer to an earlier request from the one it is
+//! waiting for.
+//!
+//! | Command       | Arguments                          | Data        |
+//! |---------------|------------------------------------|-------------|
+//! | `Version`     |                                    | VersionReg  |
+//! | `Select`      |                                    | UID         |
+//! | `Auth`        | block, key A (6)                   |             |
+//! | `ReadBlock`   | block                              | 16 bytes    |
+//! | `WriteBlock`  | block, data (16)                   |             |
+//! | `ReadSector`  | sector, key A (6)                  | 64 bytes    |
+//! | `ChangeKey`   | sector, current key A (6), trailer (16) |        |
+//!
+//! `ReadBlock` and `WriteBlock` use the sector opened by the last `Auth`.
+//! A card dump is one `ReadSector` per sector, which keeps every frame
+//! small enough for the reader's buffers.
+
+/// Largest payload in either direction: `ReadSector` data plus its header
+pub const MAX_PAYLOAD: usize = 2 + 64;
+
+/// Payload, CRC, COBS overhead and the delimiter
+pub const MAX_FRAME: usize = MAX_PAYLOAD + 2 + 2 + 1;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum Command {
+    Version = 0x01,
+    Select = 0x02,
+    Auth = 0x03,
+    ReadBlock = 0x04,
+    WriteBlock = 0x05,
+    ReadSector = 0x06,
+    ChangeKey = 0x07,
+}
+
+impl Command {
+    pub fn from_u8(value: u8) -> Option<Self> {
+        let command = match value {
+            0x01 => Command::Version,
+            0x02 => Command::Select,
+            0x03 => Command::Auth,
+            0x04 => Command::ReadBlock,
+            0x05 => Command::WriteBlock,
+            0x06 => Command::ReadSector,
+            0x07 => Command::ChangeKey,
+            _ => return None,
+        };
+        Some(command)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum Status {
+    Ok = 0x00,
+    /// No card answered, or the card left the field
+    NoCard = 0x01,
+    /// The key was refused, or `ReadBlock`/`WriteBlock` came without `Auth`
+    AuthFailed = 0x02,
+    /// The card refused or garbled a read or write
+    CardError = 0x03,
+    /// A trailer with access bits that would lock the sector
+    InvalidTrailer = 0x04,
+    /// Wrong argument length or a block out of range
+    BadRequest = 0x05,
+    UnknownCommand = 0x06,
+}
+
+impl Status {
+    pub fn from_u8(value: u8) -> Option<Self> {
+        let status = match value {
+            0x00 => Status::Ok,
+            0x01 => Status::NoCard,
+            0x02 => Status::AuthFailed,
+            0x03 => Status::CardError,
+            0x04 => Status::InvalidTrailer,
+            0x05 => Status::BadRequest,
+            0x06 => Status::UnknownCommand,
+            _ => return None,
+        };
+        Some(status)
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Status::Ok => "OK",
+            Status::NoCard => "No card",
+            Status::AuthFailed => "Authentication failed",
+            Status::CardError => "Card error",
+            Status::InvalidTrailer => "Invalid trailer",
+            Status::BadRequest => "Bad request",
+            Status::UnknownCommand => "Unknown command",
+        }
+    }
+}
+
+/// CRC-16/CCITT-FALSE: polynomial 0x1021, initial value 0xFFFF
+pub fn crc16(data: &[u8]) -> u16 {
+    let mut crc: u16 = 0xFFFF;
+    for &b in data {
+        crc ^= (b as u16) << 8;
+        for _ in 0..8 {
+            crc = if crc & 0x8000 != 0 {
+                (crc << 1) ^ 0x1021
+            } else {
+                crc << 1
+            };
+        }
+    }
+    crc
+}
+
+/// COBS-encodes `data` into `out`, returning the encoded length.
+///
+/// The output is at most `data.len() + data.len() / 254 + 1` bytes.
+fn cobs_encode(data: &[u8], out: &mut [u8]) -> Result<usize, &'static str> {
+    let mut code_index = 0;
+    let mut len = 1;
+    let mut code = 1u8;
+
+    for &b in data {
+        if b == 0 {
+            out[code_index] = code;
+            code_index = len;
+            len += 1;
+            code = 1;
+        } else {
+            *out.get_mut(len).ok_or("Frame too long")? = b;
+            len += 1;
+            code += 1;
+        }
+
+        if code == 0xFF {
+            out[code_index] = code;
+            code_index = len;
+            len += 1;
+            code = 1;
+        }
+        if len >= out.len() {
+            return Err("Frame too long");
+        }
+    }
+
+    out[code_index] = code;
+    Ok(len)
+}
+
+/// Decodes COBS data (without the `00` delimiter) in place, returning the decoded length.
+fn cobs_decode(data: &mut [u8]) -> Result<usize, &'static str> {
+    let mut read = 0;
+    let mut write = 0;
+
+    while read < data.len() {
+        let code = data[read];
+        if code == 0 {
+            return Err("Zero byte inside a frame");
+        }
+        read += 1;
+
+        let end = read + code as usize - 1;
+        if end > data.len() {
+            return Err("Truncated frame");
+        }
+        data.copy_within(read..end, write);
+        write += end - read;
+        read = end;
+
+        // A full block (0xFF) is not followed by a zero, and neither is the last block
+        if code != 0xFF && read < data.len() {
+            data[write] = 0;
+            write += 1;
+        }
+    }
+    Ok(write)
+}
+
+/// Builds a complete frame, delimiter included, returning its length.
+pub fn encode_frame(payload: &[u8], out: &mut [u8; MAX_FRAME]) -> Result<usize, &'static str> {
+    if payload.len() > MAX_PAYLOAD {
+        return Err("Payload too long");
+    }
+
+    let mut raw = [0u8; MAX_PAYLOAD + 2];
+    raw[..payload.len()].copy_from_slice(payload);
+    let crc = crc16(payload);
+    raw[payload.len()..payload.len() + 2].copy_from_slice(&crc.to_le_bytes());
+
+    let len = cobs_encode(&raw[..payload.len() + 2], out)?;
+    out[len] = 0;
+    Ok(len + 1)
+}
+
+/// Checks and decodes a frame (without the delimiter) in place, returning the payload length.
+pub fn decode_frame(frame: &mut [u8]) -> Result<usize, &'static str> {
+    let len = cobs_decode(frame)?;
+    if len < 2 {
+        return Err("Frame too short");
+    }
+
+    let payload_len = len - 2;
+    let crc = u16::from_le_bytes([frame[payload_len], frame[payload_len + 1]]);
+    if crc != crc16(&frame[..payload_len]) {
+        return Err("CRC mismatch");
+    }
+    Ok(payload_len)
+}
+
+/// Collects bytes from the serial stream until a whole frame is in.
+pub struct FrameReader {
+    buffer: [u8; MAX_FRAME],
+    len: usize,
+    /// Set when a frame overflowed the buffer, the rest of it is dropped
+    skipping: bool,
+}
+
+impl Default for FrameReader {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl FrameReader {
+    pub const fn new() -> Self {
+        Self {
+            buffer: [0; MAX_FRAME],
+            len: 0,
+            skipping: false,
+        }
+    }
+
+    /// Feeds one byte. Once a frame is complete and valid, returns its payload.
+    ///
+    /// Broken frames are dropped; the sender finds out through its timeout.
+    pub fn push(&mut self, byte: u8) -> Option<&[u8]> {
+        if byte != 0 {
+            if self.len == self.buffer.len() {
+                self.skipping = true;
+            } else if !self.skipping {
+                self.buffer[self.len] = byte;
+                self.len += 1;
+            }
+            return None;
+        }
+
+        let len = core::mem::take(&mut self.len);
+        if core::mem::take(&mut self.skipping) || len == 0 {
+            return None;
+        }
+
+        let payload_len = decode_frame(&mut self.buffer[..len]).ok()?;
+        Some(&self.buffer[..payload_len])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cobs_round_trip(data: &[u8]) -> std::vec::Vec<u8> {
+        let mut encoded = [0u8; 1024];
+        let len = cobs_encode(data, &mut encoded).unwrap();
+        assert!(
+            !encoded[..len].contains(&0),
+            "zero in {:02x?}",
+            &encoded[..len]
+        );
+        assert!(len <= data.len() + data.len() / 254 + 1);
+        let result = encoded[..len].to_vec();
+
+        let decoded_len = cobs_decode(&mut encoded[..len]).unwrap();
+        assert_eq!(&encoded[..decoded_len], data);
+        result
+    }
+
+    fn frame(payload: &[u8]) -> std::vec::Vec<u8> {
+        let mut out = [0u8; MAX_FRAME];
+        let len = encode_frame(payload, &mut out).unwrap();
+        out[..len].to_vec()
+    }
+
+    #[test]
+    fn crc16_check_value() {
+        assert_eq!(crc16(b"123456789"), 0x29B1);
+        assert_eq!(crc16(&[]), 0xFFFF);
+    }
+
+    #[test]
+    fn cobs_known_encodings() {
+        assert_eq!(cobs_round_trip(&[]), [0x01]);
+        assert_eq!(cobs_round_trip(&[0x00]), [0x01, 0x01]);
+        assert_eq!(cobs_round_trip(&[0x00, 0x00]), [0x01, 0x01, 0x01]);
+        assert_eq!(
+            cobs_round_trip(&[0x11, 0x22, 0x00, 0x33]),
+            [0x03, 0x11, 0x22, 0x02, 0x33]
+        );
+        assert_eq!(
+            cobs_round_trip(&[0x11, 0x00, 0x00, 0x00]),
+            [0x02, 0x11, 0x01, 0x01, 0x01]
+        );
+    }
+
+    #[test]
+    fn cobs_zero_runs() {
+        for len in 1..=300 {
+            cobs_round_trip(&std::vec![0u8; len]);
+        }
+    }
+
+    #[test]
+    fn cobs_254_byte_runs() {
+        let run: std::vec::Vec<u8> = (1..=254).collect();
+        let encoded = cobs_round_trip(&run);
+        // A full block has no zero after it
+        assert_eq!(encoded[0], 0xFF);
+        assert_eq!(encoded[1..255], run[..]);
+
+        for extra in [1, 2, 253, 254, 255] {
+            let mut data: std::vec::Vec<u8> = (0..254 + extra).map(|i| i as u8 | 1).collect();
+            cobs_round_trip(&data);
+            // With zeros right before, inside and right after the run
+            data[253] = 0;
+            data.insert(0, 0);
+            data.push(0);
+            cobs_round_trip(&data);
+        }
+    }
+
+    #[test]
+    fn cobs_rejects_broken_data() {
+        assert_eq!(cobs_decode(&mut [0x03, 0x11]), Err("Truncated frame"));
+        assert_eq!(
+            cobs_decode(&mut [0x01, 0x00]),
+            Err("Zero byte inside a frame")
+        );
+        assert_eq!(
+            cobs_encode(&[0x11; 8], &mut [0u8; 8]),
+            Err("Frame too long")
+        );
+    }
+
+    #[test]
+    fn frame_round_trip() {
+        let payloads: [&[u8]; 4] = [
+            &[0x01],
+            &[0x00, 0x00],
+            &[0x07, 0x00, 0xFF, 0x00],
+            &[0xAB; MAX_PAYLOAD],
+        ];
+        for payload in payloads {
+            let mut frame = frame(payload);
+            assert_eq!(frame.pop(), Some(0), "frame ends with the delimiter");
+            assert!(!frame.contains(&0));
+            assert!(frame.len() < MAX_FRAME);
+
+            let len = decode_frame(&mut frame).unwrap();
+            assert_eq!(&frame[..len], payload);
+        }
+    }
+
+    #[test]
+    fn frame_rejects_long_payloads() {
+        let mut out = [0u8; MAX_FRAME];
+        assert_eq!(
+            encode_frame(&[0x01; MAX_PAYLOAD + 1], &mut out),
+            Err("Payload too long")
+        );
+    }
+
+    #[test]
+    fn frame_with_bad_crc_is_rejected() {
+        let mut frame = frame(&[0x01, 0x02, 0x03]);
+        frame.pop();
+        frame[1] ^= 0x02;
+        assert_eq!(decode_frame(&mut frame), Err("CRC mismatch"));
+    }
+
+    #[test]
+    fn truncated_frames_are_rejected() {
+        let mut frame = frame(&[0x01, 0x02, 0x03]);
+        frame.pop();
+        frame.pop();
+        assert_eq!(decode_frame(&mut frame), Err("Truncated frame"));
+
+        // Only the CRC left, or not even that
+        assert_eq!(decode_frame(&mut [0x01]), Err("Frame too short"));
+        assert_eq!(decode_frame(&mut [0x02, 0x11]), Err("Frame too short"));
+    }
+
+    #[test]
+    fn frame_reader_returns_whole_frames() {
+        let mut reader = FrameReader::new();
+        let frame = frame(&[0x05, 0x00, 0x42]);
+
+        let (last, rest) = frame.split_last().unwrap();
+        for &byte in rest {
+            assert_eq!(reader.push(byte), None);
+        }
+        assert_eq!(reader.push(*last), Some(&[0x05, 0x00, 0x42][..]));
+
+        // Empty frames between messages are ignored
+        assert_eq!(reader.push(0), None);
+        assert_eq!(reader.push(0), None);
+    }
+
+    #[test]
+    fn frame_reader_drops_broken_frames() {
+        let mut reader = FrameReader::new();
+
+        // Joining mid-stream: the tail of some frame, then its delimiter
+        for byte in [0x42, 0x13, 0x00] {
+            assert_eq!(reader.push(byte), None);
+        }
+        let mut broken = frame(&[0x01, 0x02]);
+        broken[1] ^= 0x04;
+        for byte in broken {
+            assert_eq!(reader.push(byte), None);
+        }
+
+        let mut result = None;
+        for byte in frame(&[0x01, 0x02]) {
+            result = reader.push(byte).map(|payload| payload.to_vec());
+        }
+        assert_eq!(result.as_deref(), Some(&[0x01, 0x02][..]));
+    }
+
+    #[test]
+    fn frame_reader_resyncs_after_an_overflowing_frame() {
+        let mut reader = FrameReader::new();
+        for _ in 0..MAX_FRAME * 2 {
+            assert_eq!(reader.push(0x55), None);
+        }
+        assert_eq!(reader.push(0), None);
+
+        let mut result = None;
+        for byte in frame(&[0x09, 0x01]) {
+            result = reader.push(byte).map(|payload| payload.to_vec());
+        }
+        assert_eq!(result.as_deref(), Some(&[0x09, 0x01][..]));
+    }
+}
@@ -0,0 +1,120 @@
+//! The reader operations our card logic needs, as a trait.
+//!
+//! `Mfrc522` implements it, and so does the simulated card in `card-sim`,
+//! so the same retry, rotation, storage, dump and restore code runs against
+//! either of them. The driver can't authenticate with key B, so the flows
+//! that need it take a `pcd::PcdReader` on the Pico.
+//! The errors are the driver's own, so `RfidError::from_mfrc522` maps them
+//! the same way for both.
+
+use mfrc522::{Error, Initialized, Mfrc522, Uid};
+
+use crate::error::{Operation, RfidError};
+use crate::pcd::KeyType;
+use crate::retry::{self, RetryPolicy};
+
+pub trait CardReader {
+    /// Answer to REQA or WUPA, passed on to `select`
+    type Atqa;
+    /// Error of the link to the reader, SPI for the MFRC522
+    type CommError;
+
+    fn reqa(&mut self) -> Result<Self::Atqa, Error<Self::CommError>>;
+    /// Like `reqa`, but also wakes up a halted card
+    fn wupa(&mut self) -> Result<Self::Atqa, Error<Self::CommError>>;
+    fn select(&mut self, atqa: &Self::Atqa) -> Result<Uid, Error<Self::CommError>>;
+    fn hlta(&mut self) -> Result<(), Error<Self::CommError>>;
+    fn stop_crypto1(&mut self) -> Result<(), Error<Self::CommError>>;
+    /// Authenticates the sector of `block` with key A
+    fn mf_authenticate(
+        &mut self,
+        uid: &Uid,
+        block: u8,
+        key: &[u8; 6],
+    ) -> Result<(), Error<Self::CommError>>;
+    /// Authenticates the sector of `block` with key A or key B.
+    ///
+    /// Readers that only know key A refuse key B with `Error::Proprietary`.
+    fn mf_authenticate_with(
+        &mut self,
+        uid: &Uid,
+        block: u8,
+        key_type: KeyType,
+        key: &[u8; 6],
+    ) -> Result<(), Error<Self::CommError>> {
+        match key_type {
+            KeyType::A => self.mf_authenticate(uid, block, key),
+            KeyType::B => Err(Error::Proprietary),
+        }
+    }
+    fn mf_read(&mut self, block: u8) -> Result<[u8; 16], Error<Self::CommError>>;
+    fn mf_write(&mut self, block: u8, data: [u8; 16]) -> Result<(), Error<Self::CommError>>;
+}
+
+impl<E, COMM> CardReader for Mfrc522<COMM, Initialized>
+where
+    COMM: mfrc522::comm::Interface<Error = E>,
+{
+    type Atqa = mfrc522::AtqA;
+    type CommError = E;
+
+    fn reqa(&mut self) -> Result<Self::Atqa, Error<E>> {
+        Mfrc522::reqa(self)
+    }
+
+    fn wupa(&mut self) -> Result<Self::Atqa, Error<E>> {
+        Mfrc522::wupa(self)
+    }
+
+    fn select(&mut self, atqa: &Self::Atqa) -> Result<Uid, Error<E>> {
+        Mfrc522::select(self, atqa)
+    }
+
+    fn hlta(&mut self) -> Result<(), Error<E>> {
+        Mfrc522::hlta(self)
+    }
+
+    fn stop_crypto1(&mut self) -> Result<(), Error<E>> {
+        Mfrc522::stop_crypto1(self)
+    }
+
+    fn mf_authenticate(&mut self, uid: &Uid, block: u8, key: &[u8; 6]) -> Result<(), Error<E>> {
+        Mfrc522::mf_authenticate(self, uid, block, key)
+    }
+
+    fn mf_read(&mut self, block: u8) -> Result<[u8; 16], Error<E>> {
+        Mfrc522::mf_read(self, block)
+    }
+
+    fn mf_write(&mut self, block: u8, data: [u8; 16]) -> Result<(), Error<E>> {
+        Mfrc522::mf_write(self, block, data)
+    }
+}
+
+/// Reads the four blocks of one of the first 32 sectors with key A.
+///
+/// The whole sector is read before returning, so a retry never hands back
+/// blocks from two different attempts.
+pub fn read_sector<R: CardReader>(
+    uid: &Uid,
+    sector: u8,
+    key: &[u8; 6],
+    policy: &RetryPolicy,
+    rfid: &mut R,
+) -> Result<[[u8; 16]; 4], RfidError> {
+    let block_offset = sector * 4;
+
+    retry::with_retry(policy, uid, rfid, |rfid| {
+        rfid.mf_authenticate(uid, block_offset, key)
+            .map_err(|e| RfidError::from_mfrc522(e, Operation::Auth, block_offset))?;
+
+        let mut blocks = [[0u8; 16]; 4];
+        for (rel_block, block) in blocks.iter_mut().enumerate() {
+            let abs_block = block_offset + rel_block as u8;
+            *block = rfid
+                .mf_read(abs_block)
+                .map_err(|e| RfidError::from_mfrc522(e, Operation::Read, abs_block))?;
+        }
+        Ok(blocks)
+    })
+}
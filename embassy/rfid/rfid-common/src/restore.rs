@@ -0,0 +1,205 @@
+//! Writes a saved 1K image back onto a MIFARE Classic card.
+//!
+//! Data blocks are written first and sector trailers last, because a new
+//! trailer may change the keys we need for the rest of the sector. Every
+//! block is read back after writing to make sure it actually landed.
+
+use mfrc522::{MifareKey, Uid};
+
+use crate::access::AccessConditions;
+use crate::keyring::{self, KeyRing, SectorKey};
+use crate::pcd::KeyType;
+use crate::reader::CardReader;
+use crate::retry;
+
+pub const SECTORS: usize = 16;
+pub const BLOCKS: usize = SECTORS * 4;
+pub const IMAGE_SIZE: usize = BLOCKS * 16;
+
+/// The outcome for a single block.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum BlockStatus {
+    Pending,
+    /// Written and read back with the expected content
+    Verified,
+    /// Deliberately not written (the manufacturer block)
+    Skipped,
+    Failed(&'static str),
+}
+
+pub struct RestoreOptions {
+    /// Also write block 0. This only works on "magic" cards and
+    /// normal cards will simply reject it.
+    pub allow_block0: bool,
+}
+
+fn image_block(image: &[u8; IMAGE_SIZE], abs_block: u8) -> [u8; 16] {
+    let start = abs_block as usize * 16;
+    image[start..start + 16]
+        .try_into()
+        .expect("block is 16 bytes")
+}
+
+/// Key A and key B from the image's trailer for a sector.
+pub fn image_keys(image: &[u8; IMAGE_SIZE], sector: u8) -> (MifareKey, MifareKey) {
+    let trailer = image_block(image, sector * 4 + 3);
+    (
+        trailer[..6].try_into().expect("key is 6 bytes"),
+        trailer[10..].try_into().expect("key is 6 bytes"),
+    )
+}
+
+/// Checks that every trailer of an `.mfd` image holds a key A.
+///
+/// Key A always reads back as zeros, so a dump only has it where the tool
+/// patched in the key that opened the sector. Restoring a trailer without
+/// it would set key A to all zeros.
+pub fn check_mfd_keys(image: &[u8; IMAGE_SIZE]) -> Result<(), &'static str> {
+    if (0..SECTORS as u8).any(|sector| image_keys(image, sector).0 == [0; 6]) {
+        return Err("Image has trailers without key A");
+    }
+    Ok(())
+}
+
+/// Adds every key found in the image to the key ring, so cards that were
+/// already (partially) restored can still be opened.
+pub fn add_image_keys<const N: usize>(
+    image: &[u8; IMAGE_SIZE],
+    keyring: &mut KeyRing<N>,
+) -> Result<(), &'static str> {
+    for sector in 0..SECTORS as u8 {
+        let (key_a, key_b) = image_keys(image, sector);
+        keyring.add(key_a)?;
+        keyring.add(key_b)?;
+    }
+    Ok(())
+}
+
+fn authenticate_sector<R: CardReader>(
+    uid: &Uid,
+    sector: u8,
+    sector_key: &SectorKey,
+    rfid: &mut R,
+) -> Result<(), &'static str> {
+    // The card may still be selected after a write, so this has to halt it
+    retry::reselect(uid, rfid).map_err(|_| "Card lost")?;
+    rfid.mf_authenticate_with(uid, sector * 4 + 3, sector_key.key_type, &sector_key.key)
+        .map_err(|_| "Auth failed")
+}
+
+fn write_and_verify<R: CardReader>(
+    abs_block: u8,
+    data: [u8; 16],
+    rfid: &mut R,
+) -> Result<(), &'static str> {
+    rfid.mf_write(abs_block, data).map_err(|_| "Write failed")?;
+    let read_back = rfid.mf_read(abs_block).map_err(|_| "Read back failed")?;
+    if read_back != data {
+        return Err("Verify mismatch");
+    }
+    Ok(())
+}
+
+/// Checks a freshly written trailer by logging in with the new key A.
+///
+/// Key A always reads back as zeros and key B only reads back when the
+/// access conditions allow it, so only those parts are compared.
+fn verify_trailer<R: CardReader>(
+    uid: &Uid,
+    sector: u8,
+    trailer: &[u8; 16],
+    conditions: &AccessConditions,
+    rfid: &mut R,
+) -> Result<(), &'static str> {
+    let new_key = SectorKey {
+        key_type: KeyType::A,
+        key: trailer[..6].try_into().expect("key is 6 bytes"),
+    };
+    authenticate_sector(uid, sector, &new_key, rfid).map_err(|_| "New key rejected")?;
+
+    let read_back = rfid
+        .mf_read(sector * 4 + 3)
+        .map_err(|_| "Read back failed")?;
+    if read_back[6..10] != trailer[6..10] {
+        return Err("Verify mismatch");
+    }
+    if conditions.trailer.trailer_permissions().key_b_readable() && read_back[10..] != trailer[10..]
+    {
+        return Err("Verify mismatch");
+    }
+    Ok(())
+}
+
+/// Restores the image onto the card and returns the status of every block.
+pub fn restore_card<R: CardReader, const N: usize>(
+    uid: &Uid,
+    image: &[u8; IMAGE_SIZE],
+    keyring: &KeyRing<N>,
+    options: &RestoreOptions,
+    rfid: &mut R,
+) -> Result<[BlockStatus; BLOCKS], &'static str> {
+    let mut status = [BlockStatus::Pending; BLOCKS];
+    let mut sector_keys: [Option<SectorKey>; SECTORS] = [None; SECTORS];
+
+    // Data blocks first, with whatever key currently opens the sector
+    for sector in 0..SECTORS as u8 {
+        let block_offset = sector * 4;
+
+        let Some(sector_key) = keyring::find_sector_key(uid, sector * 4 + 3, keyring, rfid)? else {
+            for abs_block in block_offset..block_offset + 4 {
+                status[abs_block as usize] = BlockStatus::Failed("No key opened the sector");
+            }
+            continue;
+        };
+        sector_keys[sector as usize] = Some(sector_key);
+
+        for abs_block in block_offset..block_offset + 3 {
+            if abs_block == 0 && !options.allow_block0 {
+                status[0] = BlockStatus::Skipped;
+                continue;
+            }
+
+            status[abs_block as usize] =
+                match write_and_verify(abs_block, image_block(image, abs_block), rfid) {
+                    Ok(()) => BlockStatus::Verified,
+                    Err(e) => {
+                        // A failed command drops the authentication
+                        authenticate_sector(uid, sector, &sector_key, rfid)?;
+                        BlockStatus::Failed(e)
+                    }
+                };
+        }
+    }
+
+    // Trailers last, so the new keys don't lock us out halfway through a sector
+    for sector in 0..SECTORS as u8 {
+        let abs_block = sector * 4 + 3;
+        let Some(sector_key) = sector_keys[sector as usize] else {
+            continue;
+        };
+
+        let trailer = image_block(image, abs_block);
+        let conditions = match AccessConditions::from_trailer(&trailer) {
+            Ok(conditions) => conditions,
+            Err(e) => {
+                status[abs_block as usize] = BlockStatus::Failed(e);
+                continue;
+            }
+        };
+
+        authenticate_sector(uid, sector, &sector_key, rfid)?;
+        if rfid.mf_write(abs_block, trailer).is_err() {
+            status[abs_block as usize] = BlockStatus::Failed("Write failed");
+            continue;
+        }
+
+        status[abs_block as usize] = match verify_trailer(uid, sector, &trailer, &conditions, rfid)
+        {
+            Ok(()) => BlockStatus::Verified,
+            Err(e) => BlockStatus::Failed(e),
+        };
+    }
+
+    Ok(status)
+}
@@ -0,0 +1,86 @@
+//! Retrying card operations that failed because of a weak field.
+//!
+//! A card near the edge of the antenna field often drops a frame and then
+//! stops talking to us until it is woken up again. So before every new
+//! attempt the card is selected again, and the operation has to start with
+//! its own authentication.
+
+use embassy_time::{Duration, block_for};
+
+use crate::error::RfidError;
+use crate::reader::CardReader;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetryPolicy {
+    /// Total number of attempts, including the first one
+    pub attempts: u8,
+    /// Pause before the next attempt
+    pub delay_ms: u64,
+    /// Also retry failed authentication, for cards that sit very loosely on the reader
+    pub retry_auth: bool,
+}
+
+impl RetryPolicy {
+    pub const NONE: Self = Self {
+        attempts: 1,
+        delay_ms: 0,
+        retry_auth: false,
+    };
+
+    pub fn should_retry(&self, err: &RfidError) -> bool {
+        err.is_transient() || (self.retry_auth && matches!(err, RfidError::AuthFailed { .. }))
+    }
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            attempts: 3,
+            delay_ms: 20,
+            retry_auth: false,
+        }
+    }
+}
+
+/// Wakes the card up again and checks it is still the same one.
+pub fn reselect<R: CardReader>(
+    uid: &mfrc522::Uid,
+    rfid: &mut R,
+) -> Result<(), mfrc522::Error<R::CommError>> {
+    // A card that is still selected takes WUPA for a bad frame, so halt it
+    // first. One that already dropped out ignores the HLTA.
+    let _ = rfid.hlta();
+    let _ = rfid.stop_crypto1();
+    let atqa = rfid.wupa()?;
+    let new_uid = rfid.select(&atqa)?;
+    if new_uid.as_bytes() != uid.as_bytes() {
+        return Err(mfrc522::Error::Collision);
+    }
+    Ok(())
+}
+
+/// Runs `operation` until it succeeds, fails for good, or runs out of attempts.
+pub fn with_retry<T, R: CardReader>(
+    policy: &RetryPolicy,
+    uid: &mfrc522::Uid,
+    rfid: &mut R,
+    mut operation: impl FnMut(&mut R) -> Result<T, RfidError>,
+) -> Result<T, RfidError> {
+    let mut attempt = 1;
+    loop {
+        match operation(rfid) {
+            Ok(value) => return Ok(value),
+            Err(e) if attempt < policy.attempts && policy.should_retry(&e) => {
+                warn!("{:?}, retrying ({}/{})", e, attempt, policy.attempts);
+                attempt += 1;
+
+                block_for(Duration::from_millis(policy.delay_ms));
+                // If the card is gone, the original error says more than the reselect one
+                if reselect(uid, rfid).is_err() {
+                    return Err(e);
+                }
+            }
+            Err(e) => return Err(e),
+        }
+    }
+}
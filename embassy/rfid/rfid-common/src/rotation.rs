@@ -0,0 +1,216 @@
+//! Rotating the keys of many sectors in one go.
+//!
+//! A card with some sectors on the new keys and some on the old ones is a
+//! pain to recover, so the rotation runs in two passes. The first one only
+//! reads: it opens every sector with the old key A and checks that key A is
+//! allowed to rewrite the trailer. Nothing is written unless every sector
+//! passes.
+//!
+//! The second pass writes the new trailers, one sector at a time, and proves
+//! each one by opening the sector again with the new key A. If a sector
+//! fails, the sectors changed so far get their old trailer back, newest
+//! first.
+//!
+//! The access bits and the general purpose byte of each sector are kept.
+//! Only key A and key B change.
+
+use heapless::Vec;
+use mfrc522::Uid;
+
+use crate::access::{AccessConditions, Permission};
+use crate::error::{Operation, RfidError};
+use crate::keys::KeySchedule;
+use crate::layout::trailer_block;
+use crate::reader::CardReader;
+use crate::retry::{self, RetryPolicy};
+
+/// A Classic 4K has 40 sectors
+pub const MAX_SECTORS: usize = 40;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct RotationError {
+    pub sector: u8,
+    pub error: RfidError,
+    /// Sectors whose old trailer could not be put back. Empty if the card
+    /// is exactly as it was before the rotation.
+    pub stuck: Vec<u8, MAX_SECTORS>,
+}
+
+fn read_trailer<R: CardReader>(
+    uid: &Uid,
+    sector: u8,
+    key: &[u8; 6],
+    policy: &RetryPolicy,
+    rfid: &mut R,
+) -> Result<[u8; 16], RfidError> {
+    let block = trailer_block(sector);
+    retry::with_retry(policy, uid, rfid, |rfid| {
+        rfid.mf_authenticate(uid, block, key)
+            .map_err(|e| RfidError::from_mfrc522(e, Operation::Auth, block))?;
+        rfid.mf_read(block)
+            .map_err(|e| RfidError::from_mfrc522(e, Operation::Read, block))
+    })
+}
+
+fn write_trailer<R: CardReader>(
+    uid: &Uid,
+    sector: u8,
+    key: &[u8; 6],
+    trailer: [u8; 16],
+    policy: &RetryPolicy,
+    rfid: &mut R,
+) -> Result<(), RfidError> {
+    let block = trailer_block(sector);
+    retry::with_retry(policy, uid, rfid, |rfid| {
+        rfid.mf_authenticate(uid, block, key)
+            .map_err(|e| RfidError::from_mfrc522(e, Operation::Auth, block))?;
+        rfid.mf_write(block, trailer)
+            .map_err(|e| RfidError::from_mfrc522(e, Operation::Write, block))
+    })
+}
+
+/// Opens the sector with the key A of `expected` from a fresh select and checks the
+/// trailer reads back as expected.
+///
+/// Key A always reads back as zeros, and key B only when the access bits
+/// make it readable, so those are the only bytes compared.
+fn verify_trailer<R: CardReader>(
+    uid: &Uid,
+    sector: u8,
+    expected: &[u8; 16],
+    key_b_readable: bool,
+    policy: &RetryPolicy,
+    rfid: &mut R,
+) -> Result<(), RfidError> {
+    let block = trailer_block(sector);
+    // A failed authentication leaves the card halted, start from a clean select
+    retry::reselect(uid, rfid).map_err(|e| RfidError::from_mfrc522(e, Operation::Auth, block))?;
+
+    let key_a: [u8; 6] = expected[..6].try_into().expect("6 bytes");
+    let trailer = read_trailer(uid, sector, &key_a, policy, rfid)?;
+    if trailer[6..10] != expected[6..10] || (key_b_readable && trailer[10..] != expected[10..]) {
+        return Err(RfidError::WriteFailed { block });
+    }
+    Ok(())
+}
+
+/// Puts the old trailer back on a sector the rotation may have changed.
+fn roll_back<R: CardReader>(
+    uid: &Uid,
+    sector: u8,
+    original: &[u8; 16],
+    new_key_a: &[u8; 6],
+    key_b_readable: bool,
+    policy: &RetryPolicy,
+    rfid: &mut R,
+) -> Result<(), RfidError> {
+    // If the old key still opens it, the new trailer never made it to the card
+    if verify_trailer(uid, sector, original, key_b_readable, policy, rfid).is_ok() {
+        return Ok(());
+    }
+
+    let block = trailer_block(sector);
+    retry::reselect(uid, rfid).map_err(|e| RfidError::from_mfrc522(e, Operation::Auth, block))?;
+    write_trailer(uid, sector, new_key_a, *original, policy, rfid)?;
+    verify_trailer(uid, sector, original, key_b_readable, policy, rfid)
+}
+
+/// Changes key A and key B of every sector in `sectors` from `old` to `new`.
+///
+/// Both can be fixed keys (`SectorKeys`) or keys diversified per card.
+///
+/// Either all the sectors end up on the new keys, or the ones already
+/// changed are rolled back. `RotationError::stuck` lists the sectors where
+/// even that failed.
+pub fn rotate_keys<R: CardReader>(
+    uid: &Uid,
+    sectors: &[u8],
+    old: &impl KeySchedule,
+    new: &impl KeySchedule,
+    policy: &RetryPolicy,
+    rfid: &mut R,
+) -> Result<(), RotationError> {
+    let fail = |sector, error| RotationError {
+        sector,
+        error,
+        stuck: Vec::new(),
+    };
+
+    // Old and new trailer of every sector, and whether key B reads back
+    let mut plan: Vec<(u8, [u8; 16], [u8; 16], bool), MAX_SECTORS> = Vec::new();
+
+    for &sector in sectors {
+        let old = old.keys(uid.as_bytes(), sector);
+        let new = new.keys(uid.as_bytes(), sector);
+
+        let trailer =
+            read_trailer(uid, sector, &old.key_a, policy, rfid).map_err(|e| fail(sector, e))?;
+        let conditions = AccessConditions::from_trailer(&trailer)
+            .map_err(|reason| fail(sector, RfidError::InvalidTrailer { sector, reason }))?;
+
+        // We authenticate with key A, so key A has to be the one allowed to
+        // write both keys and keep the access bits as they are
+        let permissions = conditions.trailer.trailer_permissions();
+        if permissions.key_a_write != Permission::KeyA
+            || permissions.key_b_write != Permission::KeyA
+        {
+            let reason = "key A is not allowed to change the keys";
+            return Err(fail(sector, RfidError::InvalidTrailer { sector, reason }));
+        }
+
+        let key_b_readable = permissions.key_b_readable();
+        let old_key_b = if key_b_readable {
+            trailer[10..].try_into().expect("6 bytes")
+        } else {
+            old.key_b
+        };
+        let gpb = trailer[9];
+        let original = conditions.to_trailer(old.key_a, gpb, old_key_b);
+        let rotated = conditions.to_trailer(new.key_a, gpb, new.key_b);
+
+        plan.push((sector, original, rotated, key_b_readable))
+            .map_err(|_| {
+                let reason = "too many sectors";
+                fail(sector, RfidError::InvalidTrailer { sector, reason })
+            })?;
+    }
+
+    for (done, &(sector, original, rotated, key_b_readable)) in plan.iter().enumerate() {
+        let old_key_a: [u8; 6] = original[..6].try_into().expect("6 bytes");
+        let result = retry::reselect(uid, rfid)
+            .map_err(|e| RfidError::from_mfrc522(e, Operation::Auth, trailer_block(sector)))
+            .and_then(|()| write_trailer(uid, sector, &old_key_a, rotated, policy, rfid))
+            .and_then(|()| verify_trailer(uid, sector, &rotated, key_b_readable, policy, rfid));
+
+        let Err(error) = result else {
+            info!("Sector {} rotated", sector);
+            continue;
+        };
+
+        warn!("Sector {} failed: {:?}, rolling back", sector, error);
+        let mut stuck = Vec::new();
+        // The failed sector too, its write may have landed before the error
+        for &(sector, original, rotated, key_b_readable) in plan[..=done].iter().rev() {
+            let new_key_a: [u8; 6] = rotated[..6].try_into().expect("6 bytes");
+            if let Err(e) = roll_back(
+                uid,
+                sector,
+                &original,
+                &new_key_a,
+                key_b_readable,
+                policy,
+                rfid,
+            ) {
+                warn!("Sector {} could not be rolled back: {:?}", sector, e);
+                let _ = stuck.push(sector);
+            }
+        }
+        return Err(RotationError {
+            sector,
+            error,
+            stuck,
+        });
+    }
+    Ok(())
+}
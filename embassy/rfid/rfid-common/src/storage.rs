@@ -0,0 +1,241 @@
+//! Payloads that span several blocks.
+//!
+//! A payload is stored as one byte stream over consecutive data blocks,
+//! starting at a chosen block. Block 0 (manufacturer data) and the sector
+//! trailers are skipped, so the stream simply continues in the next sector.
+//! It starts with a 4 byte header:
+//!
+//! ```text
+//! length (u16 LE) | CRC-16/CCITT-FALSE of the payload (u16 LE) | payload | zero padding
+//! ```
+//!
+//! The CRC catches a write that was cut short, or a start block that never
+//! held a payload.
+
+use core::fmt;
+
+use heapless::{String, Vec};
+use mfrc522::Uid;
+
+use crate::error::{Operation, RfidError};
+use crate::keys::KeySchedule;
+use crate::layout::{sector_of, trailer_block};
+use crate::reader::CardReader;
+use crate::retry::{self, RetryPolicy};
+
+pub const HEADER_LEN: usize = 4;
+
+/// Last block of a Classic 1K
+pub const LAST_BLOCK_1K: u8 = 63;
+/// Last block of a Classic 4K
+pub const LAST_BLOCK_4K: u8 = 255;
+
+/// A 4K has fewer data blocks than this
+const MAX_BLOCKS: usize = 256;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum StorageError {
+    Rfid(RfidError),
+    /// The payload doesn't fit between the start block and the end of the card
+    TooLarge,
+    /// The header or the CRC doesn't match, nothing (complete) is stored here
+    Corrupt,
+    /// The payload is not valid UTF-8
+    NotText,
+}
+
+impl From<RfidError> for StorageError {
+    fn from(err: RfidError) -> Self {
+        Self::Rfid(err)
+    }
+}
+
+impl fmt::Display for StorageError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Rfid(err) => write!(f, "{}", err),
+            Self::TooLarge => write!(f, "payload does not fit on the card"),
+            Self::Corrupt => write!(f, "no valid payload stored"),
+            Self::NotText => write!(f, "payload is not UTF-8"),
+        }
+    }
+}
+
+/// CRC-16/CCITT-FALSE: polynomial 0x1021, initial value 0xFFFF
+fn crc16(data: &[u8]) -> u16 {
+    let mut crc: u16 = 0xFFFF;
+    for &b in data {
+        crc ^= (b as u16) << 8;
+        for _ in 0..8 {
+            crc = if crc & 0x8000 != 0 {
+                (crc << 1) ^ 0x1021
+            } else {
+                crc << 1
+            };
+        }
+    }
+    crc
+}
+
+/// The blocks a payload may use, in order.
+pub fn data_blocks(first_block: u8, last_block: u8) -> impl Iterator<Item = u8> {
+    (first_block..=last_block)
+        .filter(|&block| block != 0 && block != trailer_block(sector_of(block)))
+}
+
+/// Runs `operation` on each run of blocks that share a sector, after
+/// authenticating that sector.
+fn for_each_sector<R: CardReader>(
+    uid: &Uid,
+    blocks: &[u8],
+    keys: &impl KeySchedule,
+    policy: &RetryPolicy,
+    rfid: &mut R,
+    mut operation: impl FnMut(&mut R, usize, u8) -> Result<(), RfidError>,
+) -> Result<(), RfidError> {
+    let mut index = 0;
+    for run in blocks.chunk_by(|a, b| sector_of(*a) == sector_of(*b)) {
+        let first = run[0];
+        let key = keys.keys(uid.as_bytes(), sector_of(first)).key_a;
+
+        retry::with_retry(policy, uid, rfid, |rfid| {
+            rfid.mf_authenticate(uid, first, &key)
+                .map_err(|e| RfidError::from_mfrc522(e, Operation::Auth, first))?;
+            for (i, &block) in run.iter().enumerate() {
+                operation(rfid, index + i, block)?;
+            }
+            Ok(())
+        })?;
+        index += run.len();
+    }
+    Ok(())
+}
+
+/// Writes `payload` with its header, starting at `first_block`.
+///
+/// `last_block` is the last block of the card (`LAST_BLOCK_1K` or `LAST_BLOCK_4K`).
+pub fn write_payload<R: CardReader>(
+    uid: &Uid,
+    first_block: u8,
+    last_block: u8,
+    payload: &[u8],
+    keys: &impl KeySchedule,
+    policy: &RetryPolicy,
+    rfid: &mut R,
+) -> Result<(), StorageError> {
+    let len = u16::try_from(payload.len()).map_err(|_| StorageError::TooLarge)?;
+    let needed = (HEADER_LEN + payload.len()).div_ceil(16);
+
+    let blocks: Vec<u8, MAX_BLOCKS> = data_blocks(first_block, last_block).take(needed).collect();
+    if blocks.len() < needed {
+        return Err(StorageError::TooLarge);
+    }
+
+    let mut header = [0u8; HEADER_LEN];
+    header[..2].copy_from_slice(&len.to_le_bytes());
+    header[2..].copy_from_slice(&crc16(payload).to_le_bytes());
+
+    let stream = header.iter().chain(payload.iter());
+    for_each_sector(uid, &blocks, keys, policy, rfid, |rfid, index, block| {
+        let mut data = [0u8; 16];
+        for (byte, &value) in data.iter_mut().zip(stream.clone().skip(index * 16)) {
+            *byte = value;
+        }
+        rfid.mf_write(block, data)
+            .map_err(|e| RfidError::from_mfrc522(e, Operation::Write, block))
+    })?;
+    Ok(())
+}
+
+/// Reads back a payload written by `write_payload`, checking its CRC.
+pub fn read_payload<const N: usize, R: CardReader>(
+    uid: &Uid,
+    first_block: u8,
+    last_block: u8,
+    keys: &impl KeySchedule,
+    policy: &RetryPolicy,
+    rfid: &mut R,
+) -> Result<Vec<u8, N>, StorageError> {
+    let Some(first) = data_blocks(first_block, last_block).next() else {
+        return Err(StorageError::Corrupt);
+    };
+
+    let mut header = [0u8; 16];
+    for_each_sector(uid, &[first], keys, policy, rfid, |rfid, _, block| {
+        header = rfid
+            .mf_read(block)
+            .map_err(|e| RfidError::from_mfrc522(e, Operation::Read, block))?;
+        Ok(())
+    })?;
+
+    let len = u16::from_le_bytes([header[0], header[1]]) as usize;
+    let crc = u16::from_le_bytes([header[2], header[3]]);
+    // No payload we wrote is longer than the buffer we read it into
+    if len > N {
+        return Err(StorageError::Corrupt);
+    }
+    let needed = (HEADER_LEN + len).div_ceil(16);
+    let blocks: Vec<u8, MAX_BLOCKS> = data_blocks(first_block, last_block).take(needed).collect();
+    if blocks.len() < needed {
+        return Err(StorageError::Corrupt);
+    }
+
+    let mut payload: Vec<u8, N> = Vec::new();
+    // The first block is already here, only fetch the rest
+    let _ = payload.extend_from_slice(&header[HEADER_LEN..(HEADER_LEN + len).min(16)]);
+    for_each_sector(
+        uid,
+        &blocks[1..],
+        keys,
+        policy,
+        rfid,
+        |rfid, index, block| {
+            let data = rfid
+                .mf_read(block)
+                .map_err(|e| RfidError::from_mfrc522(e, Operation::Read, block))?;
+            // A retry starts the sector over, drop what it read the first time
+            payload.truncate((HEADER_LEN + len).min(16) - HEADER_LEN + index * 16);
+            let remaining = len - payload.len();
+            let _ = payload.extend_from_slice(&data[..remaining.min(16)]);
+            Ok(())
+        },
+    )?;
+
+    if crc16(&payload) != crc {
+        return Err(StorageError::Corrupt);
+    }
+    Ok(payload)
+}
+
+pub fn write_str<R: CardReader>(
+    uid: &Uid,
+    first_block: u8,
+    last_block: u8,
+    text: &str,
+    keys: &impl KeySchedule,
+    policy: &RetryPolicy,
+    rfid: &mut R,
+) -> Result<(), StorageError> {
+    write_payload(
+        uid,
+        first_block,
+        last_block,
+        text.as_bytes(),
+        keys,
+        policy,
+        rfid,
+    )
+}
+
+pub fn read_str<const N: usize, R: CardReader>(
+    uid: &Uid,
+    first_block: u8,
+    last_block: u8,
+    keys: &impl KeySchedule,
+    policy: &RetryPolicy,
+    rfid: &mut R,
+) -> Result<String<N>, StorageError> {
+    let bytes = read_payload::<N, _>(uid, first_block, last_block, keys, policy, rfid)?;
+    String::from_utf8(bytes).map_err(|_| StorageError::NotText)
+}
@@ -0,0 +1,285 @@
+//! Decodes what a UID says about the card, and checks it against block 0.
+//!
+//! A 7 or 10 byte UID starts with the IC manufacturer code from
+//! ISO/IEC 7816-6, so it tells us who made the chip. A 4 byte UID has no
+//! room for that; its first byte says what kind of UID it is instead
+//! (NXP AN10927).
+//!
+//! Block 0 of a MIFARE Classic (the manufacturer block) repeats the UID,
+//! followed by the BCC (XOR of the UID bytes) for 4 byte UIDs, the SAK and
+//! the ATQA. On a genuine card it is written at the factory and can't be
+//! changed. "Magic" clone cards let you rewrite it, and they often give
+//! themselves away with a block 0 that doesn't match how the card answered
+//! the select.
+
+use core::mem::discriminant;
+
+use heapless::Vec;
+use mfrc522::{GenericUid, Type, Uid};
+
+/// The manufacturer data of a widespread clone card: `bcdefghi`
+const CLONE_MANUFACTURER_DATA: [u8; 8] = [0x62, 0x63, 0x64, 0x65, 0x66, 0x67, 0x68, 0x69];
+
+const CASCADE_TAG: u8 = 0x88;
+
+/// IC manufacturer from the first byte of a 7 or 10 byte UID (ISO/IEC 7816-6).
+pub fn manufacturer(code: u8) -> Option<&'static str> {
+    let name = match code {
+        0x01 => "Motorola",
+        0x02 => "STMicroelectronics",
+        0x03 => "Hitachi",
+        0x04 => "NXP Semiconductors",
+        0x05 => "Infineon Technologies",
+        0x06 => "Cylink",
+        0x07 => "Texas Instruments",
+        0x08 => "Fujitsu",
+        0x09 => "Matsushita Electronics",
+        0x0A => "NEC",
+        0x0B => "Oki Electric",
+        0x0C => "Toshiba",
+        0x0D => "Mitsubishi Electric",
+        0x0E => "Samsung Electronics",
+        0x0F => "Hynix",
+        0x10 => "LG Semiconductors",
+        0x11 => "Emosyn-EM Microelectronics",
+        0x12 => "INSIDE Technology",
+        0x13 => "ORGA Kartensysteme",
+        0x14 => "Sharp",
+        0x15 => "ATMEL",
+        0x16 => "EM Microelectronic-Marin",
+        0x17 => "KSW Microtec",
+        0x18 => "ZMD",
+        0x19 => "XICOR",
+        0x1A => "Sony",
+        0x1B => "Malaysia Microelectronic Solutions",
+        0x1C => "Emosyn",
+        0x1D => "Shanghai Fudan Microelectronics",
+        0x1E => "Magellan Technology",
+        0x1F => "Melexis",
+        0x20 => "Renesas Technology",
+        0x21 => "TAGSYS",
+        0x22 => "Transcore",
+        0x23 => "Shanghai Belling",
+        0x24 => "Masktech",
+        0x25 => "Innovision Research and Technology",
+        0x26 => "Hitachi ULSI Systems",
+        0x27 => "Cypak",
+        0x28 => "Ricoh",
+        0x29 => "ASK",
+        0x2A => "Unicore Microsystems",
+        0x2B => "Dallas Semiconductor/Maxim",
+        0x2C => "Impinj",
+        _ => return None,
+    };
+    Some(name)
+}
+
+/// What the first byte of a 4 byte UID says about it (NXP AN10927).
+pub fn single_size_kind(uid0: u8) -> &'static str {
+    match uid0 {
+        0x08 => "random ID, changes on every power-up",
+        CASCADE_TAG => "invalid, starts with the cascade tag",
+        b if b & 0x0F == 0x0F => "fixed, not unique (NUID)",
+        b if b & 0x0F == 0x08 => "reserved for future use",
+        _ => "fixed, unique (ONUID)",
+    }
+}
+
+/// Number of anticollision cascade levels it takes to read the UID.
+pub fn cascade_levels(uid: &Uid) -> u8 {
+    match uid {
+        Uid::Single(_) => 1,
+        Uid::Double(_) => 2,
+        Uid::Triple(_) => 3,
+    }
+}
+
+pub fn type_name(card_type: &Type) -> &'static str {
+    match card_type {
+        Type::MifareMini => "MIFARE Classic Mini",
+        Type::Mifare1k => "MIFARE Classic 1K",
+        Type::Mifare4k => "MIFARE Classic 4K",
+        Type::MifareUL => "MIFARE Ultralight / NTAG",
+        Type::MifarePlus => "MIFARE Plus",
+        Type::MifareDesfire => "MIFARE DESFire",
+        Type::Iso14443_4 => "ISO/IEC 14443-4",
+        Type::Iso18092 => "ISO/IEC 18092 (NFC-DEP)",
+        Type::TNP3XXX => "TNP3XXX",
+        Type::NotComplete => "UID not complete",
+        Type::Unknown => "unknown",
+    }
+}
+
+pub fn is_classic(card_type: &Type) -> bool {
+    matches!(
+        card_type,
+        Type::MifareMini | Type::Mifare1k | Type::Mifare4k
+    )
+}
+
+fn bcc(bytes: &[u8]) -> u8 {
+    bytes.iter().fold(0, |bcc, b| bcc ^ b)
+}
+
+/// Things in block 0 of a Classic that a genuine card would not have.
+///
+/// An empty list means block 0 is consistent with the select.
+pub fn check_classic_block0(uid: &Uid, block0: &[u8; 16]) -> Vec<&'static str, 6> {
+    let mut findings = Vec::new();
+    let uid_bytes = uid.as_bytes();
+
+    if block0[..uid_bytes.len()] != *uid_bytes {
+        let _ = findings.push("UID in block 0 differs from the one the card answered with");
+    }
+
+    // 4 byte UIDs are followed by their BCC, then SAK and ATQA
+    let sak_index = match uid {
+        Uid::Single(_) => {
+            if block0[4] != bcc(&block0[..4]) {
+                let _ = findings.push("BCC in block 0 is wrong");
+            }
+            5
+        }
+        _ => uid_bytes.len(),
+    };
+
+    let block0_type = GenericUid::new([0u8; 4], block0[sak_index]).get_type();
+    if discriminant(&block0_type) != discriminant(&uid.get_type()) {
+        let _ = findings.push("SAK in block 0 doesn't match the SAK of the select");
+    }
+
+    if block0[8..] == CLONE_MANUFACTURER_DATA {
+        let _ = findings.push("Manufacturer data 62..69 (\"bcdefghi\") of a common clone");
+    }
+    if uid_bytes[0] == CASCADE_TAG {
+        let _ = findings.push("UID starts with the cascade tag");
+    }
+    if uid_bytes.len() > 4 && manufacturer(uid_bytes[0]).is_none() {
+        let _ = findings.push("Unknown IC manufacturer code");
+    }
+    findings
+}
+
+/// Same idea for an Ultralight or NTAG, from pages 0 to 3 (one `mf_read(0)`).
+///
+/// Their 7 byte UID is spread over pages 0 and 1 with two check bytes:
+/// BCC0 = 88 ^ UID0..2 (the cascade tag counts) and BCC1 = UID3..6.
+pub fn check_ultralight_pages(uid: &Uid, pages: &[u8; 16]) -> Vec<&'static str, 6> {
+    let mut findings = Vec::new();
+    let uid_bytes = uid.as_bytes();
+    if uid_bytes.len() != 7 {
+        let _ = findings.push("Ultralight without a 7 byte UID");
+        return findings;
+    }
+
+    if pages[..3] != uid_bytes[..3] || pages[4..8] != uid_bytes[3..] {
+        let _ = findings.push("UID in pages 0-1 differs from the one the card answered with");
+    }
+    if pages[3] != CASCADE_TAG ^ bcc(&pages[..3]) {
+        let _ = findings.push("BCC0 in page 0 is wrong");
+    }
+    if pages[8] != bcc(&pages[4..8]) {
+        let _ = findings.push("BCC1 in page 2 is wrong");
+    }
+    if manufacturer(uid_bytes[0]).is_none() {
+        let _ = findings.push("Unknown IC manufacturer code");
+    }
+    findings
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A 1K as it comes from the factory: UID, BCC, SAK 08, ATQA 04 00
+    const UID: [u8; 4] = [0xA1, 0xB2, 0xC3, 0xD4];
+    const BLOCK0: [u8; 16] = [
+        0xA1, 0xB2, 0xC3, 0xD4, 0x04, 0x08, 0x04, 0x00, 0x46, 0x59, 0x25, 0x58, 0x49, 0x10, 0x23,
+        0x02,
+    ];
+
+    fn selected(sak: u8) -> Uid {
+        Uid::Single(GenericUid::new(UID, sak))
+    }
+
+    #[test]
+    fn genuine_block0() {
+        assert!(check_classic_block0(&selected(0x08), &BLOCK0).is_empty());
+    }
+
+    #[test]
+    fn wrong_bcc() {
+        let mut block0 = BLOCK0;
+        block0[4] ^= 0xFF;
+        assert_eq!(
+            check_classic_block0(&selected(0x08), &block0),
+            ["BCC in block 0 is wrong"]
+        );
+    }
+
+    #[test]
+    fn sak_differs_from_the_select() {
+        // The card answered as a 4K, block 0 says 1K
+        assert_eq!(
+            check_classic_block0(&selected(0x18), &BLOCK0),
+            ["SAK in block 0 doesn't match the SAK of the select"]
+        );
+    }
+
+    #[test]
+    fn clone_manufacturer_data() {
+        let mut block0 = BLOCK0;
+        block0[8..].copy_from_slice(&CLONE_MANUFACTURER_DATA);
+        assert_eq!(
+            check_classic_block0(&selected(0x08), &block0),
+            ["Manufacturer data 62..69 (\"bcdefghi\") of a common clone"]
+        );
+    }
+
+    #[test]
+    fn uid_differs_from_the_select() {
+        let mut block0 = BLOCK0;
+        // Another UID, with its own BCC
+        block0[..5].copy_from_slice(&[0x12, 0x34, 0x56, 0x78, 0x08]);
+        assert_eq!(
+            check_classic_block0(&selected(0x08), &block0),
+            ["UID in block 0 differs from the one the card answered with"]
+        );
+    }
+
+    #[test]
+    fn ultralight_pages() {
+        // NTAG213 with UID 04 A2 2F 6A 1C 5E 80
+        let uid = Uid::Double(GenericUid::new(
+            [0x04, 0xA2, 0x2F, 0x6A, 0x1C, 0x5E, 0x80],
+            0x00,
+        ));
+        // BCC0 = 88 ^ 04 ^ A2 ^ 2F = 01, BCC1 = 6A ^ 1C ^ 5E ^ 80 = A8
+        let mut pages = [
+            0x04, 0xA2, 0x2F, 0x01, 0x6A, 0x1C, 0x5E, 0x80, 0xA8, 0x48, 0x00, 0x00, 0xE1, 0x10,
+            0x12, 0x00,
+        ];
+        assert!(check_ultralight_pages(&uid, &pages).is_empty());
+
+        pages[8] ^= 0x01;
+        assert_eq!(
+            check_ultralight_pages(&uid, &pages),
+            ["BCC1 in page 2 is wrong"]
+        );
+    }
+
+    #[test]
+    fn single_size_kinds() {
+        assert_eq!(
+            single_size_kind(0x08),
+            "random ID, changes on every power-up"
+        );
+        assert_eq!(
+            single_size_kind(0x88),
+            "invalid, starts with the cascade tag"
+        );
+        assert_eq!(single_size_kind(0x3F), "fixed, not unique (NUID)");
+        assert_eq!(single_size_kind(0x18), "reserved for future use");
+        assert_eq!(single_size_kind(0xA1), "fixed, unique (ONUID)");
+    }
+}
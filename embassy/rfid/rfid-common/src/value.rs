@@ -0,0 +1,269 @@
+//! MIFARE Classic value blocks.
+//!
+//! A value block stores a signed 32-bit value three times (once inverted)
+//! and a one byte address four times (twice inverted):
+//!
+//! ```text
+//! byte:  0..4    4..8     8..12   12    13     14    15
+//!        value   !value   value   addr  !addr  addr  !addr
+//! ```
+//!
+//! The card only accepts increment, decrement and restore on blocks in this
+//! format. The result of those commands lands in an internal register and
+//! is only stored once it is transferred to a block.
+
+use mfrc522::{Initialized, Mfrc522};
+
+const MF_DECREMENT: u8 = 0xC0;
+const MF_INCREMENT: u8 = 0xC1;
+const MF_RESTORE: u8 = 0xC2;
+const MF_TRANSFER: u8 = 0xB0;
+
+// 4-bit acknowledge sent back by the card
+const MF_ACK: u8 = 0x0A;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct ValueBlock {
+    pub value: i32,
+    /// Free for the application, usually the block number of a backup block
+    pub address: u8,
+}
+
+impl ValueBlock {
+    pub const fn new(value: i32, address: u8) -> Self {
+        Self { value, address }
+    }
+
+    pub fn encode(&self) -> [u8; 16] {
+        let value = self.value.to_le_bytes();
+        let inverted = (!self.value).to_le_bytes();
+
+        let mut block = [0u8; 16];
+        block[0..4].copy_from_slice(&value);
+        block[4..8].copy_from_slice(&inverted);
+        block[8..12].copy_from_slice(&value);
+        block[12] = self.address;
+        block[13] = !self.address;
+        block[14] = self.address;
+        block[15] = !self.address;
+        block
+    }
+
+    /// Decodes a block, rejecting it unless every copy agrees.
+    pub fn decode(block: &[u8; 16]) -> Result<Self, &'static str> {
+        let word =
+            |start: usize| i32::from_le_bytes(block[start..start + 4].try_into().expect("4 bytes"));
+
+        let value = word(0);
+        if word(4) != !value || word(8) != value {
+            return Err("Value copies do not match");
+        }
+
+        let address = block[12];
+        if block[13] != !address || block[14] != address || block[15] != !address {
+            return Err("Address copies do not match");
+        }
+
+        Ok(Self { value, address })
+    }
+}
+
+/// CRC_A from ISO/IEC 14443-3, appended to every frame sent to the card.
+///
+/// The MFRC522 can calculate it too, but the driver keeps that private.
+pub fn crc_a(data: &[u8]) -> [u8; 2] {
+    let mut crc: u16 = 0x6363;
+    for &byte in data {
+        let mut b = byte ^ (crc as u8);
+        b ^= b << 4;
+        crc = (crc >> 8) ^ ((b as u16) << 8) ^ ((b as u16) << 3) ^ ((b as u16) >> 4);
+    }
+    crc.to_le_bytes()
+}
+
+fn send_command<E, COMM>(
+    command: u8,
+    block: u8,
+    rfid: &mut Mfrc522<COMM, Initialized>,
+) -> Result<(), &'static str>
+where
+    COMM: mfrc522::comm::Interface<Error = E>,
+{
+    let mut tx = [command, block, 0, 0];
+    let crc = crc_a(&tx[..2]);
+    tx[2..].copy_from_slice(&crc);
+
+    let rx = rfid.transceive::<1>(&tx, 0, 0).map_err(|_| "No answer")?;
+    if rx.valid_bytes != 1 || rx.valid_bits != 4 || rx.buffer[0] & 0x0F != MF_ACK {
+        return Err("NAK");
+    }
+    Ok(())
+}
+
+/// Runs the second half of increment, decrement and restore.
+///
+/// The card does not answer this frame when it succeeds, so a timeout is
+/// what we expect here.
+fn send_operand<E, COMM>(
+    operand: u32,
+    rfid: &mut Mfrc522<COMM, Initialized>,
+) -> Result<(), &'static str>
+where
+    COMM: mfrc522::comm::Interface<Error = E>,
+{
+    let mut tx = [0u8; 6];
+    tx[..4].copy_from_slice(&operand.to_le_bytes());
+    let crc = crc_a(&tx[..4]);
+    tx[4..].copy_from_slice(&crc);
+
+    match rfid.transceive::<1>(&tx, 0, 0) {
+        Err(mfrc522::Error::Timeout) => Ok(()),
+        Ok(_) => Err("NAK"),
+        Err(_) => Err("Operand failed"),
+    }
+}
+
+/// Formats a block as a value block. Needs write access to the block.
+pub fn format<E, COMM>(
+    block: u8,
+    value: ValueBlock,
+    rfid: &mut Mfrc522<COMM, Initialized>,
+) -> Result<(), &'static str>
+where
+    COMM: mfrc522::comm::Interface<Error = E>,
+{
+    rfid.mf_write(block, value.encode())
+        .map_err(|_| "Write failed")
+}
+
+pub fn read<E, COMM>(
+    block: u8,
+    rfid: &mut Mfrc522<COMM, Initialized>,
+) -> Result<ValueBlock, &'static str>
+where
+    COMM: mfrc522::comm::Interface<Error = E>,
+{
+    let data = rfid.mf_read(block).map_err(|_| "Read failed")?;
+    ValueBlock::decode(&data)
+}
+
+/// Adds `amount` to the block's value, into the card's internal register.
+pub fn increment<E, COMM>(
+    block: u8,
+    amount: u32,
+    rfid: &mut Mfrc522<COMM, Initialized>,
+) -> Result<(), &'static str>
+where
+    COMM: mfrc522::comm::Interface<Error = E>,
+{
+    send_command(MF_INCREMENT, block, rfid)?;
+    send_operand(amount, rfid)
+}
+
+/// Subtracts `amount` from the block's value, into the card's internal register.
+pub fn decrement<E, COMM>(
+    block: u8,
+    amount: u32,
+    rfid: &mut Mfrc522<COMM, Initialized>,
+) -> Result<(), &'static str>
+where
+    COMM: mfrc522::comm::Interface<Error = E>,
+{
+    send_command(MF_DECREMENT, block, rfid)?;
+    send_operand(amount, rfid)
+}
+
+/// Copies the block's value into the card's internal register.
+pub fn restore<E, COMM>(
+    block: u8,
+    rfid: &mut Mfrc522<COMM, Initialized>,
+) -> Result<(), &'static str>
+where
+    COMM: mfrc522::comm::Interface<Error = E>,
+{
+    send_command(MF_RESTORE, block, rfid)?;
+    send_operand(0, rfid)
+}
+
+/// Writes the card's internal register to a block.
+pub fn transfer<E, COMM>(
+    block: u8,
+    rfid: &mut Mfrc522<COMM, Initialized>,
+) -> Result<(), &'static str>
+where
+    COMM: mfrc522::comm::Interface<Error = E>,
+{
+    send_command(MF_TRANSFER, block, rfid)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encode_layout() {
+        let block = ValueBlock::new(100, 5).encode();
+        assert_eq!(
+            block,
+            [
+                0x64, 0x00, 0x00, 0x00, 0x9B, 0xFF, 0xFF, 0xFF, //
+                0x64, 0x00, 0x00, 0x00, 0x05, 0xFA, 0x05, 0xFA,
+            ]
+        );
+    }
+
+    #[test]
+    fn round_trip() {
+        for value in [0, 1, 100, 0x1234_5678, i32::MAX] {
+            for address in [0, 5, 0x80, 0xFF] {
+                let block = ValueBlock::new(value, address);
+                assert_eq!(ValueBlock::decode(&block.encode()), Ok(block));
+            }
+        }
+    }
+
+    #[test]
+    fn negative_values() {
+        for value in [-1, -100, i32::MIN] {
+            let block = ValueBlock::new(value, 4);
+            let encoded = block.encode();
+            assert_eq!(encoded[0..4], value.to_le_bytes());
+            assert_eq!(encoded[4..8], (!value).to_le_bytes());
+            assert_eq!(ValueBlock::decode(&encoded), Ok(block));
+        }
+    }
+
+    #[test]
+    fn wrong_address_byte_is_rejected() {
+        let encoded = ValueBlock::new(100, 5).encode();
+        for byte in 12..16 {
+            let mut corrupted = encoded;
+            corrupted[byte] ^= 0x01;
+            assert!(ValueBlock::decode(&corrupted).is_err(), "byte {byte}");
+        }
+    }
+
+    #[test]
+    fn corrupted_value_copy_is_rejected() {
+        let encoded = ValueBlock::new(-100, 5).encode();
+        // Plain copy, inverted copy, second plain copy
+        for byte in 0..12 {
+            for bit in 0..8 {
+                let mut corrupted = encoded;
+                corrupted[byte] ^= 1 << bit;
+                assert!(
+                    ValueBlock::decode(&corrupted).is_err(),
+                    "byte {byte}, bit {bit}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn crc_a_check_value() {
+        // ISO/IEC 14443-3 annex B examples
+        assert_eq!(crc_a(&[0x00, 0x00]), [0xA0, 0x1E]);
+        assert_eq!(crc_a(&[0x12, 0x34]), [0x26, 0xCF]);
+    }
+}
@@ -2,10 +2,10 @@
 #![no_main]
 
 use embassy_executor::Spawner;
-use embassy_time::Timer;
+use embassy_time::{Duration, Instant, Timer};
 
 // defmt Logging
-use defmt::info;
+use defmt::{error, info, warn};
 use defmt_rtt as _;
 
 use panic_probe as _;
@@ -22,8 +22,29 @@ use embassy_rp::gpio::{Level, Output};
 // Driver for the MFRC522
 use mfrc522::{Mfrc522, comm::blocking::spi::SpiInterface};
 
-// Replace the UID Bytes with your tag UID
-const TAG_UID: [u8; 4] = [0x13, 0x37, 0x73, 0x31];
+// For the allow-list
+use embassy_rp::flash::{Blocking, Flash};
+
+use rfid_common::allowlist::{self, AllowList};
+
+// Replace the UID Bytes with the UID of your master card
+const MASTER_UID: [u8; 4] = [0x13, 0x37, 0x73, 0x31];
+
+const FLASH_SIZE: usize = 2 * 1024 * 1024;
+// Matches the 8K left out of FLASH in memory.x
+const ALLOWLIST_OFFSET: u32 = FLASH_SIZE as u32 - allowlist::STORAGE_SIZE;
+
+// Programming mode ends on its own if no card shows up for this long
+const PROGRAM_TIMEOUT: Duration = Duration::from_secs(15);
+
+async fn blink(led: &mut Output<'_>, times: u8) {
+    for _ in 0..times {
+        led.set_high();
+        Timer::after_millis(150).await;
+        led.set_low();
+        Timer::after_millis(150).await;
+    }
+}
 
 #[embassy_executor::main]
 async fn main(_spawner: Spawner) {
@@ -54,17 +75,62 @@ async fn main(_spawner: Spawner) {
     // Using External LED, connected to GPIO 15
     let mut led = Output::new(p.PIN_15, Level::Low);
 
+    let flash = Flash::<_, Blocking, FLASH_SIZE>::new_blocking(p.FLASH);
+    let mut allowlist =
+        AllowList::load(flash, ALLOWLIST_OFFSET).expect("failed to load the allow-list");
+    info!("{} cards on the allow-list", allowlist.len());
+
+    // Set while the master card has put us in programming mode
+    let mut programming_since: Option<Instant> = None;
+
     loop {
         led.set_low();
 
         if let Ok(atqa) = rfid.reqa() {
             if let Ok(uid) = rfid.select(&atqa) {
-                if *uid.as_bytes() == TAG_UID {
+                let uid = uid.as_bytes();
+
+                if uid == MASTER_UID {
+                    if programming_since.take().is_some() {
+                        info!("Programming mode off");
+                    } else {
+                        info!("Programming mode on: present cards to enroll or revoke them");
+                        programming_since = Some(Instant::now());
+                    }
+                    blink(&mut led, 3).await;
+                } else if programming_since.is_some() {
+                    programming_since = Some(Instant::now());
+                    match allowlist.toggle(uid) {
+                        Ok(true) => {
+                            info!("Enrolled {=[u8]:02x}", uid);
+                            blink(&mut led, 2).await;
+                        }
+                        Ok(false) => {
+                            info!("Revoked {=[u8]:02x}", uid);
+                            blink(&mut led, 1).await;
+                        }
+                        Err(e) => error!("Error updating the allow-list: {:?}", e),
+                    }
+                } else if allowlist.contains(uid) {
+                    info!("Access granted");
                     led.set_high();
                     Timer::after_millis(500).await;
+                } else {
+                    warn!("Access denied for {=[u8]:02x}", uid);
                 }
+
+                // The card stays quiet until it is taken away and presented again
+                let _ = rfid.hlta();
             }
         }
+
+        if let Some(since) = programming_since
+            && since.elapsed() > PROGRAM_TIMEOUT
+        {
+            info!("Programming mode timed out");
+            programming_since = None;
+        }
+
         Timer::after_millis(100).await;
     }
 }
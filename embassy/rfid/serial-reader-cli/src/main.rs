@@ -0,0 +1,184 @@
+//! Drives the `serial-reader` firmware from the command line.
+//!
+//! Every command selects the card on the reader first, so scripts can run
+//! them one after another without keeping any state on the host.
+
+pub mod port;
+
+use std::process::ExitCode;
+
+use rfid_common::protocol::Command;
+
+use crate::port::Port;
+
+const USAGE: &str = "\
+Usage: serial-reader-cli <port> <command>
+
+Commands:
+  version                                  MFRC522 version register
+  uid                                      UID of the card on the reader
+  auth <block> <key A>                     Check that the key opens the block's sector
+  read <block> <key A>                     Read one block
+  write <block> <data> <key A>             Write one block (16 bytes of hex)
+  dump <key A> [sectors]                   Read every sector, 16 by default
+  change-key <sector> <key A> <new key A> <new key B> [access bits]
+                                           Write a new trailer, the access bits
+                                           (with GPB) default to FF078069
+
+Keys are 12 hex digits, e.g. FFFFFFFFFFFF.";
+
+fn parse_hex<const N: usize>(text: &str, what: &str) -> Result<[u8; N], String> {
+    let text = text.trim();
+    if !text.is_ascii() || text.len() != N * 2 {
+        return Err(format!("{} must be {} hex digits", what, N * 2));
+    }
+
+    let mut bytes = [0u8; N];
+    for (i, byte) in bytes.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(&text[i * 2..i * 2 + 2], 16)
+            .map_err(|_| format!("{} is not valid hex", what))?;
+    }
+    Ok(bytes)
+}
+
+fn parse_number(text: &str, what: &str) -> Result<u8, String> {
+    text.parse()
+        .map_err(|_| format!("{} must be a number from 0 to 255", what))
+}
+
+fn hex(data: &[u8]) -> String {
+    data.iter()
+        .map(|b| format!("{:02X}", b))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn select(port: &mut Port) -> Result<Vec<u8>, String> {
+    port.request(Command::Select, &[])
+}
+
+fn authenticate(port: &mut Port, block: u8, key: &[u8; 6]) -> Result<(), String> {
+    let mut args = vec![block];
+    args.extend_from_slice(key);
+    port.request(Command::Auth, &args)?;
+    Ok(())
+}
+
+fn run(port: &mut Port, command: &str, args: &[String]) -> Result<(), String> {
+    let arg = |i: usize, what: &str| {
+        args.get(i)
+            .map(String::as_str)
+            .ok_or(format!("Missing {}\n\n{}", what, USAGE))
+    };
+
+    match command {
+        "version" => {
+            let version = port.request(Command::Version, &[])?;
+            let name = match version.first() {
+                Some(0x91) => "v1.0",
+                Some(0x92) => "v2.0",
+                Some(0x88) => "FM17522 clone",
+                _ => "unknown",
+            };
+            println!("VersionReg: {} ({})", hex(&version), name);
+        }
+        "uid" => {
+            let uid = select(port)?;
+            println!("UID: {}", hex(&uid));
+        }
+        "auth" => {
+            let block = parse_number(arg(0, "block")?, "block")?;
+            let key = parse_hex::<6>(arg(1, "key")?, "key")?;
+            select(port)?;
+            authenticate(port, block, &key)?;
+            println!("Key opens sector {}", block / 4);
+        }
+        "read" => {
+            let block = parse_number(arg(0, "block")?, "block")?;
+            let key = parse_hex::<6>(arg(1, "key")?, "key")?;
+            select(port)?;
+            authenticate(port, block, &key)?;
+            let data = port.request(Command::ReadBlock, &[block])?;
+            println!("Block {:3}: {}", block, hex(&data));
+        }
+        "write" => {
+            let block = parse_number(arg(0, "block")?, "block")?;
+            let data = parse_hex::<16>(arg(1, "data")?, "data")?;
+            let key = parse_hex::<6>(arg(2, "key")?, "key")?;
+            select(port)?;
+            authenticate(port, block, &key)?;
+
+            let mut request = vec![block];
+            request.extend_from_slice(&data);
+            port.request(Command::WriteBlock, &request)?;
+            println!("Written block {}", block);
+        }
+        "dump" => {
+            let key = parse_hex::<6>(arg(0, "key")?, "key")?;
+            let sectors = match args.get(1) {
+                Some(sectors) => parse_number(sectors, "sectors")?,
+                None => 16,
+            };
+
+            let uid = select(port)?;
+            println!("UID: {}", hex(&uid));
+
+            let mut request = vec![0];
+            request.extend_from_slice(&key);
+            for sector in 0..sectors {
+                request[0] = sector;
+                match port.request(Command::ReadSector, &request) {
+                    Ok(data) => {
+                        for (rel_block, block) in data.chunks(16).enumerate() {
+                            println!(
+                                "Block {:3}: {}",
+                                sector as usize * 4 + rel_block,
+                                hex(block)
+                            );
+                        }
+                    }
+                    Err(e) => println!("Sector {:2}: {}", sector, e),
+                }
+            }
+        }
+        "change-key" => {
+            let sector = parse_number(arg(0, "sector")?, "sector")?;
+            let key = parse_hex::<6>(arg(1, "key")?, "key")?;
+            let new_key_a = parse_hex::<6>(arg(2, "new key A")?, "new key A")?;
+            let new_key_b = parse_hex::<6>(arg(3, "new key B")?, "new key B")?;
+            let access = match args.get(4) {
+                Some(access) => parse_hex::<4>(access, "access bits")?,
+                None => [0xFF, 0x07, 0x80, 0x69],
+            };
+
+            let mut request = vec![sector];
+            request.extend_from_slice(&key);
+            request.extend_from_slice(&new_key_a);
+            request.extend_from_slice(&access);
+            request.extend_from_slice(&new_key_b);
+
+            select(port)?;
+            port.request(Command::ChangeKey, &request)?;
+            println!("Sector {} now opens with {}", sector, hex(&new_key_a));
+        }
+        _ => return Err(format!("Unknown command {}\n\n{}", command, USAGE)),
+    }
+    Ok(())
+}
+
+fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let [path, command, rest @ ..] = args.as_slice() else {
+        eprintln!("{}", USAGE);
+        return ExitCode::FAILURE;
+    };
+
+    let result = Port::open(path).and_then(|mut port| run(&mut port, command, rest));
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
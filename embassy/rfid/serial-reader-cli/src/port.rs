@@ -0,0 +1,104 @@
+//! The reader's USB serial port, speaking the request/response protocol.
+
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Write};
+use std::os::fd::AsRawFd;
+use std::os::unix::fs::OpenOptionsExt;
+use std::time::{Duration, Instant};
+use rfid_common::protocol::{self, Command, FrameReader, MAX_FRAME, MAX_PAYLOAD, Status};
+
+/// Long enough for a `ChangeKey`, which authenticates twice and writes
+const RESPONSE_TIMEOUT: Duration = Duration::from_secs(2);
+
+pub struct Port {
+    file: File,
+    sequence: u8,
+    frames: FrameReader,
+}
+
+/// Puts the terminal in raw mode, so the kernel doesn't echo bytes back to
+/// the reader or turn `0D` into `0A`. Reads return after 100 ms without data.
+fn make_raw(file: &File) -> std::io::Result<()> {
+    let fd = file.as_raw_fd();
+    // SAFETY: `termios` is plain data, filled in by `tcgetattr` before use,
+    // and `fd` is an open file for the duration of the calls.
+    unsafe {
+        let mut termios: libc::termios = std::mem::zeroed();
+        if libc::tcgetattr(fd, &mut termios) != 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+        libc::cfmakeraw(&mut termios);
+        termios.c_cc[libc::VMIN] = 0;
+        termios.c_cc[libc::VTIME] = 1;
+        if libc::tcsetattr(fd, libc::TCSANOW, &termios) != 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+        // Drop whatever the reader sent before we opened the port
+        libc::tcflush(fd, libc::TCIFLUSH);
+    }
+    Ok(())
+}
+
+impl Port {
+    pub fn open(path: &str) -> Result<Self, String> {
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .custom_flags(libc::O_NOCTTY)
+            .open(path)
+            .map_err(|e| format!("Can't open {}: {}", path, e))?;
+        make_raw(&file).map_err(|e| format!("Can't configure {}: {}", path, e))?;
+
+        Ok(Self {
+            file,
+            sequence: 0,
+            frames: FrameReader::new(),
+        })
+    }
+
+    /// Sends a request and waits for its response, returning the response data.
+    pub fn request(&mut self, command: Command, args: &[u8]) -> Result<Vec<u8>, String> {
+        self.sequence = self.sequence.wrapping_add(1);
+
+        let mut payload = vec![self.sequence, command as u8];
+        payload.extend_from_slice(args);
+        if payload.len() > MAX_PAYLOAD {
+            return Err("Request too long".to_string());
+        }
+
+        let mut frame = [0u8; MAX_FRAME];
+        let len = protocol::encode_frame(&payload, &mut frame)?;
+        self.file
+            .write_all(&frame[..len])
+            .map_err(|e| format!("Write failed: {}", e))?;
+
+        let deadline = Instant::now() + RESPONSE_TIMEOUT;
+        let mut buffer = [0u8; 64];
+        while Instant::now() < deadline {
+            let read = self
+                .file
+                .read(&mut buffer)
+                .map_err(|e| format!("Read failed: {}", e))?;
+
+            for &byte in &buffer[..read] {
+                let Some(response) = self.frames.push(byte) else {
+                    continue;
+                };
+                // An answer to an earlier request that timed out
+                let [sequence, status, data @ ..] = response else {
+                    continue;
+                };
+                if *sequence != self.sequence {
+                    continue;
+                }
+
+                return match Status::from_u8(*status) {
+                    Some(Status::Ok) => Ok(data.to_vec()),
+                    Some(status) => Err(status.as_str().to_string()),
+                    None => Err(format!("Unknown status {:02X}", status)),
+                };
+            }
+        }
+        Err("No response from the reader".to_string())
+    }
+}
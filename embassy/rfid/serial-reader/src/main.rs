@@ -0,0 +1,139 @@
+#![no_std]
+#![no_main]
+
+pub mod session;
+
+use embassy_executor::Spawner;
+use embassy_futures::join::join;
+use embassy_time::Timer;
+
+use panic_halt as _;
+
+// For USB
+use embassy_rp::{peripherals::USB, usb};
+use embassy_usb::Builder;
+use embassy_usb::class::cdc_acm::{CdcAcmClass, State};
+use embassy_usb::driver::EndpointError;
+
+// For SPI
+use embassy_rp::spi;
+use embassy_rp::spi::Spi;
+use embassy_time::Delay;
+use embedded_hal_bus::spi::ExclusiveDevice;
+
+// For CS Pin
+use embassy_rp::gpio::{Level, Output};
+
+// Driver for the MFRC522
+use mfrc522::{Mfrc522, comm::blocking::spi::SpiInterface};
+
+use rfid_common::protocol::{self, FrameReader, MAX_FRAME, MAX_PAYLOAD};
+
+use crate::session::Session;
+
+embassy_rp::bind_interrupts!(struct Irqs {
+    USBCTRL_IRQ => usb::InterruptHandler<USB>;
+});
+
+const MAX_PACKET_SIZE: u16 = 64;
+
+/// Sends a frame, split into USB packets.
+async fn write_frame<'d, D: embassy_usb::driver::Driver<'d>>(
+    class: &mut CdcAcmClass<'d, D>,
+    frame: &[u8],
+) -> Result<(), EndpointError> {
+    for packet in frame.chunks(MAX_PACKET_SIZE as usize) {
+        class.write_packet(packet).await?;
+    }
+    // A full last packet needs an empty one after it, or the host keeps waiting for more
+    if frame.len().is_multiple_of(MAX_PACKET_SIZE as usize) {
+        class.write_packet(&[]).await?;
+    }
+    Ok(())
+}
+
+#[embassy_executor::main]
+async fn main(_spawner: Spawner) {
+    let p = embassy_rp::init(Default::default());
+
+    // USB serial port
+    let driver = usb::Driver::new(p.USB, Irqs);
+
+    let mut config = embassy_usb::Config::new(0xc0de, 0xcafe);
+    config.manufacturer = Some("implRust");
+    config.product = Some("RFID serial reader");
+    config.serial_number = Some("12345678");
+
+    let mut config_descriptor = [0; 256];
+    let mut bos_descriptor = [0; 256];
+    let mut msos_descriptor = [0; 256];
+    let mut control_buf = [0; 64];
+    let mut state = State::new();
+
+    let mut builder = Builder::new(
+        driver,
+        config,
+        &mut config_descriptor,
+        &mut bos_descriptor,
+        &mut msos_descriptor,
+        &mut control_buf,
+    );
+
+    let mut class = CdcAcmClass::new(&mut builder, &mut state, MAX_PACKET_SIZE);
+
+    let mut usb = builder.build();
+
+    // RFID reader
+    let miso = p.PIN_0;
+    let cs_pin = Output::new(p.PIN_1, Level::High);
+    let clk = p.PIN_2;
+    let mosi = p.PIN_3;
+
+    let mut config = spi::Config::default();
+    config.frequency = 1_000_000;
+
+    let spi_bus = Spi::new_blocking(p.SPI0, clk, mosi, miso, config);
+    let spi = ExclusiveDevice::new(spi_bus, cs_pin, Delay).expect("Failed to get exclusive device");
+
+    let itf = SpiInterface::new(spi);
+
+    let serve = async {
+        // Without a reader there is nothing to serve, the host's requests time out
+        let Ok(rfid) = Mfrc522::new(itf).init() else {
+            loop {
+                Timer::after_secs(1).await;
+            }
+        };
+        let mut session = Session::new(rfid);
+        let mut frames = FrameReader::new();
+
+        loop {
+            class.wait_connection().await;
+
+            let mut packet = [0u8; MAX_PACKET_SIZE as usize];
+            'connected: loop {
+                let Ok(len) = class.read_packet(&mut packet).await else {
+                    break;
+                };
+
+                for &byte in &packet[..len] {
+                    let Some(request) = frames.push(byte) else {
+                        continue;
+                    };
+
+                    let mut response = [0u8; MAX_PAYLOAD];
+                    let response_len = session.handle(request, &mut response);
+
+                    let mut frame = [0u8; MAX_FRAME];
+                    let frame_len = protocol::encode_frame(&response[..response_len], &mut frame)
+                        .expect("response fits in a frame");
+                    if write_frame(&mut class, &frame[..frame_len]).await.is_err() {
+                        break 'connected;
+                    }
+                }
+            }
+        }
+    };
+
+    join(usb.run(), serve).await;
+}
@@ -0,0 +1,223 @@
+//! Runs the requests that come in over USB against the reader.
+//!
+//! The session remembers the selected card and the sector it is
+//! authenticated to between requests, so the host can `Auth` once and
+//! then read or write the blocks of that sector.
+
+use mfrc522::comm::Interface;
+use mfrc522::{Initialized, Mfrc522, Uid};
+use rfid_common::access::AccessConditions;
+use rfid_common::protocol::{Command, MAX_PAYLOAD, Status};
+use rfid_common::retry;
+
+/// Blocks 0..128 are the 4-block sectors of every MIFARE Classic size
+const MAX_BLOCK: u8 = 127;
+
+pub struct Session<COMM: Interface> {
+    rfid: Mfrc522<COMM, Initialized>,
+    uid: Option<Uid>,
+    authenticated: Option<u8>,
+}
+
+impl<COMM: Interface> Session<COMM> {
+    pub fn new(rfid: Mfrc522<COMM, Initialized>) -> Self {
+        Self {
+            rfid,
+            uid: None,
+            authenticated: None,
+        }
+    }
+
+    /// Runs one request and writes the response payload, returning its length.
+    pub fn handle(&mut self, request: &[u8], response: &mut [u8; MAX_PAYLOAD]) -> usize {
+        let [sequence, command, args @ ..] = request else {
+            response[..2].copy_from_slice(&[
+                request.first().copied().unwrap_or(0),
+                Status::BadRequest as u8,
+            ]);
+            return 2;
+        };
+        response[0] = *sequence;
+
+        let mut data = [0u8; MAX_PAYLOAD - 2];
+        let result = match Command::from_u8(*command) {
+            Some(command) => self.run(command, args, &mut data),
+            None => Err(Status::UnknownCommand),
+        };
+
+        match result {
+            Ok(len) => {
+                response[1] = Status::Ok as u8;
+                response[2..2 + len].copy_from_slice(&data[..len]);
+                2 + len
+            }
+            Err(status) => {
+                response[1] = status as u8;
+                2
+            }
+        }
+    }
+
+    fn run(&mut self, command: Command, args: &[u8], data: &mut [u8]) -> Result<usize, Status> {
+        match command {
+            Command::Version => {
+                let [] = args else {
+                    return Err(Status::BadRequest);
+                };
+                data[0] = self.rfid.version().map_err(|_| Status::CardError)?;
+                Ok(1)
+            }
+            Command::Select => {
+                let [] = args else {
+                    return Err(Status::BadRequest);
+                };
+                let uid = self.select()?;
+                data[..uid.len()].copy_from_slice(uid);
+                Ok(uid.len())
+            }
+            Command::Auth => {
+                let [block, key @ ..] = args else {
+                    return Err(Status::BadRequest);
+                };
+                let key: &[u8; 6] = key.try_into().map_err(|_| Status::BadRequest)?;
+                self.authenticate(*block, key)?;
+                Ok(0)
+            }
+            Command::ReadBlock => {
+                let [block] = args else {
+                    return Err(Status::BadRequest);
+                };
+                self.check_auth(*block)?;
+                let block = self.rfid.mf_read(*block).map_err(|_| self.card_error())?;
+                data[..16].copy_from_slice(&block);
+                Ok(16)
+            }
+            Command::WriteBlock => {
+                let [block, bytes @ ..] = args else {
+                    return Err(Status::BadRequest);
+                };
+                let bytes: [u8; 16] = bytes.try_into().map_err(|_| Status::BadRequest)?;
+                self.write_block(*block, bytes)?;
+                Ok(0)
+            }
+            Command::ReadSector => {
+                let [sector, key @ ..] = args else {
+                    return Err(Status::BadRequest);
+                };
+                let key: &[u8; 6] = key.try_into().map_err(|_| Status::BadRequest)?;
+                let first_block = sector.checked_mul(4).ok_or(Status::BadRequest)?;
+                self.authenticate(first_block, key)?;
+
+                for rel_block in 0..4u8 {
+                    let block = self
+                        .rfid
+                        .mf_read(first_block + rel_block)
+                        .map_err(|_| self.card_error())?;
+                    let start = rel_block as usize * 16;
+                    data[start..start + 16].copy_from_slice(&block);
+                }
+                Ok(64)
+            }
+            Command::ChangeKey => {
+                let [sector, rest @ ..] = args else {
+                    return Err(Status::BadRequest);
+                };
+                if rest.len() != 6 + 16 {
+                    return Err(Status::BadRequest);
+                }
+                let key: &[u8; 6] = rest[..6].try_into().expect("6 bytes");
+                let trailer: [u8; 16] = rest[6..].try_into().expect("16 bytes");
+                self.change_key(*sector, key, trailer)?;
+                Ok(0)
+            }
+        }
+    }
+
+    /// Halts whatever card was selected and selects the one in the field.
+    fn select(&mut self) -> Result<&[u8], Status> {
+        let _ = self.rfid.hlta();
+        let _ = self.rfid.stop_crypto1();
+        self.uid = None;
+        self.authenticated = None;
+
+        let atqa = self.rfid.wupa().map_err(|_| Status::NoCard)?;
+        let uid = self.rfid.select(&atqa).map_err(|_| Status::NoCard)?;
+        Ok(self.uid.insert(uid).as_bytes())
+    }
+
+    /// A failed command may leave the card idle or still selected, so halt
+    /// it and select it again. The host can then carry on with a new `Auth`,
+    /// as long as it is the same card.
+    fn recover(&mut self) {
+        self.authenticated = None;
+
+        let Some(uid) = self.uid.take() else {
+            let _ = self.rfid.stop_crypto1();
+            return;
+        };
+        if retry::reselect(&uid, &mut self.rfid).is_ok() {
+            self.uid = Some(uid);
+        }
+    }
+
+    fn card_error(&mut self) -> Status {
+        self.recover();
+        Status::CardError
+    }
+
+    fn authenticate(&mut self, block: u8, key: &[u8; 6]) -> Result<(), Status> {
+        if block > MAX_BLOCK {
+            return Err(Status::BadRequest);
+        }
+        let Some(uid) = &self.uid else {
+            return Err(Status::NoCard);
+        };
+
+        if self.rfid.mf_authenticate(uid, block, key).is_err() {
+            self.recover();
+            return Err(Status::AuthFailed);
+        }
+        self.authenticated = Some(block / 4);
+        Ok(())
+    }
+
+    fn check_auth(&self, block: u8) -> Result<(), Status> {
+        if block > MAX_BLOCK {
+            return Err(Status::BadRequest);
+        }
+        if self.authenticated != Some(block / 4) {
+            return Err(Status::AuthFailed);
+        }
+        Ok(())
+    }
+
+    fn write_block(&mut self, block: u8, data: [u8; 16]) -> Result<(), Status> {
+        // The manufacturer block is read-only, and a trailer with broken
+        // access bits locks the sector for good
+        if block == 0 {
+            return Err(Status::BadRequest);
+        }
+        if block % 4 == 3 {
+            AccessConditions::from_trailer(&data).map_err(|_| Status::InvalidTrailer)?;
+        }
+
+        self.check_auth(block)?;
+        self.rfid
+            .mf_write(block, data)
+            .map_err(|_| self.card_error())
+    }
+
+    /// Writes a new trailer and checks that the new key A opens the sector.
+    fn change_key(&mut self, sector: u8, key: &[u8; 6], trailer: [u8; 16]) -> Result<(), Status> {
+        let first_block = sector.checked_mul(4).ok_or(Status::BadRequest)?;
+        AccessConditions::from_trailer(&trailer).map_err(|_| Status::InvalidTrailer)?;
+
+        self.authenticate(first_block, key)?;
+        self.write_block(first_block + 3, trailer)?;
+
+        // Start over with the new key, like a fresh session would
+        let new_key: [u8; 6] = trailer[..6].try_into().expect("6 bytes");
+        self.recover();
+        self.authenticate(first_block, &new_key)
+    }
+}
@@ -0,0 +1,86 @@
+//! Turns a UID into the text the keyboard types.
+
+use core::fmt::Write;
+
+use heapless::String;
+
+/// Enough for a 10 byte UID in hex with separators
+pub type Text = String<32>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    /// Two hex digits per byte, e.g. `04:A1:B2:C3`
+    Hex {
+        separator: Option<char>,
+        uppercase: bool,
+    },
+    /// The last 3 bytes as an 8 digit number, like a Wiegand 26-bit reader
+    Wiegand26,
+    /// The last 4 bytes as a 10 digit number, like a Wiegand 34-bit reader
+    Wiegand34,
+}
+
+/// Key pressed after the UID
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Suffix {
+    None,
+    Enter,
+    Tab,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct UidFormat {
+    pub format: Format,
+    /// Use the bytes last to first. Many desk readers do this, so the
+    /// decimal numbers match the ones printed on the card.
+    pub reversed: bool,
+    pub suffix: Suffix,
+}
+
+/// Big endian number from the last `count` bytes of `bytes`.
+fn tail_number(bytes: &[u8], count: usize) -> u32 {
+    let start = bytes.len().saturating_sub(count);
+    bytes[start..]
+        .iter()
+        .fold(0, |number, &b| (number << 8) | b as u32)
+}
+
+impl UidFormat {
+    /// The text for `uid`, without the suffix.
+    pub fn format(&self, uid: &[u8]) -> Text {
+        let mut bytes = [0u8; 10];
+        let bytes = &mut bytes[..uid.len()];
+        bytes.copy_from_slice(uid);
+        if self.reversed {
+            bytes.reverse();
+        }
+
+        let mut text = Text::new();
+        match self.format {
+            Format::Hex {
+                separator,
+                uppercase,
+            } => {
+                for (i, b) in bytes.iter().enumerate() {
+                    if let Some(separator) = separator
+                        && i > 0
+                    {
+                        text.push(separator).expect("text too long");
+                    }
+                    if uppercase {
+                        write!(text, "{:02X}", b).expect("text too long");
+                    } else {
+                        write!(text, "{:02x}", b).expect("text too long");
+                    }
+                }
+            }
+            Format::Wiegand26 => {
+                write!(text, "{:08}", tail_number(bytes, 3)).expect("text too long");
+            }
+            Format::Wiegand34 => {
+                write!(text, "{:010}", tail_number(bytes, 4)).expect("text too long");
+            }
+        }
+        text
+    }
+}
@@ -0,0 +1,83 @@
+//! Types text by sending HID keyboard reports.
+//!
+//! A keyboard reports which keys are down, not which characters they make,
+//! so the host's keyboard layout decides what shows up. The key codes below
+//! are for a US layout; digits, letters and Enter/Tab come out the same on
+//! most layouts, the separators may not.
+
+use embassy_time::Timer;
+use embassy_usb::class::hid::HidWriter;
+use embassy_usb::driver::{Driver, EndpointError};
+use usbd_hid::descriptor::KeyboardReport;
+
+use crate::format::Suffix;
+
+const LEFT_SHIFT: u8 = 0x02;
+
+const KEY_A: u8 = 0x04;
+const KEY_1: u8 = 0x1E;
+const KEY_0: u8 = 0x27;
+const KEY_ENTER: u8 = 0x28;
+const KEY_TAB: u8 = 0x2B;
+const KEY_SPACE: u8 = 0x2C;
+const KEY_MINUS: u8 = 0x2D;
+const KEY_SEMICOLON: u8 = 0x33;
+const KEY_COMMA: u8 = 0x36;
+const KEY_DOT: u8 = 0x37;
+
+/// Some hosts drop keys that come in faster than this
+const KEY_DELAY_MS: u64 = 5;
+
+/// Modifier and key code that types `c`, if the keyboard can type it.
+fn key_for(c: char) -> Option<(u8, u8)> {
+    let key = match c {
+        'a'..='z' => (0, KEY_A + (c as u8 - b'a')),
+        'A'..='Z' => (LEFT_SHIFT, KEY_A + (c as u8 - b'A')),
+        '0' => (0, KEY_0),
+        '1'..='9' => (0, KEY_1 + (c as u8 - b'1')),
+        ' ' => (0, KEY_SPACE),
+        '-' => (0, KEY_MINUS),
+        ':' => (LEFT_SHIFT, KEY_SEMICOLON),
+        ',' => (0, KEY_COMMA),
+        '.' => (0, KEY_DOT),
+        _ => return None,
+    };
+    Some(key)
+}
+
+/// Presses and releases one key.
+async fn tap<'d, D: Driver<'d>>(
+    writer: &mut HidWriter<'d, D, 8>,
+    modifier: u8,
+    keycode: u8,
+) -> Result<(), EndpointError> {
+    let mut report = KeyboardReport::default();
+    report.modifier = modifier;
+    report.keycodes[0] = keycode;
+    writer.write_serialize(&report).await?;
+    Timer::after_millis(KEY_DELAY_MS).await;
+
+    // Releasing every key in between lets the same character repeat
+    writer.write_serialize(&KeyboardReport::default()).await?;
+    Timer::after_millis(KEY_DELAY_MS).await;
+    Ok(())
+}
+
+/// Types `text` followed by the suffix key. Characters without a key are skipped.
+pub async fn type_text<'d, D: Driver<'d>>(
+    writer: &mut HidWriter<'d, D, 8>,
+    text: &str,
+    suffix: Suffix,
+) -> Result<(), EndpointError> {
+    for c in text.chars() {
+        if let Some((modifier, keycode)) = key_for(c) {
+            tap(writer, modifier, keycode).await?;
+        }
+    }
+
+    match suffix {
+        Suffix::None => Ok(()),
+        Suffix::Enter => tap(writer, 0, KEY_ENTER).await,
+        Suffix::Tab => tap(writer, 0, KEY_TAB).await,
+    }
+}
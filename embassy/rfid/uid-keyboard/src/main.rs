@@ -0,0 +1,133 @@
+#![no_std]
+#![no_main]
+
+pub mod format;
+pub mod keyboard;
+
+use embassy_executor::Spawner;
+use embassy_futures::join::join;
+use embassy_time::Timer;
+
+use panic_halt as _;
+
+// For USB
+use embassy_rp::{peripherals::USB, usb};
+use embassy_usb::Builder;
+use embassy_usb::class::hid::{self, HidWriter, State};
+use usbd_hid::descriptor::{KeyboardReport, SerializedDescriptor};
+
+// For SPI
+use embassy_rp::spi;
+use embassy_rp::spi::Spi;
+use embassy_time::Delay;
+use embedded_hal_bus::spi::ExclusiveDevice;
+
+// For CS Pin
+use embassy_rp::gpio::{Level, Output};
+
+// Driver for the MFRC522
+use mfrc522::{Mfrc522, comm::blocking::spi::SpiInterface};
+
+use crate::format::{Format, Suffix, UidFormat};
+
+embassy_rp::bind_interrupts!(struct Irqs {
+    USBCTRL_IRQ => usb::InterruptHandler<USB>;
+});
+
+/// How the UID gets typed. Some other options:
+/// - `Format::Hex { separator: None, uppercase: false }` types `04a1b2c3`
+/// - `Format::Wiegand34` with `reversed: true` types the 10 digit number
+///   most USB desk readers produce
+const UID_FORMAT: UidFormat = UidFormat {
+    format: Format::Hex {
+        separator: Some(':'),
+        uppercase: true,
+    },
+    reversed: false,
+    suffix: Suffix::Enter,
+};
+
+#[embassy_executor::main]
+async fn main(_spawner: Spawner) {
+    let p = embassy_rp::init(Default::default());
+
+    // USB keyboard
+    let driver = usb::Driver::new(p.USB, Irqs);
+
+    let mut config = embassy_usb::Config::new(0xc0de, 0xcafe);
+    config.manufacturer = Some("implRust");
+    config.product = Some("RFID keyboard");
+    config.serial_number = Some("12345678");
+    // Plain HID device, not a composite one
+    config.composite_with_iads = false;
+    config.device_class = 0;
+    config.device_sub_class = 0;
+    config.device_protocol = 0;
+
+    let mut config_descriptor = [0; 256];
+    let mut bos_descriptor = [0; 256];
+    let mut msos_descriptor = [0; 256];
+    let mut control_buf = [0; 64];
+    let mut state = State::new();
+
+    let mut builder = Builder::new(
+        driver,
+        config,
+        &mut config_descriptor,
+        &mut bos_descriptor,
+        &mut msos_descriptor,
+        &mut control_buf,
+    );
+
+    let hid_config = hid::Config {
+        report_descriptor: KeyboardReport::desc(),
+        request_handler: None,
+        poll_ms: 10,
+        max_packet_size: 8,
+    };
+    let mut writer = HidWriter::<_, 8>::new(&mut builder, &mut state, hid_config);
+
+    let mut usb = builder.build();
+
+    // RFID reader
+    let miso = p.PIN_0;
+    let cs_pin = Output::new(p.PIN_1, Level::High);
+    let clk = p.PIN_2;
+    let mosi = p.PIN_3;
+
+    let mut config = spi::Config::default();
+    config.frequency = 1_000_000;
+
+    let spi_bus = Spi::new_blocking(p.SPI0, clk, mosi, miso, config);
+    let spi = ExclusiveDevice::new(spi_bus, cs_pin, Delay).expect("Failed to get exclusive device");
+
+    let itf = SpiInterface::new(spi);
+
+    let scan_loop = async {
+        // Nothing to report the error to, so the keyboard just stays silent
+        let Ok(mut rfid) = Mfrc522::new(itf).init() else {
+            loop {
+                Timer::after_secs(1).await;
+            }
+        };
+
+        // Wait for the host to configure us as a keyboard
+        writer.ready().await;
+
+        loop {
+            if let Ok(atqa) = rfid.reqa()
+                && let Ok(uid) = rfid.select(&atqa)
+            {
+                let text = UID_FORMAT.format(uid.as_bytes());
+                // The host went away, the next card gets another try
+                let _ = keyboard::type_text(&mut writer, &text, UID_FORMAT.suffix).await;
+
+                let _ = rfid.hlta();
+                Timer::after_millis(500).await;
+            }
+            Timer::after_millis(100).await;
+        }
+    };
+
+    join(usb.run(), scan_loop).await;
+}
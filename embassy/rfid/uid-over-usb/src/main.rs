@@ -19,12 +19,14 @@ use embedded_hal_bus::spi::ExclusiveDevice;
 use embassy_rp::gpio::{Level, Output};
 
 // Driver for the MFRC522
-use mfrc522::{Mfrc522, comm::blocking::spi::SpiInterface};
+use mfrc522::{Initialized, Mfrc522, Type, Uid, comm::blocking::spi::SpiInterface};
 
 // to prepare buffer with data before writing into USB serial
 use core::fmt::Write;
 use heapless::String;
 
+use rfid_common::uid_info;
+
 embassy_rp::bind_interrupts!(struct Irqs {
     USBCTRL_IRQ => usb::InterruptHandler<USB>;
 });
@@ -36,12 +38,78 @@ async fn logger_task(usb: embassy_rp::Peri<'static, embassy_rp::peripherals::USB
     embassy_usb_logger::run!(1024, log::LevelFilter::Info, driver);
 }
 
-fn print_hex_to_serial(data: &[u8]) {
+const DEFAULT_KEY: [u8; 6] = [0xFF; 6];
+
+fn hex(data: &[u8]) -> String<64> {
     let mut buff: String<64> = String::new();
     for &d in data.iter() {
         write!(buff, "{:02x} ", d).expect("failed to write byte into buffer");
     }
-    log::info!("UID: {}", buff);
+    buff
+}
+
+/// Reads block 0 of a Classic, or pages 0 to 3 of an Ultralight.
+fn read_manufacturer_block<E, COMM>(
+    rfid: &mut Mfrc522<COMM, Initialized>,
+    uid: &Uid,
+) -> Result<[u8; 16], &'static str>
+where
+    COMM: mfrc522::comm::Interface<Error = E>,
+{
+    let card_type = uid.get_type();
+    if uid_info::is_classic(&card_type) {
+        rfid.mf_authenticate(uid, 0, &DEFAULT_KEY)
+            .map_err(|_| "default key refused for sector 0")?;
+    } else if !matches!(card_type, Type::MifareUL) {
+        return Err("not a MIFARE Classic or Ultralight");
+    }
+    rfid.mf_read(0).map_err(|_| "read failed")
+}
+
+fn print_card_summary<E, COMM>(rfid: &mut Mfrc522<COMM, Initialized>, uid: &Uid)
+where
+    COMM: mfrc522::comm::Interface<Error = E>,
+{
+    let bytes = uid.as_bytes();
+    let card_type = uid.get_type();
+
+    log::info!("UID: {}", hex(bytes));
+    log::info!(
+        "  {} bytes, {} cascade level(s)",
+        bytes.len(),
+        uid_info::cascade_levels(uid)
+    );
+    log::info!("  Type: {}", uid_info::type_name(&card_type));
+    if bytes.len() == 4 {
+        log::info!("  UID kind: {}", uid_info::single_size_kind(bytes[0]));
+    } else {
+        log::info!(
+            "  Manufacturer: {} ({:02x})",
+            uid_info::manufacturer(bytes[0]).unwrap_or("unknown"),
+            bytes[0]
+        );
+    }
+
+    match read_manufacturer_block(rfid, uid) {
+        Ok(block0) => {
+            log::info!("  Block 0: {}", hex(&block0));
+            let findings = if uid_info::is_classic(&card_type) {
+                uid_info::check_classic_block0(uid, &block0)
+            } else {
+                uid_info::check_ultralight_pages(uid, &block0)
+            };
+            if findings.is_empty() {
+                log::info!("  Block 0 is consistent, looks genuine");
+            }
+            for finding in findings {
+                log::warn!("  Possible clone: {}", finding);
+            }
+        }
+        Err(e) => log::info!("  Block 0 not checked: {}", e),
+    }
+
+    let _ = rfid.hlta();
+    let _ = rfid.stop_crypto1();
 }
 
 #[embassy_executor::main]
@@ -82,7 +150,7 @@ async fn main(spawner: Spawner) {
     loop {
         if let Ok(atqa) = rfid.reqa() {
             if let Ok(uid) = rfid.select(&atqa) {
-                print_hex_to_serial(uid.as_bytes());
+                print_card_summary(&mut rfid, &uid);
                 Timer::after_millis(500).await;
             }
         }
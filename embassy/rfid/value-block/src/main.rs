@@ -0,0 +1,102 @@
+#![no_std]
+#![no_main]
+
+use embassy_executor::Spawner;
+use embassy_time::Timer;
+
+// defmt Logging
+use defmt::{error, info};
+use defmt_rtt as _;
+
+use panic_probe as _;
+
+// For SPI
+use embassy_rp::spi;
+use embassy_rp::spi::Spi;
+use embassy_time::Delay;
+use embedded_hal_bus::spi::ExclusiveDevice;
+
+// For CS Pin
+use embassy_rp::gpio::{Level, Output};
+
+// Driver for the MFRC522
+use mfrc522::{Mfrc522, comm::blocking::spi::SpiInterface};
+
+use rfid_common::value::{self, ValueBlock};
+
+/// Formats a counter, tops it up, charges it and keeps a backup copy.
+fn run_counter<E, COMM>(
+    uid: &mfrc522::Uid,
+    sector: u8,
+    rfid: &mut Mfrc522<COMM, mfrc522::Initialized>,
+) -> Result<(), &'static str>
+where
+    COMM: mfrc522::comm::Interface<Error = E>,
+{
+    const AUTH_KEY: [u8; 6] = [0xFF; 6];
+
+    let block_offset = sector * 4;
+    let counter_block = block_offset;
+    let backup_block = block_offset + 1;
+
+    rfid.mf_authenticate(uid, block_offset, &AUTH_KEY)
+        .map_err(|_| "Auth failed")?;
+
+    value::format(counter_block, ValueBlock::new(100, counter_block), rfid)?;
+    info!("Formatted: {:?}", value::read(counter_block, rfid)?);
+
+    value::increment(counter_block, 25, rfid)?;
+    value::transfer(counter_block, rfid)?;
+    info!("After +25: {:?}", value::read(counter_block, rfid)?);
+
+    value::decrement(counter_block, 40, rfid)?;
+    value::transfer(counter_block, rfid)?;
+    info!("After -40: {:?}", value::read(counter_block, rfid)?);
+
+    // The backup block has to be a value block too, before it can take a transfer
+    value::format(backup_block, ValueBlock::new(0, counter_block), rfid)?;
+    value::restore(counter_block, rfid)?;
+    value::transfer(backup_block, rfid)?;
+    info!("Backup: {:?}", value::read(backup_block, rfid)?);
+
+    Ok(())
+}
+
+#[embassy_executor::main]
+async fn main(_spawner: Spawner) {
+    let p = embassy_rp::init(Default::default());
+
+    info!("Initializing the program");
+
+    let miso = p.PIN_0;
+    let cs_pin = Output::new(p.PIN_1, Level::High);
+    let clk = p.PIN_2;
+    let mosi = p.PIN_3;
+
+    let mut config = spi::Config::default();
+    config.frequency = 1_000_000;
+
+    let spi_bus = Spi::new_blocking(p.SPI0, clk, mosi, miso, config);
+    let spi = ExclusiveDevice::new(spi_bus, cs_pin, Delay).expect("Failed to get exclusive device");
+
+    let itf = SpiInterface::new(spi);
+    let mut rfid = Mfrc522::new(itf)
+        .init()
+        .expect("failed to initialize the RFID reader");
+
+    let target_sector = 1;
+
+    loop {
+        if let Ok(atqa) = rfid.reqa()
+            && let Ok(uid) = rfid.select(&atqa)
+        {
+            if let Err(e) = run_counter(&uid, target_sector, &mut rfid) {
+                error!("Error updating value block: {:?}", e);
+            }
+            let _ = rfid.hlta();
+            let _ = rfid.stop_crypto1();
+            Timer::after_millis(500).await;
+        }
+        Timer::after_millis(100).await;
+    }
+}
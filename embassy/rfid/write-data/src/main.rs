@@ -26,6 +26,12 @@ use mfrc522::{Mfrc522, comm::blocking::spi::SpiInterface};
 use core::fmt::Write;
 use heapless::String;
 
+use rfid_common::error::RfidError;
+use rfid_common::keys::{Diversified, KeySchedule, MASTER_SECRET};
+use rfid_common::reader;
+use rfid_common::retry::RetryPolicy;
+use rfid_common::storage;
+
 fn print_hex(data: &[u8]) {
     let mut buff: String<64> = String::new();
     for &d in data.iter() {
@@ -37,47 +43,22 @@ fn print_hex(data: &[u8]) {
 fn read_sector<E, COMM>(
     uid: &mfrc522::Uid,
     sector: u8,
+    keys: &impl KeySchedule,
+    policy: &RetryPolicy,
     rfid: &mut Mfrc522<COMM, mfrc522::Initialized>,
-) -> Result<(), &'static str>
+) -> Result<(), RfidError>
 where
     COMM: mfrc522::comm::Interface<Error = E>,
 {
-    const AUTH_KEY: [u8; 6] = [0xFF; 6];
-
-    let block_offset = sector * 4;
-    rfid.mf_authenticate(uid, block_offset, &AUTH_KEY)
-        .map_err(|_| "Auth failed")?;
+    let auth_key = keys.keys(uid.as_bytes(), sector).key_a;
+    let blocks = reader::read_sector(uid, sector, &auth_key, policy, rfid)?;
 
-    for abs_block in block_offset..block_offset + 4 {
-        let data = rfid.mf_read(abs_block).map_err(|_| "Read failed")?;
-        print_hex(&data);
+    for data in blocks.iter() {
+        print_hex(data);
     }
     Ok(())
 }
 
-fn write_block<E, COMM>(
-    uid: &mfrc522::Uid,
-    sector: u8,
-    rel_block: u8,
-    data: [u8; 16],
-    rfid: &mut Mfrc522<COMM, mfrc522::Initialized>,
-) -> Result<(), &'static str>
-where
-    COMM: mfrc522::comm::Interface<Error = E>,
-{
-    const AUTH_KEY: [u8; 6] = [0xFF; 6];
-
-    let block_offset = sector * 4;
-    let abs_block = block_offset + rel_block;
-
-    rfid.mf_authenticate(uid, block_offset, &AUTH_KEY)
-        .map_err(|_| "Auth failed")?;
-
-    rfid.mf_write(abs_block, data).map_err(|_| "Write failed")?;
-
-    Ok(())
-}
-
 #[embassy_executor::main]
 async fn main(_spawner: Spawner) {
     let p = embassy_rp::init(Default::default());
@@ -101,28 +82,49 @@ async fn main(_spawner: Spawner) {
         .expect("failed to initialize the RFID reader");
 
     let target_sector = 4;
-    let rel_block = 2;
-    const DATA: [u8; 16] = [
-        b'i', b'm', b'p', b'l', b'R', b'u', b's', b't', // "implRust"
-        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, // Remaining bytes as 0x00
-    ];
+    // Long enough to run past the sector trailer into the next sector
+    const RECORD: &str = r#"{"name":"Ferris the Crab","role":"mascot","lang":"Rust"}"#;
+    // The cards were moved to diversified keys by `change-key`, with the same secret
+    let keys = Diversified::new(MASTER_SECRET);
+    let policy = RetryPolicy::default();
 
     loop {
         if let Ok(atqa) = rfid.reqa() {
             if let Ok(uid) = rfid.select(&atqa) {
                 info!("\r\n----Before Write----\r\n");
-                if let Err(e) = read_sector(&uid, target_sector, &mut rfid) {
+                if let Err(e) = read_sector(&uid, target_sector, &keys, &policy, &mut rfid) {
                     error!("Error reading sector: {:?}", e);
                 }
 
-                if let Err(e) = write_block(&uid, target_sector, rel_block, DATA, &mut rfid) {
+                let first_block = target_sector * 4;
+                if let Err(e) = storage::write_str(
+                    &uid,
+                    first_block,
+                    storage::LAST_BLOCK_1K,
+                    RECORD,
+                    &keys,
+                    &policy,
+                    &mut rfid,
+                ) {
                     error!("Error writing data: {:?}", e);
                 }
 
                 info!("\r\n----After Write----\r\n");
-                if let Err(e) = read_sector(&uid, target_sector, &mut rfid) {
+                if let Err(e) = read_sector(&uid, target_sector, &keys, &policy, &mut rfid) {
                     error!("Error reading sector: {:?}", e);
                 }
+
+                match storage::read_str::<128, _>(
+                    &uid,
+                    first_block,
+                    storage::LAST_BLOCK_1K,
+                    &keys,
+                    &policy,
+                    &mut rfid,
+                ) {
+                    Ok(text) => info!("Stored record: {}", text.as_str()),
+                    Err(e) => error!("Error reading data: {:?}", e),
+                }
                 let _ = rfid.hlta();
                 let _ = rfid.stop_crypto1();
                 Timer::after_millis(500).await;